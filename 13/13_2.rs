@@ -2,237 +2,78 @@ extern crate pancurses;
 
 use std::env;
 use std::fs;
-use std::collections::{HashMap, VecDeque};
 use std::thread;
 use std::time;
 use pancurses::{Window, Input};
+use intcode::{Memory, Program, load_program, init_program, run_program, StepResult};
+use intcode::session::{Recorder, load_session, replay, run_program_recorded, save_session};
+use intcode::snapshot::{load_snapshot, save_snapshot};
 
 const TILE_WALL: u8 = 1;
 const TILE_BLOCK: u8 = 2;
 const TILE_PADDLE: u8 = 3;
 const TILE_BALL: u8 = 4;
 
-const MODE_POS: i64 = 0;
-const MODE_IMM: i64 = 1;
-const MODE_REL: i64 = 2;
-
-const OP_HALT: i64 = 99;
-const OP_ADD: i64 = 1;
-const OP_MULTIPLY: i64 = 2;
-const OP_INPUT: i64 = 3;
-const OP_OUTPUT: i64 = 4;
-const OP_JUMP_IF_TRUE: i64 = 5;
-const OP_JUMP_IF_FALSE: i64 = 6;
-const OP_LESS_THAN: i64 = 7;
-const OP_EQUALS: i64 = 8;
-const OP_REL_BASE_OFFSET: i64 = 9;
-
-const EXIT_ERROR: i64 = -1;
-const EXIT_HALT: i64 = 0;
-const EXIT_NEED_INPUT: i64 = 1;
-const EXIT_OUTPUT: i64 = 2;
-
-struct Program {
-    code: Vec<i64>,
-    ip: i64,
-    memory: HashMap::<i64, i64>,
-    rel_base: i64,
-    data: VecDeque<i64>
-}
-
-impl Program {
-    pub fn new(code: Vec<i64>) -> Program {
-        Program {
-            code: code,
-            ip: 0,
-            memory: HashMap::new(),
-            rel_base: 0,
-            data: VecDeque::new()
-        }
-    }
-}
-
-impl Clone for Program {
-    fn clone(&self) -> Program {
-        Program {
-            code: self.code.clone(),
-            ip: self.ip.clone(),
-            memory: self.memory.clone(),
-            rel_base: self.rel_base,
-            data: self.data.clone()
-        }
-    }
-}
-
-fn load_program(path: &str) -> Program {
-    let code = fs::read_to_string(path)
-        .unwrap()
-        .trim()
-        .split(",")
-        .map(|x| x.parse::<i64>().unwrap())
-        .collect::<Vec<i64>>();
-    return Program::new(code);
-}
-
-fn init_program(program: &mut Program) {
-    program.ip = 0;
-    program.memory.clear();
-    program.rel_base = 0;
-    program.data.clear();
-
-    for i in 0..program.code.len() {
-        program.memory.insert(i as i64, program.code[i]);
-    }
-}
-
-fn run_program(program: &mut Program) -> i64 {
-    let code = &mut program.code;
-    let memory = &mut program.memory;
-    let mut ip = program.ip;
-    let mut rel_base = program.rel_base;
-    let data = &mut program.data;
-
-    while ip < code.len() as i64 {
-        let instr = memory[&ip];
-        let opcode = read_opcode(instr);
-        ip += 1;
-
-        match opcode {
-            OP_HALT => {
-                return EXIT_HALT;
-            },
-            OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => {
-                let param1 = read_param_value(ip, &memory, rel_base, 0);
-                let param2 = read_param_value(ip, &memory, rel_base, 1);
-                let result_address = read_param_value_out(ip, &memory, rel_base, 2);
-                ip += 3;
-                match opcode {
-                    OP_ADD => {
-                        memory.insert(result_address, param1 + param2);
-                    },
-                    OP_MULTIPLY => {
-                        memory.insert(result_address, param1 * param2);
-                    },
-                    OP_LESS_THAN => {
-                        memory.insert(result_address, if param1 < param2 {1} else {0});
-                    },
-                    OP_EQUALS => {
-                        memory.insert(result_address, if param1 == param2 {1} else {0});
-                    }
-                    _ => panic!()
-                }
-            },
-            OP_INPUT => {
-                let param = read_param_value_out(ip, &memory, rel_base, 0);
-                ip += 1;
-                match data.pop_front() {
-                    Some(value) => {
-                        memory.insert(param, value);
-                    },
-                    None => return EXIT_NEED_INPUT
-                }
-            },
-            OP_OUTPUT => {
-                let param = read_param_value(ip, &memory, rel_base, 0);
-                ip += 1;
-                data.push_back(param);
-                program.ip = ip;
-                return EXIT_OUTPUT;
-            },
-            OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => {
-                let param1 = read_param_value(ip, &memory, rel_base, 0);
-                let param2 = read_param_value(ip, &memory, rel_base, 1);
-                ip += 2;
-                if (opcode == OP_JUMP_IF_TRUE && param1 != 0)
-                        || (opcode == OP_JUMP_IF_FALSE && param1 == 0) {
-                    ip = param2;
-                }
-            },
-            OP_REL_BASE_OFFSET => {
-                rel_base += read_param_value(ip, &memory, rel_base, 0);
-                ip += 1;
-            }
-            _ => {
-                println!("Invalid opcoe at address {}: {}", ip - 1, instr);
-                return EXIT_ERROR;
-            }
-        }
-
-        program.ip = ip;
-        program.rel_base = rel_base;
-    }
-
-    fn read_opcode(instr: i64) -> i64 {
-        instr % 100
-    }
-
-    fn read_param_mode(instr: i64, index: u32) -> i64 {
-        instr % 10_i64.pow(index + 3) / 10_i64.pow(index + 2)
-    }
-
-    fn read_param_value(start: i64, memory: &HashMap::<i64, i64>, rel_base: i64, index: u32) -> i64 {
-        let mode = read_param_mode(memory[&(start - 1)], index);
-        let param = *memory.get(&(start + index as i64)).unwrap_or(&0);
-        match mode {
-            MODE_POS => *memory.get(&param).unwrap_or(&0),
-            MODE_IMM => param,
-            MODE_REL => {
-                if rel_base + param < 0 {
-                    panic!("Attempt to read memory at invalid address {}", rel_base + param);
-                }
-                *memory.get(&(param + rel_base)).unwrap_or(&0)
-            }
-            _ => panic!()
-        }
-    }
-
-    fn read_param_value_out(start: i64, memory: &HashMap::<i64, i64>, rel_base: i64, index: u32) -> i64 {
-        let mode = read_param_mode(memory[&(start - 1)], index);
-        let param = *memory.get(&(start + index as i64)).unwrap_or(&0);
-        match mode {
-            MODE_POS => param,
-            MODE_REL => param + rel_base,
-            _ => panic!()
-        }
-    }
-
-    return EXIT_ERROR;
-}
+const SAVE_PATH: &str = "save.txt";
+const SAVE_SCREEN_PATH: &str = "save_screen.txt";
+const SESSION_PATH: &str = "session.txt";
+const SESSION_START_PATH: &str = "session_start.txt";
 
 struct GameState {
     grid: Vec<Vec<u8>>,
-    score: i64,
-    steps: Vec<i32>,
-    steps_to_replay: VecDeque<i32>
+    score: i64
 }
 
 impl GameState {
     pub fn new() -> GameState {
         GameState {
             grid: vec![vec![0; 80]; 60],
-            score: 0,
-            steps: Vec::new(),
-            steps_to_replay: VecDeque::new()
+            score: 0
         }
     }
+
+    // The program only outputs the tiles that change, so the screen is saved
+    // along with the program's snapshot.
+    fn save(&self, path: &str) {
+        let mut text = format!("{}\n", self.score);
+        for line in &self.grid {
+            text.push_str(&line.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(","));
+            text.push('\n');
+        }
+        fs::write(path, text).unwrap();
+    }
+
+    fn load(&mut self, path: &str) {
+        let text = fs::read_to_string(path).unwrap();
+        let mut lines = text.lines();
+        self.score = lines.next().unwrap().parse::<i64>().unwrap();
+        self.grid = lines
+            .map(|line| line.split(',').map(|x| x.parse::<u8>().unwrap()).collect())
+            .collect();
+    }
 }
 
+// Every game is recorded to SESSION_PATH so that it can be replayed later,
+// along with a snapshot of the state the recording starts from. Loading a
+// saved game starts a new recording from the loaded state.
 fn run_game_manual(program: &mut Program, game: &mut GameState, window: &Window) {
     init_program(program);
-    program.memory.insert(0, 2); // insert 2 quarters
+    program.memory.write(0, 2); // insert 2 quarters
+    save_snapshot(program, SESSION_START_PATH).unwrap();
 
     window.refresh();
     window.keypad(true);
 
+    let mut recorder = Recorder::new();
+
     'game: loop {
-        match run_program(program) {
-            EXIT_NEED_INPUT => {
+        match run_program_recorded(program, &mut recorder) {
+            Ok(StepResult::NeedInput) => {
                 window.clear();
                 window.printw(format!("Score: {}\n\n", game.score));
 
-                for i in 0..game.grid.len() {
-                    for j in 0..game.grid[i].len() {
-                        let tile = game.grid[i][j] as u8;
+                for line in &game.grid {
+                    for &tile in line {
                         let symbol = match tile {
                             TILE_WALL => "#",
                             TILE_BLOCK => "*",
@@ -245,14 +86,6 @@ fn run_game_manual(program: &mut Program, game: &mut GameState, window: &Window)
                     window.printw("\n");
                 }
 
-                match game.steps_to_replay.pop_front() {
-                    Some(v) => {
-                        program.data.push_back(v as i64);
-                        continue;
-                    }
-                    None => {}
-                }
-
                 loop {
                     let key = window.getch();
                     match key {
@@ -269,33 +102,30 @@ fn run_game_manual(program: &mut Program, game: &mut GameState, window: &Window)
                             break;
                         },
                         Some(Input::Character('l')) => {
-                            game.steps = fs::read_to_string("save.txt").unwrap()
-                                .split(",")
-                                .map(|x| x.parse::<i32>().unwrap())
-                                .collect();
-                            game.steps_to_replay = game.steps.clone().into_iter().collect();
+                            if let Err(error) = load_snapshot(program, SAVE_PATH) {
+                                window.printw(format!("Could not load game: {}\n", error));
+                                continue;
+                            }
+                            game.load(SAVE_SCREEN_PATH);
+                            save_snapshot(program, SESSION_START_PATH).unwrap();
+                            recorder = Recorder::new();
                             continue 'game;
                         },
                         Some(Input::Character('s')) => {
-                            fs::write("save.txt",
-                                game.steps.iter()
-                                    .map(|x| x.to_string())
-                                    .collect::<Vec<String>>().join(",")).unwrap();
-                            break;
+                            save_snapshot(program, SAVE_PATH).unwrap();
+                            game.save(SAVE_SCREEN_PATH);
+                            window.printw("Game saved\n");
                         },
                         Some(Input::Character('q')) => {
+                            save_session(&recorder.session, SESSION_PATH).unwrap();
                             println!("Goodbye!");
                             return;
                         },
                         _ => {}
                     }
                 }
-
-                if !program.data.is_empty() {
-                    game.steps.push(*program.data.back().unwrap() as i32);
-                }
             },
-            EXIT_OUTPUT => {
+            Ok(StepResult::Output) => {
                 if program.data.len() == 3 {
                     let x = program.data.pop_front().unwrap();
                     let y = program.data.pop_front().unwrap();
@@ -312,20 +142,35 @@ fn run_game_manual(program: &mut Program, game: &mut GameState, window: &Window)
                     }
                 }
             },
-            EXIT_HALT => {
+            Ok(StepResult::Halt) => {
                 break;
             }
-            _ => {
-                println!("Oops, something went wrong");
+            Err(error) => {
+                println!("Oops, something went wrong: {}", error);
                 break;
             }
         }
     }
+    save_session(&recorder.session, SESSION_PATH).unwrap();
+}
+
+// Check that the recorded game still plays out the same way.
+fn replay_game(program: &mut Program) {
+    if let Err(error) = load_snapshot(program, SESSION_START_PATH) {
+        println!("Could not load {}: {}", SESSION_START_PATH, error);
+        return;
+    }
+
+    let session = load_session(SESSION_PATH).unwrap();
+    match replay(program, &session) {
+        Ok(instructions) => println!("Replayed {} moves ({} instructions)", session.inputs().len(), instructions),
+        Err(error) => println!("{}", error)
+    }
 }
 
 fn run_game_auto(program: &mut Program, window: &Window) {
     init_program(program);
-    program.memory.insert(0, 2); // insert 2 quarters
+    program.memory.write(0, 2); // insert 2 quarters
 
     let mut grid: Vec<Vec<u8>> = vec![vec![0; 50]; 25];
 
@@ -335,12 +180,11 @@ fn run_game_auto(program: &mut Program, window: &Window) {
 
     loop {
         match run_program(program) {
-            EXIT_NEED_INPUT => {
+            Ok(StepResult::NeedInput) => {
                 thread::sleep(time::Duration::from_millis(50));
 
-                for i in 0..grid.len() {
-                    for j in 0..grid[i].len() {
-                        let tile = grid[i][j] as u8;
+                for line in &grid {
+                    for (j, &tile) in line.iter().enumerate() {
                         match tile {
                             TILE_BALL => {
                                 ball_x = j;
@@ -361,7 +205,7 @@ fn run_game_auto(program: &mut Program, window: &Window) {
                     program.data.push_back(0);
                 }
             },
-            EXIT_OUTPUT => {
+            Ok(StepResult::Output) => {
                 if program.data.len() == 3 {
                     let x = program.data.pop_front().unwrap();
                     let y = program.data.pop_front().unwrap();
@@ -380,9 +224,8 @@ fn run_game_auto(program: &mut Program, window: &Window) {
                     window.printw(format!("Score: {}\n", score));
                     window.printw("\n");
 
-                    for i in 0..grid.len() {
-                        for j in 0..grid[i].len() {
-                            let tile = grid[i][j] as u8;
+                    for line in &grid {
+                        for &tile in line {
                             let symbol = match tile {
                                 TILE_WALL => "#",
                                 TILE_BLOCK => "*",
@@ -398,15 +241,15 @@ fn run_game_auto(program: &mut Program, window: &Window) {
                     window.refresh();
                 }
             },
-            EXIT_HALT => {
+            Ok(StepResult::Halt) => {
                 window.clear();
                 window.printw(format!("You score: {}\n", score));
                 window.printw("Press any key to exit...\n");
                 window.getch();
                 break;
             }
-            _ => {
-                println!("Oops, something went wrong");
+            Err(error) => {
+                println!("Oops, something went wrong: {}", error);
                 break;
             }
         }
@@ -414,12 +257,17 @@ fn run_game_auto(program: &mut Program, window: &Window) {
 }
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "replay" {
+        replay_game(&mut load_program("input.txt"));
+        return;
+    }
+
     let window = pancurses::initscr();
     pancurses::noecho();
     pancurses::cbreak();
 
     let mut program = load_program("input.txt");
-    let args: Vec<String> = env::args().collect();
 
     if args.len() > 1 && args[1] == "play" {
         let mut game = GameState::new();
@@ -0,0 +1,186 @@
+// Search for the phase settings that produce the highest signal from a chain
+// of amplifiers.
+//
+// Each amplifier runs a copy of the same program and is given its phase
+// setting followed by the output signal of the previous amplifier; the first
+// one gets a signal of 0. In a feedback loop the last amplifier is connected
+// back to the first one and the chain runs until every amplifier halts. The
+// signal of a chain is the last value output by its last amplifier.
+//
+// PhaseSearch tries every arrangement of distinct phase settings in worker
+// threads, each with its own copies of the program. Arrangements are numbered
+// and each worker generates the ones it runs from their numbers:
+//
+//     let results = PhaseSearch::new(program, (5..=9).collect())
+//         .with_feedback()
+//         .with_top(3)
+//         .run()?;
+
+use std::thread;
+use super::*;
+use super::network::Network;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PhaseResult {
+    pub signal: i64,
+    pub phases: Vec<i64>
+}
+
+// Number of ordered selections of length distinct elements out of count,
+// None if it doesn't fit in a u64.
+pub fn permutation_count(count: usize, length: usize) -> Option<u64> {
+    if length > count {
+        return Some(0);
+    }
+    (count - length + 1..=count).try_fold(1u64, |product, n| product.checked_mul(n as u64))
+}
+
+// The selection with the specified index in the order of permutations().
+pub fn nth_permutation(values: &[i64], length: usize, mut index: u64) -> Vec<i64> {
+    let mut remaining = values.to_vec();
+    let mut result = Vec::with_capacity(length);
+    for position in 0..length {
+        // Number of selections starting with the elements chosen so far and
+        // each of the remaining ones.
+        let block = permutation_count(remaining.len() - 1, length - position - 1).unwrap_or(u64::MAX);
+        result.push(remaining.remove((index / block) as usize));
+        index %= block;
+    }
+    result
+}
+
+// All ordered selections of length distinct elements of values, in
+// lexicographic order of their indices. They are generated one at a time.
+pub fn permutations(values: &[i64], length: usize) -> impl Iterator<Item = Vec<i64>> + '_ {
+    let count = permutation_count(values.len(), length).unwrap_or(u64::MAX);
+    (0..count).map(move |index| nth_permutation(values, length, index))
+}
+
+// Run a chain of amplifiers with the specified phase settings. Returns None
+// if the last amplifier doesn't output anything.
+pub fn run_chain(program: &Program, phases: &[i64], feedback: bool) -> Result<Option<i64>, VmError> {
+    let mut network = Network::new();
+    for (index, &phase) in phases.iter().enumerate() {
+        let input = if index == 0 { vec![phase, 0] } else { vec![phase] };
+        network.add_program(&index.to_string(), program.clone(), &input);
+    }
+    for index in 1..phases.len() {
+        network.add_link(index - 1, index);
+    }
+    if feedback && !phases.is_empty() {
+        network.add_link(phases.len() - 1, 0);
+    }
+
+    network.run(network::Scheduler::RoundRobin).map_err(|e| e.error)?;
+    Ok(network.nodes.last().and_then(|node| node.outputs.last().cloned()))
+}
+
+pub struct PhaseSearch {
+    program: Program,
+    phases: Vec<i64>,
+    length: usize,
+    feedback: bool,
+    threads: usize,
+    top: usize
+}
+
+impl PhaseSearch {
+    // By default the chain uses every phase setting once, runs without
+    // feedback and only the best result is returned.
+    pub fn new(program: Program, phases: Vec<i64>) -> PhaseSearch {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        PhaseSearch { length: phases.len(), program, phases, feedback: false, threads, top: 1 }
+    }
+
+    // Number of amplifiers in the chain, at most the number of phase settings.
+    pub fn with_length(mut self, length: usize) -> PhaseSearch {
+        self.length = length;
+        self
+    }
+
+    pub fn with_feedback(mut self) -> PhaseSearch {
+        self.feedback = true;
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> PhaseSearch {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // Number of results to return.
+    pub fn with_top(mut self, top: usize) -> PhaseSearch {
+        self.top = top;
+        self
+    }
+
+    // Number of phase settings run() tries, None if there are too many to
+    // count.
+    pub fn candidates(&self) -> Option<u64> {
+        permutation_count(self.phases.len(), self.length)
+    }
+
+    // Return the best results ordered by decreasing signal; results with the
+    // same signal are ordered by their phase settings. If the program faults
+    // for some phase settings, the error for the first of them is returned.
+    pub fn run(&self) -> Result<Vec<PhaseResult>, VmError> {
+        let count = self.candidates().unwrap_or(u64::MAX);
+        let threads = (self.threads as u64).min(count).max(1);
+
+        let worker_results: Vec<Result<Vec<PhaseResult>, (u64, VmError)>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|worker| {
+                scope.spawn(move || {
+                    let mut results = Vec::new();
+                    for index in (worker..count).step_by(threads as usize) {
+                        let phases = nth_permutation(&self.phases, self.length, index);
+                        match run_chain(&self.program, &phases, self.feedback) {
+                            Ok(Some(signal)) => {
+                                results.push(PhaseResult { signal, phases });
+                                if results.len() > self.top {
+                                    self.keep_top(&mut results);
+                                }
+                            },
+                            Ok(None) => {},
+                            Err(error) => return Err((index, error))
+                        }
+                    }
+                    Ok(results)
+                })
+            }).collect();
+            workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+        });
+
+        let mut results = Vec::new();
+        let mut first_error: Option<(u64, VmError)> = None;
+        for worker_result in worker_results {
+            match worker_result {
+                Ok(worker_results) => results.extend(worker_results),
+                Err((index, error)) => {
+                    if first_error.as_ref().is_none_or(|&(first, _)| index < first) {
+                        first_error = Some((index, error));
+                    }
+                }
+            }
+        }
+        if let Some((_, error)) = first_error {
+            return Err(error);
+        }
+        self.keep_top(&mut results);
+        Ok(results)
+    }
+
+    fn keep_top(&self, results: &mut Vec<PhaseResult>) {
+        results.sort_by(|a, b| b.signal.cmp(&a.signal).then_with(|| a.phases.cmp(&b.phases)));
+        results.truncate(self.top);
+    }
+}
+
+pub fn format_results_json(results: &[PhaseResult]) -> String {
+    let items: Vec<String> = results.iter()
+        .map(|result| {
+            let phases: Vec<String> = result.phases.iter().map(|x| x.to_string()).collect();
+            format!("{{\"signal\":{},\"phases\":[{}]}}", result.signal, phases.join(","))
+        })
+        .collect();
+    format!("[{}]", items.join(","))
+}
@@ -0,0 +1,182 @@
+// Assemble Intcode programs from the language produced by the disassembler.
+//
+// Each line may contain any number of labels ("name:") followed by either an
+// instruction or a .data directive; everything after a ';' is a comment.
+// Address prefixes printed by the disassembler ("0042:") are ignored.
+// Instruction parameters are written as [x] for position mode, x for
+// immediate mode and [$x] for relative mode, where x is a number or a label.
+// Instructions that store a result may also be written as an assignment to
+// their last parameter: "[x] = add [x] 1". A .data directive emits its values
+// (numbers or labels) as is:
+//
+//     loop:   input [value]
+//             output [value]
+//             jump_if_true 1 loop
+//     value:  .data 0
+//
+// assemble_with() also accepts the custom instructions of an OpcodeSet.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use super::*;
+use super::opcodes::OpcodeSet;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+struct Assembler<'a> {
+    opcodes: &'a OpcodeSet,
+    code: Vec<i64>,
+    labels: HashMap<String, i64>,
+    fixups: Vec<(usize, String, usize)>,
+    line: usize
+}
+
+impl<'a> Assembler<'a> {
+    fn error(&self, message: String) -> AsmError {
+        AsmError { line: self.line, message }
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), AsmError> {
+        if !is_identifier(name) {
+            return Err(self.error(format!("Invalid label name '{}'", name)));
+        }
+        if self.labels.contains_key(name) {
+            return Err(self.error(format!("Duplicate label '{}'", name)));
+        }
+        self.labels.insert(name.to_string(), self.code.len() as i64);
+        Ok(())
+    }
+
+    fn emit_value(&mut self, text: &str) -> Result<(), AsmError> {
+        if let Ok(value) = text.parse::<i64>() {
+            self.code.push(value);
+        } else if is_identifier(text) {
+            self.fixups.push((self.code.len(), text.to_string(), self.line));
+            self.code.push(0);
+        } else {
+            return Err(self.error(format!("Invalid value '{}'", text)));
+        }
+        Ok(())
+    }
+
+    fn emit_instruction(&mut self, mnemonic: &str, operands: &[&str]) -> Result<(), AsmError> {
+        let opcode = match self.opcodes.opcode_by_name(mnemonic) {
+            Some(opcode) => opcode,
+            None => return Err(self.error(format!("Unknown instruction '{}'", mnemonic)))
+        };
+
+        let count = self.opcodes.params(opcode).unwrap().len();
+        if operands.len() != count {
+            return Err(self.error(format!("Instruction '{}' expects {} parameters, got {}",
+                mnemonic, count, operands.len())));
+        }
+
+        let instr_index = self.code.len();
+        let mut instr = opcode;
+        self.code.push(0);
+
+        for (i, operand) in operands.iter().enumerate() {
+            let (mode, value) = if operand.starts_with("[$") && operand.ends_with(']') {
+                (MODE_REL, &operand[2..operand.len() - 1])
+            } else if operand.starts_with('[') && operand.ends_with(']') {
+                (MODE_POS, &operand[1..operand.len() - 1])
+            } else {
+                (MODE_IMM, *operand)
+            };
+            instr += mode * 10_i64.pow(i as u32 + 2);
+            self.emit_value(value)?;
+        }
+
+        self.code[instr_index] = instr;
+        Ok(())
+    }
+
+    fn assemble_line(&mut self, line: &str) -> Result<(), AsmError> {
+        let mut text = match line.find(';') {
+            Some(index) => &line[..index],
+            None => line
+        }.trim();
+
+        while let Some(token) = text.split_whitespace().next().filter(|t| t.ends_with(':')) {
+            let name = &token[..token.len() - 1];
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_digit()) {
+                self.define_label(name)?;
+            }
+            text = text[token.len()..].trim_start();
+        }
+
+        let tokens: Vec<&str> = text
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty())
+            .collect();
+        match tokens.split_first() {
+            Some((&".data", values)) => {
+                for value in values {
+                    self.emit_value(value)?;
+                }
+                Ok(())
+            },
+            Some((target, ["=", mnemonic, operands @ ..])) => {
+                let mut operands = operands.to_vec();
+                operands.push(target);
+                self.emit_instruction(mnemonic, &operands)
+            },
+            Some((mnemonic, operands)) => self.emit_instruction(mnemonic, operands),
+            None => Ok(())
+        }
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        },
+        _ => false
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    assemble_with(source, &OpcodeSet::new())
+}
+
+pub fn assemble_with(source: &str, opcodes: &OpcodeSet) -> Result<Vec<i64>, AsmError> {
+    let mut assembler = Assembler {
+        opcodes,
+        code: Vec::new(),
+        labels: HashMap::new(),
+        fixups: Vec::new(),
+        line: 0
+    };
+
+    for (index, line) in source.lines().enumerate() {
+        assembler.line = index + 1;
+        assembler.assemble_line(line)?;
+    }
+
+    for (index, name, line) in &assembler.fixups {
+        match assembler.labels.get(name) {
+            Some(&address) => assembler.code[*index] = address,
+            None => return Err(AsmError {
+                line: *line,
+                message: format!("Undefined label '{}'", name)
+            })
+        }
+    }
+
+    Ok(assembler.code)
+}
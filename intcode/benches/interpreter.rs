@@ -0,0 +1,81 @@
+// Compare the reference interpreter with the decode cache on the day 9 BOOST
+// program (sensor boost mode) and on auto-playing the day 13 Breakout game.
+//
+// Run with: cargo bench -p intcode --bench interpreter
+
+use std::time::{Duration, Instant};
+use intcode::*;
+use intcode::cache::{DecodeCache, run_program_cached};
+
+const ITERATIONS: u32 = 10;
+
+fn input_path(day: &str) -> String {
+    format!("{}/../{}/input.txt", env!("CARGO_MANIFEST_DIR"), day)
+}
+
+// Run the program to the end, giving it the same input value whenever it
+// needs one, or playing Breakout if the input is 0. Returns the last output
+// value or the score.
+fn run(program: &mut Program, input: i64, cached: bool) -> i64 {
+    let mut cache = DecodeCache::new();
+    let mut last_output = 0;
+    let mut ball_x: i64 = 0;
+    let mut paddle_x = 0;
+
+    loop {
+        let result = if cached {
+            run_program_cached(program, &mut cache)
+        } else {
+            run_program(program)
+        };
+        match result.unwrap() {
+            StepResult::NeedInput if input == 0 => {
+                program.data.push_back((ball_x - paddle_x).signum());
+            },
+            StepResult::NeedInput => program.data.push_back(input),
+            StepResult::Output if input != 0 => {
+                last_output = program.data.pop_front().unwrap();
+            },
+            StepResult::Output => {
+                if program.data.len() == 3 {
+                    let x = program.data.pop_front().unwrap();
+                    let y = program.data.pop_front().unwrap();
+                    let tile = program.data.pop_front().unwrap();
+                    match (x, y, tile) {
+                        (-1, 0, _) => last_output = tile,
+                        (_, _, 3) => paddle_x = x,
+                        (_, _, 4) => ball_x = x,
+                        _ => {}
+                    }
+                }
+            },
+            StepResult::Halt => return last_output
+        }
+    }
+}
+
+fn bench(name: &str, program: &Program, input: i64, cached: bool) -> Duration {
+    let start = Instant::now();
+    let mut result = 0;
+    for _ in 0..ITERATIONS {
+        result = run(&mut program.clone(), input, cached);
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{: >10}: {:?} per run (result {})", name, elapsed, result);
+    elapsed
+}
+
+fn main() {
+    // Input 2 runs day 9 in sensor boost mode; 0 plays Breakout for day 13.
+    for &(day, input) in [("9", 2), ("13", 0)].iter() {
+        let mut program = load_program(&input_path(day));
+        if day == "13" {
+            program.memory.write(0, 2); // insert 2 quarters
+        }
+
+        println!("day {}:", day);
+        let reference = bench("reference", &program, input, false);
+        let cached = bench("cached", &program, input, true);
+        println!("speedup: {:.2}x", reference.as_secs_f64() / cached.as_secs_f64());
+    }
+}
@@ -0,0 +1,59 @@
+// Compare memory backends by auto-playing the day 13 Breakout game to the end.
+//
+// Run with: cargo bench -p intcode --bench memory
+
+use std::time::{Duration, Instant};
+use intcode::*;
+
+const INPUT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../13/input.txt");
+const ITERATIONS: u32 = 10;
+
+fn play_breakout<M: Memory>(program: &mut Program<M>) -> i64 {
+    program.memory.write(0, 2); // insert 2 quarters
+
+    let mut score = 0;
+    let mut ball_x: i64 = 0;
+    let mut paddle_x = 0;
+
+    loop {
+        match run_program(program).unwrap() {
+            StepResult::NeedInput => {
+                program.data.push_back((ball_x - paddle_x).signum());
+            },
+            StepResult::Output => {
+                if program.data.len() == 3 {
+                    let x = program.data.pop_front().unwrap();
+                    let y = program.data.pop_front().unwrap();
+                    let tile = program.data.pop_front().unwrap();
+                    match (x, y, tile) {
+                        (-1, 0, _) => score = tile,
+                        (_, _, 3) => paddle_x = x,
+                        (_, _, 4) => ball_x = x,
+                        _ => {}
+                    }
+                }
+            },
+            StepResult::Halt => return score
+        }
+    }
+}
+
+fn bench<M: Memory + Clone>(name: &str, program: &Program<M>) -> Duration {
+    let start = Instant::now();
+    let mut score = 0;
+    for _ in 0..ITERATIONS {
+        score = play_breakout(&mut program.clone());
+    }
+    let elapsed = start.elapsed() / ITERATIONS;
+    println!("{: >8}: {:?} per game (score {})", name, elapsed, score);
+    elapsed
+}
+
+fn main() {
+    let code = load_code(INPUT_PATH);
+
+    let paged = bench("paged", &Program::with_memory(code.clone(), PagedMemory::new()));
+    let sparse = bench("sparse", &Program::with_memory(code, SparseMemory::new()));
+
+    println!("speedup: {:.2}x", sparse.as_secs_f64() / paged.as_secs_f64());
+}
@@ -0,0 +1,374 @@
+// Arbitrary precision Intcode VM.
+//
+// BigProgram works like Program but memory cells, parameters and input and
+// output values are BigInts, so additions and multiplications never overflow.
+// Instructions, addresses and the relative base still have to fit in an i64;
+// values that don't are reported as VmError::Overflow.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::{Add, Mul, Neg};
+use std::str::FromStr;
+use super::*;
+
+// Signed integer of any size: a sign and a magnitude stored as base 2^32
+// digits, least significant first, without leading zero digits.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    digits: Vec<u32>
+}
+
+fn compare_digits(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    if carry != 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+// Subtract b from a, where a >= b.
+fn sub_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &digit) in a.iter().enumerate() {
+        let mut difference = digit as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if difference < 0 {
+            difference += 1 << 32;
+            borrow = 1;
+        }
+        result.push(difference as u32);
+    }
+    result
+}
+
+fn mul_digits(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let product = x as u64 * y as u64 + result[i + j] as u64 + carry;
+            result[i + j] = product as u32;
+            carry = product >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+    result
+}
+
+impl BigInt {
+    fn new(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        BigInt { negative: negative && !digits.is_empty(), digits }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 2 {
+            return None;
+        }
+        let magnitude = self.digits.iter().rev().fold(0u64, |value, &digit| value << 32 | digit as u64);
+        if self.negative {
+            if magnitude <= i64::MAX as u64 + 1 {
+                Some((magnitude as i64).wrapping_neg())
+            } else {
+                None
+            }
+        } else if magnitude <= i64::MAX as u64 {
+            Some(magnitude as i64)
+        } else {
+            None
+        }
+    }
+
+    // Divide the magnitude by a small number, returning the remainder.
+    fn div_rem_small(&self, divisor: u32) -> (BigInt, u32) {
+        let mut quotient = vec![0u32; self.digits.len()];
+        let mut remainder = 0u64;
+        for i in (0..self.digits.len()).rev() {
+            let value = remainder << 32 | self.digits[i] as u64;
+            quotient[i] = (value / divisor as u64) as u32;
+            remainder = value % divisor as u64;
+        }
+        (BigInt::new(self.negative, quotient), remainder as u32)
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(value: i64) -> BigInt {
+        let magnitude = value.unsigned_abs();
+        BigInt::new(value < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<BigInt, String> {
+        let (negative, number) = match text.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, text.strip_prefix('+').unwrap_or(text))
+        };
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Invalid number '{}'", text));
+        }
+        let mut digits = Vec::new();
+        for c in number.bytes() {
+            digits = add_digits(&mul_digits(&digits, &[10]), &[(c - b'0') as u32]);
+        }
+        Ok(BigInt::new(negative, digits))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // Split into groups of 9 decimal digits.
+        let mut groups = Vec::new();
+        let mut value = BigInt::new(false, self.digits.clone());
+        while !value.is_zero() {
+            let (quotient, remainder) = value.div_rem_small(1_000_000_000);
+            groups.push(remainder);
+            value = quotient;
+        }
+        let mut text = if self.negative { "-".to_string() } else { String::new() };
+        text.push_str(&groups.pop().unwrap().to_string());
+        for group in groups.iter().rev() {
+            text.push_str(&format!("{:09}", group));
+        }
+        write!(f, "{}", text)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_digits(&self.digits, &other.digits),
+            (true, true) => compare_digits(&other.digits, &self.digits)
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.digits.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add_digits(&self.digits, &other.digits));
+        }
+        match compare_digits(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::new(other.negative, sub_digits(&other.digits, &self.digits)),
+            _ => BigInt::new(self.negative, sub_digits(&self.digits, &other.digits))
+        }
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(self.negative != other.negative, mul_digits(&self.digits, &other.digits))
+    }
+}
+
+pub fn parse_big_code(text: &str) -> Result<Vec<BigInt>, String> {
+    text.trim().split(',').map(|x| x.trim().parse::<BigInt>()).collect()
+}
+
+#[derive(Clone)]
+pub struct BigProgram {
+    pub code: Vec<BigInt>,
+    pub ip: i64,
+    pub memory: HashMap<i64, BigInt>,
+    pub rel_base: i64,
+    pub data: VecDeque<BigInt>
+}
+
+impl BigProgram {
+    pub fn new(code: Vec<BigInt>) -> BigProgram {
+        let mut program = BigProgram {
+            code,
+            ip: 0,
+            memory: HashMap::new(),
+            rel_base: 0,
+            data: VecDeque::new()
+        };
+        init_big_program(&mut program);
+        program
+    }
+
+    pub fn read(&self, address: i64) -> BigInt {
+        self.memory.get(&address).cloned().unwrap_or_default()
+    }
+
+    pub fn write(&mut self, address: i64, value: BigInt) {
+        if value.is_zero() {
+            self.memory.remove(&address);
+        } else {
+            self.memory.insert(address, value);
+        }
+    }
+}
+
+pub fn init_big_program(program: &mut BigProgram) {
+    program.ip = 0;
+    program.memory.clear();
+    program.rel_base = 0;
+    program.data.clear();
+
+    for i in 0..program.code.len() {
+        let value = program.code[i].clone();
+        program.write(i as i64, value);
+    }
+}
+
+// Read the word at the address as an i64, as needed for instructions and
+// addresses.
+fn read_small(program: &BigProgram, address: i64, instr: i64, opcode: i64) -> Result<i64, VmError> {
+    program.read(address).to_i64().ok_or(VmError::Overflow { address: program.ip, instr, opcode })
+}
+
+// Resolve a parameter to an address, or return the value of an immediate
+// mode parameter.
+fn read_param(program: &BigProgram, instr: i64, index: u32, output: bool) -> Result<Result<i64, BigInt>, VmError> {
+    let address = program.ip;
+    let opcode = read_opcode(instr);
+    let mode = read_param_mode(instr, index);
+    let param_address = address + 1 + index as i64;
+    let target = match mode {
+        MODE_POS => read_small(program, param_address, instr, opcode)?,
+        MODE_IMM if output => return Err(VmError::ImmediateWrite { address, instr, opcode, index }),
+        MODE_IMM => return Ok(Err(program.read(param_address))),
+        MODE_REL => {
+            let offset = read_small(program, param_address, instr, opcode)?;
+            offset.checked_add(program.rel_base).ok_or(VmError::Overflow { address, instr, opcode })?
+        },
+        _ => return Err(VmError::InvalidMode { address, instr, opcode, index, mode })
+    };
+    if target < 0 {
+        return Err(VmError::NegativeAddress { address, instr, opcode, index, mode, target });
+    }
+    Ok(Ok(target))
+}
+
+fn read_param_value(program: &BigProgram, instr: i64, index: u32) -> Result<BigInt, VmError> {
+    Ok(match read_param(program, instr, index, false)? {
+        Ok(address) => program.read(address),
+        Err(value) => value
+    })
+}
+
+fn read_param_address(program: &BigProgram, instr: i64, index: u32) -> Result<i64, VmError> {
+    Ok(read_param(program, instr, index, true)?.unwrap_or_default())
+}
+
+// Execute a single instruction, see step_program().
+pub fn step_big_program(program: &mut BigProgram) -> Result<Option<StepResult>, VmError> {
+    let ip = program.ip;
+    if ip < 0 || ip >= program.code.len() as i64 {
+        return Err(VmError::IpOutOfRange { address: ip });
+    }
+
+    let word = program.read(ip);
+    let instr = match word.to_i64() {
+        Some(instr) => instr,
+        None => {
+            // Report the instruction clamped to the i64 range.
+            let instr = if word.negative { i64::MIN } else { i64::MAX };
+            let opcode = word.div_rem_small(100).1 as i64 * if word.negative { -1 } else { 1 };
+            return Err(VmError::Overflow { address: ip, instr, opcode });
+        }
+    };
+    let opcode = read_opcode(instr);
+    let overflow = VmError::Overflow { address: ip, instr, opcode };
+
+    match opcode {
+        OP_HALT => return Ok(Some(StepResult::Halt)),
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => {
+            let param1 = read_param_value(program, instr, 0)?;
+            let param2 = read_param_value(program, instr, 1)?;
+            let result_address = read_param_address(program, instr, 2)?;
+            let result = match opcode {
+                OP_ADD => &param1 + &param2,
+                OP_MULTIPLY => &param1 * &param2,
+                OP_LESS_THAN => BigInt::from((param1 < param2) as i64),
+                _ => BigInt::from((param1 == param2) as i64)
+            };
+            program.write(result_address, result);
+            program.ip += 4;
+        },
+        OP_INPUT => {
+            let address = read_param_address(program, instr, 0)?;
+            match program.data.pop_front() {
+                Some(value) => program.write(address, value),
+                None => return Ok(Some(StepResult::NeedInput))
+            }
+            program.ip += 2;
+        },
+        OP_OUTPUT => {
+            let value = read_param_value(program, instr, 0)?;
+            program.data.push_back(value);
+            program.ip += 2;
+            return Ok(Some(StepResult::Output));
+        },
+        OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => {
+            let condition = read_param_value(program, instr, 0)?;
+            let target = read_param_value(program, instr, 1)?;
+            if condition.is_zero() == (opcode == OP_JUMP_IF_FALSE) {
+                program.ip = target.to_i64().ok_or(overflow)?;
+            } else {
+                program.ip += 3;
+            }
+        },
+        OP_REL_BASE_OFFSET => {
+            let offset = read_param_value(program, instr, 0)?.to_i64().ok_or_else(|| overflow.clone())?;
+            program.rel_base = program.rel_base.checked_add(offset).ok_or(overflow)?;
+            program.ip += 2;
+        },
+        _ => return Err(VmError::InvalidOpcode { address: ip, instr, opcode })
+    }
+    Ok(None)
+}
+
+pub fn run_big_program(program: &mut BigProgram) -> Result<StepResult, VmError> {
+    loop {
+        if let Some(result) = step_big_program(program)? {
+            return Ok(result);
+        }
+    }
+}
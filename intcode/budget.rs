@@ -0,0 +1,179 @@
+// Resource limits for running untrusted or non-terminating programs.
+//
+// run_program_limited() runs a program like run_program() but stops with
+// VmError::BudgetExceeded before executing an instruction that would go over
+// one of the limits of a Budget: the number of executed instructions, the
+// wall-clock time spent running and the number of memory cells outside the
+// code that the program writes to. As with other faults the ip is left
+// pointing at the instruction and nothing else is changed, so the host can
+// inspect the program, raise the budget and resume.
+//
+// Usage is accumulated in a Meter across calls. The memory limit is checked
+// before the write reaches the memory backend; note that PagedMemory
+// allocates a whole page for every page written to, so for programs that
+// write to scattered addresses SparseMemory keeps the memory actually used
+// closer to the number of cells.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, Instant};
+use super::*;
+use super::opcodes::Param;
+
+// How often the clock is checked, in instructions.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Instructions,
+    Time,
+    MemoryCells
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions => write!(f, "instruction limit"),
+            Limit::Time => write!(f, "time limit"),
+            Limit::MemoryCells => write!(f, "memory limit")
+        }
+    }
+}
+
+// Limits that are None are not enforced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    pub instructions: Option<u64>,
+    pub time: Option<Duration>,
+    pub memory_cells: Option<usize>
+}
+
+impl Budget {
+    pub fn new() -> Budget {
+        Budget::default()
+    }
+
+    pub fn with_instructions(mut self, instructions: u64) -> Budget {
+        self.instructions = Some(instructions);
+        self
+    }
+
+    pub fn with_time(mut self, time: Duration) -> Budget {
+        self.time = Some(time);
+        self
+    }
+
+    pub fn with_memory_cells(mut self, memory_cells: usize) -> Budget {
+        self.memory_cells = Some(memory_cells);
+        self
+    }
+}
+
+// Resources used by a program so far.
+#[derive(Clone, Debug, Default)]
+pub struct Meter {
+    pub budget: Budget,
+    instructions: u64,
+    elapsed: Duration,
+    // Addresses outside the code that were written to.
+    cells: HashSet<i64>
+}
+
+impl Meter {
+    pub fn new(budget: Budget) -> Meter {
+        Meter { budget, ..Meter::default() }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    // Only counted while there is a memory limit.
+    pub fn memory_cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    // Start counting from zero, e.g. after init_program().
+    pub fn reset(&mut self) {
+        self.instructions = 0;
+        self.elapsed = Duration::default();
+        self.cells.clear();
+    }
+}
+
+// Addresses outside the code that the instruction at the ip writes to.
+// Errors are left for step_program() to report.
+fn new_cells<M: Memory>(program: &Program<M>, meter: &Meter) -> Vec<i64> {
+    let ip = program.ip;
+    if ip < 0 || ip >= program.code.len() as i64 {
+        return Vec::new();
+    }
+    let params = match program.opcodes.params(read_opcode(program.memory.read(ip))) {
+        Some(params) => params,
+        None => return Vec::new()
+    };
+    let mut cells = Vec::new();
+    for (index, param) in params.iter().enumerate() {
+        if *param != Param::Write {
+            continue;
+        }
+        if let Ok(address) = read_param_value_out(ip + 1, &program.memory, program.rel_base, program.arithmetic, index as u32) {
+            if address >= program.code.len() as i64 && !meter.cells.contains(&address) && !cells.contains(&address) {
+                cells.push(address);
+            }
+        }
+    }
+    cells
+}
+
+// Execute a single instruction like step_program() unless it goes over the
+// budget. The clock is not checked here, see run_program_limited().
+pub fn step_program_limited<M: Memory>(program: &mut Program<M>, meter: &mut Meter)
+        -> Result<Option<StepResult>, VmError> {
+    let exceeded = |limit| Err(VmError::BudgetExceeded { address: program.ip, limit });
+    if meter.budget.instructions.is_some_and(|limit| meter.instructions >= limit) {
+        return exceeded(Limit::Instructions);
+    }
+    let cells = match meter.budget.memory_cells {
+        Some(limit) => {
+            let cells = new_cells(program, meter);
+            if meter.cells.len() + cells.len() > limit {
+                return exceeded(Limit::MemoryCells);
+            }
+            cells
+        },
+        None => Vec::new()
+    };
+
+    let result = step_program(program)?;
+    if result != Some(StepResult::NeedInput) {
+        meter.instructions += 1;
+        meter.cells.extend(cells);
+    }
+    Ok(result)
+}
+
+// Run the program until it halts, produces an output value, needs an input
+// value or exceeds the budget.
+pub fn run_program_limited<M: Memory>(program: &mut Program<M>, meter: &mut Meter) -> Result<StepResult, VmError> {
+    let start = Instant::now();
+    let mut steps = 0;
+    let result = loop {
+        if let Some(limit) = meter.budget.time {
+            if steps % TIME_CHECK_INTERVAL == 0 && meter.elapsed + start.elapsed() >= limit {
+                break Err(VmError::BudgetExceeded { address: program.ip, limit: Limit::Time });
+            }
+        }
+        match step_program_limited(program, meter) {
+            Ok(None) => steps += 1,
+            Ok(Some(result)) => break Ok(result),
+            Err(error) => break Err(error)
+        }
+    };
+    meter.elapsed += start.elapsed();
+    result
+}
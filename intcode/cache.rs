@@ -0,0 +1,242 @@
+// Faster execution engine that caches decoded instructions.
+//
+// run_program_cached() behaves exactly like run_program() but decodes each
+// instruction only the first time it is executed and keeps the result in a
+// DecodeCache. Writes made by the program itself invalidate the cached
+// instructions they overlap, so self-modifying code works as usual. Anything
+// out of the ordinary, such as invalid instructions, accesses to negative
+// addresses or checked arithmetic overflows, is handed over to step_program()
+// to produce the same error. Custom opcodes are executed by step_program()
+// too, which is slow since the cache is cleared after each of them.
+//
+// The cache can't see memory writes made by the host: call invalidate() for
+// each modified address, or clear(), before running the program again.
+
+use super::*;
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Pos(i64),
+    Imm(i64),
+    Rel(i64)
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Op {
+    Add(Operand, Operand, Operand),
+    Multiply(Operand, Operand, Operand),
+    LessThan(Operand, Operand, Operand),
+    Equals(Operand, Operand, Operand),
+    Input(Operand),
+    Output(Operand),
+    JumpIfTrue(Operand, Operand),
+    JumpIfFalse(Operand, Operand),
+    RelBaseOffset(Operand),
+    Halt
+}
+
+// Longest instruction: opcode and three parameters.
+const MAX_INSTRUCTION_SIZE: usize = 4;
+
+#[derive(Clone, Default)]
+pub struct DecodeCache {
+    ops: Vec<Option<Op>>
+}
+
+impl DecodeCache {
+    pub fn new() -> DecodeCache {
+        DecodeCache { ops: Vec::new() }
+    }
+
+    // Forget the cached instructions that include the address.
+    pub fn invalidate(&mut self, address: i64) {
+        if address < 0 {
+            return;
+        }
+        let address = address as usize;
+        let start = address.saturating_sub(MAX_INSTRUCTION_SIZE - 1);
+        for op in self.ops.iter_mut().take(address + 1).skip(start) {
+            *op = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for op in &mut self.ops {
+            *op = None;
+        }
+    }
+}
+
+// Decode the instruction at the address, or return None if it is invalid.
+fn decode<M: Memory>(memory: &M, address: i64) -> Option<Op> {
+    let instr = memory.read(address);
+    let operand = |index: u32| {
+        let value = memory.read(address + 1 + index as i64);
+        match read_param_mode(instr, index) {
+            MODE_POS => Some(Operand::Pos(value)),
+            MODE_IMM => Some(Operand::Imm(value)),
+            MODE_REL => Some(Operand::Rel(value)),
+            _ => None
+        }
+    };
+    let output_operand = |index: u32| {
+        match operand(index)? {
+            Operand::Imm(_) => None,
+            operand => Some(operand)
+        }
+    };
+
+    let op = match read_opcode(instr) {
+        OP_HALT => Op::Halt,
+        OP_ADD => Op::Add(operand(0)?, operand(1)?, output_operand(2)?),
+        OP_MULTIPLY => Op::Multiply(operand(0)?, operand(1)?, output_operand(2)?),
+        OP_LESS_THAN => Op::LessThan(operand(0)?, operand(1)?, output_operand(2)?),
+        OP_EQUALS => Op::Equals(operand(0)?, operand(1)?, output_operand(2)?),
+        OP_INPUT => Op::Input(output_operand(0)?),
+        OP_OUTPUT => Op::Output(operand(0)?),
+        OP_JUMP_IF_TRUE => Op::JumpIfTrue(operand(0)?, operand(1)?),
+        OP_JUMP_IF_FALSE => Op::JumpIfFalse(operand(0)?, operand(1)?),
+        OP_REL_BASE_OFFSET => Op::RelBaseOffset(operand(0)?),
+        _ => return None
+    };
+    Some(op)
+}
+
+// Resolve the address of a position or relative mode operand. Returns None
+// for negative addresses and overflows, which step_program() deals with
+// according to the arithmetic mode.
+#[inline]
+fn operand_address(operand: Operand, rel_base: i64) -> Option<i64> {
+    let address = match operand {
+        Operand::Pos(address) => address,
+        Operand::Rel(offset) => offset.checked_add(rel_base)?,
+        Operand::Imm(_) => return None
+    };
+    if address < 0 { None } else { Some(address) }
+}
+
+#[inline]
+fn operand_value<M: Memory>(memory: &M, operand: Operand, rel_base: i64) -> Option<i64> {
+    match operand {
+        Operand::Imm(value) => Some(value),
+        _ => operand_address(operand, rel_base).map(|address| memory.read(address))
+    }
+}
+
+pub fn run_program_cached<M: Memory>(program: &mut Program<M>, cache: &mut DecodeCache)
+        -> Result<StepResult, VmError> {
+    let code_len = program.code.len();
+    if cache.ops.len() != code_len {
+        cache.ops.clear();
+        cache.ops.resize(code_len, None);
+    }
+
+    // Execute the current instruction with step_program() and return its
+    // result, if any, or carry on with the cache.
+    macro_rules! fall_back {
+        () => {
+            match fall_back(program, cache)? {
+                Some(result) => return Ok(result),
+                None => continue
+            }
+        };
+    }
+
+    loop {
+        let ip = program.ip;
+        if ip < 0 || ip >= code_len as i64 {
+            return Err(VmError::IpOutOfRange { address: ip });
+        }
+
+        let op = match cache.ops[ip as usize] {
+            Some(op) => op,
+            None => match decode(&program.memory, ip) {
+                Some(op) => {
+                    cache.ops[ip as usize] = Some(op);
+                    op
+                },
+                None => fall_back!()
+            }
+        };
+
+        let memory = &mut program.memory;
+        let rel_base = program.rel_base;
+        match op {
+            Op::Add(a, b, c) | Op::Multiply(a, b, c) | Op::LessThan(a, b, c) | Op::Equals(a, b, c) => {
+                let values = (operand_value(memory, a, rel_base), operand_value(memory, b, rel_base),
+                    operand_address(c, rel_base));
+                let (a, b, address) = match values {
+                    (Some(a), Some(b), Some(address)) => (a, b, address),
+                    _ => fall_back!()
+                };
+                let result = match op {
+                    Op::Add(..) => program.arithmetic.add(a, b),
+                    Op::Multiply(..) => program.arithmetic.multiply(a, b),
+                    Op::LessThan(..) => Some((a < b) as i64),
+                    _ => Some((a == b) as i64)
+                };
+                let result = match result {
+                    Some(result) => result,
+                    None => fall_back!()
+                };
+                memory.write(address, result);
+                cache.invalidate(address);
+                program.ip = ip + 4;
+            },
+            Op::Input(a) => {
+                let address = match operand_address(a, rel_base) {
+                    Some(address) => address,
+                    None => fall_back!()
+                };
+                match program.data.pop_front() {
+                    Some(value) => {
+                        memory.write(address, value);
+                        cache.invalidate(address);
+                        program.ip = ip + 2;
+                    },
+                    None => return Ok(StepResult::NeedInput)
+                }
+            },
+            Op::Output(a) => {
+                let value = match operand_value(memory, a, rel_base) {
+                    Some(value) => value,
+                    None => fall_back!()
+                };
+                program.data.push_back(value);
+                program.ip = ip + 2;
+                return Ok(StepResult::Output);
+            },
+            Op::JumpIfTrue(a, b) | Op::JumpIfFalse(a, b) => {
+                let (condition, target) = match (operand_value(memory, a, rel_base), operand_value(memory, b, rel_base)) {
+                    (Some(condition), Some(target)) => (condition, target),
+                    _ => fall_back!()
+                };
+                let jump = match op {
+                    Op::JumpIfTrue(..) => condition != 0,
+                    _ => condition == 0
+                };
+                program.ip = if jump { target } else { ip + 3 };
+            },
+            Op::RelBaseOffset(a) => {
+                match operand_value(memory, a, rel_base).and_then(|value| program.arithmetic.add(rel_base, value)) {
+                    Some(rel_base) => program.rel_base = rel_base,
+                    None => fall_back!()
+                }
+                program.ip = ip + 2;
+            },
+            Op::Halt => return Ok(StepResult::Halt)
+        }
+    }
+}
+
+// Execute the current instruction with the reference interpreter, which
+// reports the error. If it succeeds instead, e.g. for custom opcodes, the
+// cache is cleared since the instruction may have written anywhere.
+fn fall_back<M: Memory>(program: &mut Program<M>, cache: &mut DecodeCache)
+        -> Result<Option<StepResult>, VmError> {
+    let result = step_program(program)?;
+    if result.is_none() {
+        cache.clear();
+    }
+    Ok(result)
+}
@@ -0,0 +1,239 @@
+// Code coverage.
+//
+// Coverage is a Tracer that records which instructions are executed and, for
+// each conditional jump, how many times it was taken and not taken. Jumps
+// with an immediate condition are not counted as branches since they always
+// go the same way. Coverage from several runs, e.g. with different inputs,
+// can be merged.
+//
+// Coverage is measured against the instructions found by disasm::analyze()
+// when it also starts from every executed address, so code that is only
+// reached through computed jumps counts as soon as one run gets there, plus
+// any other executed instructions, such as those modified at run time. The
+// results can be shown as a disassembly listing annotated with hit counts,
+// or written as an lcov-like tracefile with instruction addresses in place of
+// line numbers:
+//
+//     TN:
+//     SF:5/input.txt
+//     DA:0,2
+//     DA:6,0
+//     BRDA:6,0,0,1
+//     BRDA:6,0,1,-
+//     BRF:2
+//     BRH:1
+//     LF:2
+//     LH:1
+//     end_of_record
+//
+// parse_lcov() reads such a file back so that coverage can be accumulated
+// across invocations of a tool.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use super::*;
+use super::disasm::{Analysis, analyze_from};
+use super::opcodes::OpcodeSet;
+use super::trace::{TraceRecord, Tracer};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    // Number of executions by instruction address.
+    pub hits: BTreeMap<i64, u64>,
+    // Number of times each conditional jump was taken and not taken.
+    pub branches: BTreeMap<i64, (u64, u64)>
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub instructions: usize,
+    pub instructions_hit: usize,
+    // Each conditional jump counts as two branches.
+    pub branches: usize,
+    pub branches_hit: usize
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CoverageError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for CoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for CoverageError {}
+
+impl Tracer for Coverage {
+    fn trace(&mut self, record: &TraceRecord) {
+        *self.hits.entry(record.ip).or_insert(0) += 1;
+        if (record.opcode == OP_JUMP_IF_TRUE || record.opcode == OP_JUMP_IF_FALSE) && record.modes[0] != MODE_IMM {
+            let taken = (record.opcode == OP_JUMP_IF_TRUE) == (record.values[0] != 0);
+            let counts = self.branches.entry(record.ip).or_insert((0, 0));
+            if taken {
+                counts.0 += 1;
+            } else {
+                counts.1 += 1;
+            }
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&address, &count) in &other.hits {
+            *self.hits.entry(address).or_insert(0) += count;
+        }
+        for (&address, &(taken, not_taken)) in &other.branches {
+            let counts = self.branches.entry(address).or_insert((0, 0));
+            counts.0 += taken;
+            counts.1 += not_taken;
+        }
+    }
+
+    // Address 0 and the executed addresses, to start the analysis and the
+    // disassembly from.
+    pub fn entries(&self) -> Vec<i64> {
+        Some(0).into_iter().chain(self.hits.keys().cloned()).collect()
+    }
+
+    fn analyze(&self, code: &[i64]) -> Analysis {
+        analyze_from(code, &OpcodeSet::new(), &self.entries())
+    }
+
+    // All instructions and conditional jumps of the program with their
+    // counts, including those that were never executed.
+    fn complete(&self, code: &[i64]) -> Coverage {
+        let mut coverage = self.clone();
+        for (&address, instruction) in &self.analyze(code).instructions {
+            coverage.hits.entry(address).or_insert(0);
+            if instruction.params.first().is_some_and(|&(mode, _)| mode != MODE_IMM)
+                    && (instruction.opcode == OP_JUMP_IF_TRUE || instruction.opcode == OP_JUMP_IF_FALSE) {
+                coverage.branches.entry(address).or_insert((0, 0));
+            }
+        }
+        coverage
+    }
+
+    pub fn summary(&self, code: &[i64]) -> Summary {
+        let coverage = self.complete(code);
+        Summary {
+            instructions: coverage.hits.len(),
+            instructions_hit: coverage.hits.values().filter(|&&count| count > 0).count(),
+            branches: coverage.branches.len() * 2,
+            branches_hit: coverage.branches.values()
+                .map(|&(taken, not_taken)| (taken > 0) as usize + (not_taken > 0) as usize)
+                .sum()
+        }
+    }
+}
+
+fn percent(hit: usize, total: usize) -> f64 {
+    if total == 0 { 100.0 } else { hit as f64 * 100.0 / total as f64 }
+}
+
+pub fn format_summary(summary: &Summary) -> String {
+    format!("Instructions: {} of {} executed ({:.2}%)\nBranches: {} of {} taken ({:.2}%)\n",
+        summary.instructions_hit, summary.instructions, percent(summary.instructions_hit, summary.instructions),
+        summary.branches_hit, summary.branches, percent(summary.branches_hit, summary.branches))
+}
+
+pub fn format_lcov(coverage: &Coverage, code: &[i64], name: &str) -> String {
+    let complete = coverage.complete(code);
+    let summary = coverage.summary(code);
+    let mut text = format!("TN:\nSF:{}\n", name);
+    for (&address, &count) in &complete.hits {
+        text.push_str(&format!("DA:{},{}\n", address, count));
+    }
+    for (&address, &(taken, not_taken)) in &complete.branches {
+        // "-" means that the jump was never executed.
+        let executed = taken + not_taken > 0;
+        for (index, count) in [taken, not_taken].iter().enumerate() {
+            let count = if executed { count.to_string() } else { "-".to_string() };
+            text.push_str(&format!("BRDA:{},0,{},{}\n", address, index, count));
+        }
+    }
+    text.push_str(&format!("BRF:{}\nBRH:{}\n", summary.branches, summary.branches_hit));
+    text.push_str(&format!("LF:{}\nLH:{}\n", summary.instructions, summary.instructions_hit));
+    text.push_str("end_of_record\n");
+    text
+}
+
+// Read the counts from a tracefile written by format_lcov(). Records for
+// other things than instructions and branches are ignored.
+pub fn parse_lcov(text: &str) -> Result<Coverage, CoverageError> {
+    let mut coverage = Coverage::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: &str| CoverageError { line: index + 1, message: message.to_string() };
+        let (key, value) = match line.trim().find(':') {
+            Some(colon) => (&line.trim()[..colon], &line.trim()[colon + 1..]),
+            None => continue
+        };
+        let fields: Vec<&str> = value.split(',').collect();
+        let number = |text: &str| text.parse::<i64>().map_err(|_| error(&format!("Invalid number '{}'", text)));
+        match (key, &fields[..]) {
+            ("DA", &[address, count]) => {
+                let (address, count) = (number(address)?, number(count)?);
+                if count < 0 {
+                    return Err(error("Negative count"));
+                }
+                *coverage.hits.entry(address).or_insert(0) += count as u64;
+            },
+            ("BRDA", &[address, _, branch, count]) => {
+                let address = number(address)?;
+                let count = if count == "-" { 0 } else { number(count)? };
+                if count < 0 {
+                    return Err(error("Negative count"));
+                }
+                let counts = coverage.branches.entry(address).or_insert((0, 0));
+                match branch {
+                    "0" => counts.0 += count as u64,
+                    "1" => counts.1 += count as u64,
+                    _ => return Err(error(&format!("Invalid branch '{}'", branch)))
+                }
+            },
+            ("DA", _) | ("BRDA", _) => return Err(error(&format!("Invalid {} record", key))),
+            _ => ()
+        }
+    }
+    Ok(coverage)
+}
+
+// Prefix each line of a disassembly listing that starts with an instruction
+// address ("0042: ...") with its hit count, or "#####" if the instruction was
+// never executed, and append the branch counts to conditional jumps. The
+// listing should start from the same entries, see Coverage::entries().
+pub fn annotate_listing(listing: &str, coverage: &Coverage, code: &[i64]) -> String {
+    let analysis = coverage.analyze(code);
+    let mut text = String::new();
+    for line in listing.lines() {
+        let address = line.find(':')
+            .map(|index| &line[..index])
+            .filter(|prefix| !prefix.is_empty() && prefix.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|prefix| prefix.parse::<i64>().ok());
+        let count = match address.and_then(|address| coverage.hits.get(&address)) {
+            Some(&count) => format!("{: >10}", count),
+            None if address.is_some_and(|address| analysis.instructions.contains_key(&address)) =>
+                format!("{: >10}", "#####"),
+            None if line.is_empty() => {
+                text.push('\n');
+                continue;
+            },
+            None => String::new()
+        };
+        text.push_str(&format!("{: >10}  {}", count, line));
+        if let Some(&(taken, not_taken)) = address.and_then(|address| coverage.branches.get(&address)) {
+            text.push_str(&format!("   ; taken {}, not taken {}", taken, not_taken));
+        }
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,1090 @@
+// Decompile Intcode programs into C-like pseudo-code.
+//
+// Puzzle programs are compiled with a stack-based calling convention built on
+// the relative base. The caller stores the arguments in [$1], [$2], ... and
+// the return address in [$0], then jumps to the function:
+//
+//     [$1] = add 0 [v10]
+//     [$0] = add 0 1234
+//     jump_if_true 1 func        ; 1234 is the address after the jump
+//
+// The function allocates its stack frame with "rel_base_offset n", so that
+// the arguments end up in [$1-n], [$2-n], ..., and returns with
+// "rel_base_offset -n" followed by "jump_if_true 1 [$0]". A return value is
+// left in the first argument, i.e. in the caller's [$1].
+//
+// find_functions() follows these calls from address 0 (and constants that
+// point to a function prologue, which are used as function pointers) to split
+// the program into functions. decompile() then rebuilds loops from backward
+// jumps and if/else statements from forward jumps, and names the values on
+// the stack: a1, a2, ... for arguments and l3, l4, ... for locals. Globals are
+// named v<address> like in the disassembler and cells inside the code, which
+// the program modifies, are written as mem[<address>]. Comparison results
+// that are only used by the following conditional jump are folded into the
+// condition. Control flow that doesn't fit these patterns is written with
+// goto. Instructions that the program modifies before executing them are
+// shown as comments, and ranges outside the decoded code that look like code,
+// e.g. reached only through a jump table, are listed as undecoded at the end.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use super::*;
+use super::disasm::{Analysis, Instruction, analyze, decode_instruction};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Plain,
+    // Conditional or unconditional jump within the function.
+    Jump,
+    // Jump to a function (an immediate address or a function pointer) after
+    // storing the return address in [$0].
+    Call { target: (i64, i64), ret: i64 },
+    Return,
+    Halt
+}
+
+#[derive(Clone, Debug)]
+pub struct Function {
+    pub entry: i64,
+    // Size of the stack frame allocated at the entry, 0 if there is none.
+    pub frame: i64,
+    pub params: usize,
+    pub returns_value: bool,
+    // The function is called through a pointer.
+    pub address_taken: bool,
+    pub instructions: BTreeMap<i64, Instruction>,
+    pub kinds: BTreeMap<i64, Kind>,
+    // Relative base before each instruction relative to its value at the
+    // entry, or None if it isn't always the same.
+    pub deltas: BTreeMap<i64, Option<i64>>,
+    // Addresses where control flow can enter other than by falling through.
+    pub block_starts: BTreeSet<i64>,
+    // Words on the paths of the function that the program modifies before
+    // executing them (see disasm::Analysis::self_modified), with the size of
+    // the instruction they become and where it may jump to.
+    pub self_modified: BTreeMap<i64, (i64, Option<i64>)>
+}
+
+fn successors(instruction: &Instruction, kind: &Kind) -> Vec<i64> {
+    let end = instruction.address + instruction.size();
+    match kind {
+        Kind::Plain => vec![end],
+        Kind::Call { ret, .. } => vec![*ret],
+        Kind::Return | Kind::Halt => vec![],
+        Kind::Jump if instruction.is_never_taken_jump() => vec![end],
+        Kind::Jump => {
+            let mut successors: Vec<i64> = instruction.jump_target().into_iter().collect();
+            if !instruction.is_unconditional_jump() {
+                successors.push(end);
+            }
+            successors
+        }
+    }
+}
+
+fn classify(instruction: &Instruction, stored_return: Option<i64>) -> Kind {
+    match instruction.opcode {
+        OP_HALT => Kind::Halt,
+        OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE if instruction.is_unconditional_jump() => {
+            let target = instruction.params[1];
+            if target == (MODE_REL, 0) {
+                Kind::Return
+            } else if stored_return == Some(instruction.address + instruction.size()) {
+                Kind::Call { target, ret: instruction.address + instruction.size() }
+            } else {
+                Kind::Jump
+            }
+        },
+        OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => Kind::Jump,
+        _ => Kind::Plain
+    }
+}
+
+// Parameter written by the instruction.
+fn write_param(instruction: &Instruction) -> Option<(i64, i64)> {
+    match instruction.opcode {
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => Some(instruction.params[2]),
+        OP_INPUT => Some(instruction.params[0]),
+        _ => None
+    }
+}
+
+fn read_params(instruction: &Instruction) -> &[(i64, i64)] {
+    match instruction.opcode {
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => &instruction.params[..2],
+        OP_INPUT => &[],
+        _ => &instruction.params
+    }
+}
+
+// Returns the source of an instruction that copies a value: "add x 0",
+// "multiply 1 x", etc.
+fn move_source(instruction: &Instruction) -> Option<(i64, i64)> {
+    match (instruction.opcode, &instruction.params[..]) {
+        (OP_ADD, [(MODE_IMM, 0), source, _]) | (OP_ADD, [source, (MODE_IMM, 0), _])
+            | (OP_MULTIPLY, [(MODE_IMM, 1), source, _]) | (OP_MULTIPLY, [source, (MODE_IMM, 1), _]) => Some(*source),
+        _ => None
+    }
+}
+
+fn prologue_size(code: &[i64], address: i64) -> Option<i64> {
+    match decode_instruction(code, address) {
+        Some(Instruction { opcode: OP_REL_BASE_OFFSET, ref params, .. }) => match params[0] {
+            (MODE_IMM, size) if size > 0 => Some(size),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+// Decode the instructions reachable from the entry without following calls.
+fn explore(code: &[i64], entry: i64, analysis: &Analysis) -> Function {
+    let mut instructions = BTreeMap::new();
+    let mut kinds = BTreeMap::new();
+    let mut block_starts = BTreeSet::new();
+    let mut self_modified = BTreeMap::new();
+    let mut pending = vec![entry];
+    block_starts.insert(entry);
+
+    while let Some(start) = pending.pop() {
+        let mut ip = start;
+        let mut stored_return = None;
+        while !instructions.contains_key(&ip) && !self_modified.contains_key(&ip) {
+            let instruction = match decode_instruction(code, ip) {
+                Some(instruction) => instruction,
+                None => match analysis.self_modified.get(&ip) {
+                    Some(&size) => {
+                        // Like the analysis, assume the instruction falls
+                        // through but may jump to an immediate target.
+                        let target = Some(code[ip as usize + 2])
+                            .filter(|target| size == 4 && analysis.jump_targets.contains(target));
+                        if let Some(target) = target {
+                            block_starts.insert(target);
+                            pending.push(target);
+                        }
+                        self_modified.insert(ip, (size, target));
+                        ip += size;
+                        continue;
+                    },
+                    None => break
+                }
+            };
+            if write_param(&instruction) == Some((MODE_REL, 0)) {
+                stored_return = instruction.stored_constant();
+            }
+            let kind = classify(&instruction, stored_return);
+            let successors = successors(&instruction, &kind);
+            let end = ip + instruction.size();
+            let falls_through = kind == Kind::Plain;
+            instructions.insert(ip, instruction);
+            kinds.insert(ip, kind);
+            if falls_through {
+                ip = end;
+                continue;
+            }
+            for successor in successors {
+                if successor != end || !falls_through {
+                    block_starts.insert(successor);
+                }
+                pending.push(successor);
+            }
+            break;
+        }
+    }
+
+    // Fall-through successors of conditional jumps are not real block starts.
+    let jump_ends: BTreeSet<i64> = instructions.iter()
+        .filter(|&(address, instruction)| kinds[address] == Kind::Jump && !instruction.is_unconditional_jump())
+        .map(|(_, instruction)| instruction.address + instruction.size())
+        .collect();
+    let targets: BTreeSet<i64> = instructions.iter()
+        .filter(|&(address, _)| kinds[address] == Kind::Jump)
+        .filter_map(|(_, instruction)| instruction.jump_target())
+        .collect();
+    block_starts.retain(|address| !jump_ends.contains(address) || targets.contains(address) || *address == entry);
+
+    // The program may set up its stack at the start, it isn't a frame.
+    let frame = if entry == 0 { 0 } else { prologue_size(code, entry).unwrap_or(0) };
+    Function {
+        entry,
+        frame,
+        params: 0,
+        returns_value: false,
+        address_taken: false,
+        instructions,
+        kinds,
+        deltas: BTreeMap::new(),
+        block_starts,
+        self_modified
+    }
+}
+
+fn compute_deltas(function: &mut Function) {
+    let mut deltas: BTreeMap<i64, Option<i64>> = BTreeMap::new();
+    let mut pending = vec![(function.entry, Some(0))];
+    while let Some((address, delta)) = pending.pop() {
+        let instruction = match function.instructions.get(&address) {
+            Some(instruction) => instruction,
+            None => continue
+        };
+        let merged = match deltas.get(&address) {
+            None => delta,
+            Some(&old) if old == delta => continue,
+            Some(_) => None
+        };
+        if deltas.get(&address) == Some(&merged) {
+            continue;
+        }
+        deltas.insert(address, merged);
+        let next = match (instruction.opcode, instruction.params.first()) {
+            (OP_REL_BASE_OFFSET, Some(&(MODE_IMM, offset))) => merged.map(|delta| delta + offset),
+            (OP_REL_BASE_OFFSET, _) => None,
+            _ => merged
+        };
+        for successor in successors(instruction, &function.kinds[&address]) {
+            pending.push((successor, next));
+        }
+    }
+    function.deltas = deltas;
+}
+
+// Instructions right before a call that store its arguments, by stack slot
+// (0 for the return address).
+fn call_arguments(function: &Function, call: i64) -> BTreeMap<i64, i64> {
+    let mut arguments = BTreeMap::new();
+    let delta = function.deltas.get(&call).cloned().flatten();
+    let mut address = call;
+    while !function.block_starts.contains(&address) {
+        let (previous, instruction) = match function.instructions.range(..address).next_back() {
+            Some((&previous, instruction)) if previous + instruction.size() == address => (previous, instruction),
+            _ => break
+        };
+        let same_frame = function.deltas.get(&previous).cloned().flatten() == delta;
+        match write_param(instruction) {
+            Some((MODE_REL, slot)) if slot >= 0 && same_frame && function.kinds[&previous] == Kind::Plain => {
+                arguments.entry(slot).or_insert(previous);
+            },
+            _ => break
+        }
+        address = previous;
+    }
+    arguments
+}
+
+// Split the program into functions, starting from address 0 and the extra
+// entry points.
+pub fn find_functions(code: &[i64], entries: &[i64]) -> BTreeMap<i64, Function> {
+    let mut functions: BTreeMap<i64, Function> = BTreeMap::new();
+    let mut pending: Vec<i64> = entries.iter().rev().cloned().chain(Some(0)).collect();
+    let mut pointers = BTreeSet::new();
+    let analysis = analyze(code);
+
+    loop {
+        while let Some(entry) = pending.pop() {
+            if functions.contains_key(&entry) || decode_instruction(code, entry).is_none() {
+                continue;
+            }
+            let function = explore(code, entry, &analysis);
+            for kind in function.kinds.values() {
+                if let Kind::Call { target: (MODE_IMM, target), .. } = *kind {
+                    pending.push(target);
+                }
+            }
+            functions.insert(entry, function);
+        }
+
+        // Constants that point to a function prologue outside the code found
+        // so far are function pointers.
+        let owned: BTreeSet<i64> = functions.values()
+            .flat_map(|function| function.instructions.values())
+            .flat_map(|instruction| instruction.address..instruction.address + instruction.size())
+            .collect();
+        for function in functions.values() {
+            for instruction in function.instructions.values() {
+                if let Some(value) = instruction.stored_constant() {
+                    if value != 0 && prologue_size(code, value).is_some() && (!owned.contains(&value) || functions.contains_key(&value))
+                            && pointers.insert(value) && !functions.contains_key(&value) {
+                        pending.push(value);
+                    }
+                }
+            }
+        }
+        if pending.is_empty() {
+            break;
+        }
+    }
+
+    for function in functions.values_mut() {
+        compute_deltas(function);
+        function.address_taken = pointers.contains(&function.entry);
+    }
+
+    // Count the arguments passed by the callers and check whether they use
+    // the return value.
+    let mut params: BTreeMap<i64, usize> = BTreeMap::new();
+    let mut returns_value = BTreeSet::new();
+    for function in functions.values() {
+        for (&address, kind) in &function.kinds {
+            let (target, ret) = match *kind {
+                Kind::Call { target, ret } => (target, ret),
+                _ => continue
+            };
+            let count = call_arguments(function, address).keys().cloned().max().unwrap_or(0) as usize;
+            let uses_result = function.instructions.get(&ret)
+                .is_some_and(|instruction| read_params(instruction).contains(&(MODE_REL, 1)));
+            let callees: Vec<i64> = match target {
+                (MODE_IMM, target) => vec![target],
+                _ => pointers.iter().cloned().collect()
+            };
+            for callee in callees {
+                let entry = params.entry(callee).or_insert(0);
+                *entry = (*entry).max(count);
+                if uses_result {
+                    returns_value.insert(callee);
+                }
+            }
+        }
+    }
+    for function in functions.values_mut() {
+        function.params = params.get(&function.entry).cloned().unwrap_or(0);
+        if function.frame > 0 {
+            function.params = function.params.min(function.frame as usize - 1);
+        }
+        function.returns_value = returns_value.contains(&function.entry);
+    }
+
+    functions
+}
+
+#[derive(Clone, Debug)]
+enum Cond {
+    Value(String, bool),
+    Compare(String, &'static str, String)
+}
+
+impl Cond {
+    fn negate(&self) -> Cond {
+        match self {
+            Cond::Value(value, negated) => Cond::Value(value.clone(), !negated),
+            Cond::Compare(a, op, b) => {
+                let op = match *op {
+                    "<" => ">=",
+                    ">=" => "<",
+                    "==" => "!=",
+                    _ => "=="
+                };
+                Cond::Compare(a.clone(), op, b.clone())
+            }
+        }
+    }
+
+    fn format(&self) -> String {
+        match self {
+            Cond::Value(value, false) => value.clone(),
+            Cond::Value(value, true) => format!("!{}", value),
+            Cond::Compare(a, op, b) => format!("{} {} {}", a, op, b)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Stmt {
+    Line(i64, String),
+    Comment(i64, String),
+    If(i64, Cond, Vec<Stmt>, Vec<Stmt>),
+    Loop(i64, Vec<Stmt>),
+    While(i64, Cond, Vec<Stmt>),
+    DoWhile(i64, Vec<Stmt>, Cond)
+}
+
+impl Stmt {
+    fn start(&self) -> i64 {
+        match *self {
+            Stmt::Line(start, _) | Stmt::Comment(start, _) | Stmt::If(start, ..) | Stmt::Loop(start, _)
+                | Stmt::While(start, ..) | Stmt::DoWhile(start, ..) => start
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Context {
+    // Jumps to these addresses are written as continue and break.
+    header: Option<i64>,
+    latch: Option<i64>,
+    exit: Option<i64>,
+    // Where execution continues after the region.
+    follow: Option<i64>
+}
+
+struct Decompiler<'a> {
+    functions: &'a BTreeMap<i64, Function>,
+    symbols: &'a BTreeMap<i64, String>,
+    // Addresses of words that are part of decoded instructions.
+    owned: BTreeSet<i64>,
+    // Global cells read by each function and its callees, other than by a
+    // comparison folded into a condition.
+    reads: BTreeMap<i64, BTreeSet<i64>>
+}
+
+#[derive(Clone)]
+struct CallSite {
+    // Instructions storing the arguments by stack slot.
+    arguments: BTreeMap<i64, i64>,
+    // Where the result is copied right after the call.
+    result: Option<(i64, i64)>
+}
+
+struct FunctionDecompiler<'a> {
+    decompiler: &'a Decompiler<'a>,
+    function: &'a Function,
+    // Instructions that are part of another statement.
+    consumed: BTreeSet<i64>,
+    calls: BTreeMap<i64, CallSite>,
+    // Conditional jumps with the comparison folded into them.
+    conditions: BTreeMap<i64, i64>,
+    // Start of statements that include consumed instructions.
+    starts: BTreeMap<i64, i64>,
+    epilogues: BTreeSet<i64>,
+    labels: BTreeSet<i64>,
+    locals: BTreeSet<i64>,
+    globals: BTreeSet<i64>
+}
+
+impl<'a> Decompiler<'a> {
+    fn function_name(&self, entry: i64) -> String {
+        match self.symbols.get(&entry) {
+            Some(name) => name.clone(),
+            None if entry == 0 => "main".to_string(),
+            None => format!("f_{:0>4}", entry)
+        }
+    }
+
+    fn global_name(&self, address: i64) -> String {
+        match self.symbols.get(&address) {
+            Some(name) => name.clone(),
+            None if self.owned.contains(&address) => format!("mem[{}]", address),
+            None => format!("v{}", address)
+        }
+    }
+
+    // Whether the conditional jump at the address tests a comparison stored
+    // by the instruction right before it.
+    fn folded_comparison(function: &Function, address: i64) -> Option<i64> {
+        let instruction = &function.instructions[&address];
+        if function.kinds[&address] != Kind::Jump || instruction.is_unconditional_jump()
+                || instruction.is_never_taken_jump() || function.block_starts.contains(&address) {
+            return None;
+        }
+        let (previous, compare) = function.instructions.range(..address).next_back()?;
+        let cell = instruction.params[0];
+        let is_compare = compare.opcode == OP_LESS_THAN || compare.opcode == OP_EQUALS;
+        if is_compare && previous + compare.size() == address && cell.0 == MODE_POS && compare.params[2] == cell {
+            Some(*previous)
+        } else {
+            None
+        }
+    }
+
+    fn compute_reads(&mut self) {
+        for function in self.functions.values() {
+            let mut reads = BTreeSet::new();
+            for (&address, instruction) in &function.instructions {
+                if Decompiler::folded_comparison(function, address).is_some() {
+                    continue;
+                }
+                for &(mode, value) in read_params(instruction) {
+                    if mode == MODE_POS {
+                        reads.insert(value);
+                    }
+                }
+            }
+            self.reads.insert(function.entry, reads);
+        }
+
+        let pointers: Vec<i64> = self.functions.values()
+            .filter(|function| function.address_taken)
+            .map(|function| function.entry)
+            .collect();
+        loop {
+            let mut changed = false;
+            for function in self.functions.values() {
+                let callees: BTreeSet<i64> = function.kinds.values()
+                    .flat_map(|kind| match *kind {
+                        Kind::Call { target: (MODE_IMM, target), .. } => vec![target],
+                        Kind::Call { .. } => pointers.clone(),
+                        _ => vec![]
+                    })
+                    .collect();
+                let mut reads = self.reads[&function.entry].clone();
+                for callee in callees {
+                    if let Some(callee_reads) = self.reads.get(&callee) {
+                        reads.extend(callee_reads);
+                    }
+                }
+                if reads.len() != self.reads[&function.entry].len() {
+                    self.reads.insert(function.entry, reads);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // Check that the cell is written before it is read on every path from
+    // the instruction. Returning from the function ends a path.
+    fn is_dead_after(&self, function: &Function, address: i64, cell: i64) -> bool {
+        let instruction = &function.instructions[&address];
+        let mut pending = successors(instruction, &function.kinds[&address]);
+        let mut visited = BTreeSet::new();
+        while let Some(address) = pending.pop() {
+            if !visited.insert(address) {
+                continue;
+            }
+            let instruction = match function.instructions.get(&address) {
+                Some(instruction) => instruction,
+                None => return false
+            };
+            if read_params(instruction).contains(&(MODE_POS, cell)) {
+                return false;
+            }
+            if write_param(instruction) == Some((MODE_POS, cell)) {
+                continue;
+            }
+            let kind = &function.kinds[&address];
+            match kind {
+                Kind::Call { target: (MODE_IMM, target), .. }
+                    if self.reads.get(target).is_none_or(|reads| reads.contains(&cell)) => return false,
+                Kind::Call { target: (MODE_IMM, _), .. } => {},
+                Kind::Call { .. } => {
+                    let pointers_read = self.functions.values()
+                        .any(|callee| callee.address_taken && self.reads[&callee.entry].contains(&cell));
+                    if pointers_read {
+                        return false;
+                    }
+                },
+                Kind::Jump if instruction.jump_target().is_none() && !instruction.is_never_taken_jump() => return false,
+                _ => {}
+            }
+            pending.extend(successors(instruction, kind));
+        }
+        true
+    }
+}
+
+impl<'a> FunctionDecompiler<'a> {
+    fn new(decompiler: &'a Decompiler<'a>, function: &'a Function) -> FunctionDecompiler<'a> {
+        let mut this = FunctionDecompiler {
+            decompiler,
+            function,
+            consumed: BTreeSet::new(),
+            calls: BTreeMap::new(),
+            conditions: BTreeMap::new(),
+            starts: BTreeMap::new(),
+            epilogues: BTreeSet::new(),
+            labels: BTreeSet::new(),
+            locals: BTreeSet::new(),
+            globals: BTreeSet::new()
+        };
+        this.prepare();
+        this
+    }
+
+    fn prepare(&mut self) {
+        let function = self.function;
+        if function.frame > 0 {
+            self.consumed.insert(function.entry);
+        }
+
+        for (&address, kind) in &function.kinds {
+            match *kind {
+                Kind::Call { ret, .. } => {
+                    let arguments = call_arguments(function, address);
+                    self.consumed.extend(arguments.values());
+                    if let Some(&first) = arguments.values().min() {
+                        self.starts.insert(address, first);
+                    }
+                    let result = function.instructions.get(&ret)
+                        .filter(|instruction| move_source(instruction) == Some((MODE_REL, 1)))
+                        .and_then(write_param)
+                        .filter(|&(mode, slot)| !(mode == MODE_REL && slot >= 0));
+                    if result.is_some() {
+                        self.consumed.insert(ret);
+                    }
+                    self.calls.insert(address, CallSite { arguments, result });
+                },
+                Kind::Return => {
+                    // rel_base_offset -n right before the return.
+                    if let Some((&previous, instruction)) = function.instructions.range(..address).next_back() {
+                        if previous + instruction.size() == address && instruction.opcode == OP_REL_BASE_OFFSET
+                                && function.frame > 0 && instruction.params[0] == (MODE_IMM, -function.frame) {
+                            self.consumed.insert(previous);
+                            self.epilogues.insert(previous);
+                            self.starts.insert(address, previous);
+                        }
+                    }
+                },
+                Kind::Jump => {
+                    if let Some(compare) = Decompiler::folded_comparison(function, address) {
+                        let cell = function.instructions[&address].params[0].1;
+                        if self.decompiler.is_dead_after(function, address, cell) {
+                            self.consumed.insert(compare);
+                            self.conditions.insert(address, compare);
+                            self.starts.insert(address, compare);
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    fn operand(&mut self, address: i64, (mode, value): (i64, i64)) -> String {
+        match mode {
+            MODE_IMM => match self.decompiler.functions.get(&value) {
+                Some(function) if function.address_taken => self.decompiler.function_name(value),
+                _ => value.to_string()
+            },
+            MODE_POS => {
+                if !self.decompiler.owned.contains(&value) {
+                    self.globals.insert(value);
+                }
+                self.decompiler.global_name(value)
+            },
+            _ => {
+                let delta = self.function.deltas.get(&address).cloned().flatten();
+                match delta {
+                    _ if value >= 0 => format!("out{}", value),
+                    Some(delta) if value + delta == 0 => "ret_addr".to_string(),
+                    Some(delta) if value + delta > 0 => {
+                        let slot = value + delta;
+                        if slot as usize <= self.function.params {
+                            format!("a{}", slot)
+                        } else {
+                            self.locals.insert(slot);
+                            format!("l{}", slot)
+                        }
+                    },
+                    _ => format!("rb[{}]", value)
+                }
+            }
+        }
+    }
+
+    // Value computed by an arithmetic, comparison or input instruction.
+    fn expression(&mut self, instruction: &Instruction) -> String {
+        let address = instruction.address;
+        let params = &instruction.params;
+        if instruction.opcode == OP_INPUT {
+            return "input()".to_string();
+        }
+        if let Some(source) = move_source(instruction) {
+            return self.operand(address, source);
+        }
+        let (a, b) = (self.operand(address, params[0]), self.operand(address, params[1]));
+        match (instruction.opcode, params[0], params[1]) {
+            (OP_ADD, _, (MODE_IMM, value)) if value < 0 => format!("{} - {}", a, -value),
+            (OP_ADD, (MODE_IMM, value), _) if value < 0 => format!("{} - {}", b, -value),
+            (OP_ADD, ..) => format!("{} + {}", a, b),
+            (OP_MULTIPLY, (MODE_IMM, 0), _) | (OP_MULTIPLY, _, (MODE_IMM, 0)) => "0".to_string(),
+            (OP_MULTIPLY, (MODE_IMM, -1), _) => format!("-{}", b),
+            (OP_MULTIPLY, _, (MODE_IMM, -1)) => format!("-{}", a),
+            (OP_MULTIPLY, ..) => format!("{} * {}", a, b),
+            (OP_LESS_THAN, ..) => format!("{} < {}", a, b),
+            _ => format!("{} == {}", a, b)
+        }
+    }
+
+    fn condition(&mut self, address: i64) -> Cond {
+        let instruction = &self.function.instructions[&address];
+        let cond = match self.conditions.get(&address) {
+            Some(compare) => {
+                let compare = &self.function.instructions[compare];
+                let (a, b) = (self.operand(compare.address, compare.params[0]),
+                    self.operand(compare.address, compare.params[1]));
+                Cond::Compare(a, if compare.opcode == OP_LESS_THAN { "<" } else { "==" }, b)
+            },
+            None => Cond::Value(self.operand(address, instruction.params[0]), false)
+        };
+        if instruction.opcode == OP_JUMP_IF_TRUE { cond } else { cond.negate() }
+    }
+
+    fn return_statement(&mut self) -> String {
+        if self.function.returns_value {
+            let value = self.operand_slot(1);
+            format!("return {}", value)
+        } else {
+            "return".to_string()
+        }
+    }
+
+    fn operand_slot(&self, slot: i64) -> String {
+        if slot as usize <= self.function.params { format!("a{}", slot) } else { format!("l{}", slot) }
+    }
+
+    fn call_statement(&mut self, address: i64) -> String {
+        let (target, ret) = match self.function.kinds[&address] {
+            Kind::Call { target, ret } => (target, ret),
+            _ => unreachable!()
+        };
+        let CallSite { arguments, result } = self.calls[&address].clone();
+        let name = match target {
+            (MODE_IMM, target) => self.decompiler.function_name(target),
+            target => format!("(*{})", self.operand(address, target))
+        };
+        let count = arguments.keys().cloned().max().unwrap_or(0);
+        let values: Vec<String> = (1..=count)
+            .map(|slot| match arguments.get(&slot) {
+                Some(argument) => {
+                    let instruction = &self.function.instructions[argument];
+                    self.expression(instruction)
+                },
+                None => "?".to_string()
+            })
+            .collect();
+        let call = format!("{}({})", name, values.join(", "));
+
+        let uses_result = match target {
+            (MODE_IMM, target) => self.decompiler.functions.get(&target).is_some_and(|f| f.returns_value),
+            _ => self.function.instructions.get(&ret)
+                .is_some_and(|instruction| read_params(instruction).contains(&(MODE_REL, 1)))
+        };
+        match result {
+            Some(destination) => format!("{} = {}", self.operand(ret, destination), call),
+            None if uses_result => format!("out1 = {}", call),
+            None => call
+        }
+    }
+
+    fn statement(&mut self, instruction: &Instruction) -> String {
+        let address = instruction.address;
+        match instruction.opcode {
+            OP_HALT => "halt()".to_string(),
+            OP_OUTPUT => format!("output({})", self.operand(address, instruction.params[0])),
+            OP_REL_BASE_OFFSET => format!("rel_base += {}", self.operand(address, instruction.params[0])),
+            _ => {
+                let destination = self.operand(address, write_param(instruction).unwrap());
+                let value = self.expression(instruction);
+                format!("{} = {}", destination, value)
+            }
+        }
+    }
+
+    fn next_address(&self, address: i64) -> Option<i64> {
+        self.function.instructions.range(address + 1..).next().map(|(&address, _)| address)
+    }
+
+    // Latest jump in the region back to the address.
+    fn latch(&self, header: i64, end: i64) -> Option<i64> {
+        self.function.instructions.range(header..end).rev()
+            .find(|&(address, instruction)| {
+                self.function.kinds[address] == Kind::Jump && !instruction.is_never_taken_jump()
+                    && instruction.jump_target() == Some(header)
+            })
+            .map(|(&address, _)| address)
+    }
+
+    // A jump written as a statement, or None if it goes to the next statement.
+    fn jump(&mut self, address: i64, target: i64, context: &Context, last: bool) -> Option<String> {
+        if Some(target) == context.header || Some(target) == context.latch {
+            Some("continue".to_string())
+        } else if Some(target) == context.exit {
+            Some("break".to_string())
+        } else if self.epilogues.contains(&target) {
+            Some(self.return_statement())
+        } else if Some(target) == self.next_address(address) || (last && Some(target) == context.follow) {
+            None
+        } else if !self.function.instructions.contains_key(&target) {
+            // Not the start of a decoded instruction.
+            Some(format!("goto *{}", target))
+        } else {
+            self.labels.insert(target);
+            Some(format!("goto L_{:0>4}", target))
+        }
+    }
+
+    // Comments for the self-modified instructions between the addresses.
+    fn self_modified(&mut self, start: i64, end: i64) -> Vec<Stmt> {
+        let mut stmts = Vec::new();
+        for (&address, &(size, target)) in self.function.self_modified.range(start..end) {
+            let mut comment = format!("self-modified instruction at {}..{}", address, address + size - 1);
+            if let Some(target) = target {
+                self.labels.insert(target);
+                comment.push_str(&format!(", may jump to L_{:0>4}", target));
+            }
+            stmts.push(Stmt::Comment(address, comment));
+        }
+        stmts
+    }
+
+    fn region(&mut self, start: i64, end: i64, context: Context) -> Vec<Stmt> {
+        let mut stmts = Vec::new();
+        let mut next = start;
+
+        while let Some((&address, instruction)) = self.function.instructions.range(next..end).next() {
+            let function = self.function;
+            stmts.extend(self.self_modified(next, address));
+            let stmt_start = self.starts.get(&address).cloned().unwrap_or(address);
+            next = address + instruction.size();
+
+            if context.header != Some(address) {
+                if let Some(latch) = self.latch(address, end) {
+                    let latch_instruction = &function.instructions[&latch];
+                    let exit = latch + latch_instruction.size();
+                    let inner = Context {
+                        header: Some(address),
+                        latch: if latch_instruction.is_unconditional_jump() { None } else { Some(latch) },
+                        exit: Some(exit),
+                        follow: Some(address)
+                    };
+                    let body = self.region(address, latch, inner);
+                    if latch_instruction.is_unconditional_jump() {
+                        stmts.push(Stmt::Loop(stmt_start, body));
+                    } else {
+                        let cond = self.condition(latch);
+                        stmts.push(Stmt::DoWhile(stmt_start, body, cond));
+                    }
+                    next = exit;
+                    continue;
+                }
+            }
+
+            if self.consumed.contains(&address) {
+                continue;
+            }
+            let last = self.next_address(address).is_none_or(|next| next >= end);
+
+            match function.kinds[&address] {
+                Kind::Halt => stmts.push(Stmt::Line(stmt_start, "halt()".to_string())),
+                Kind::Return => {
+                    let text = self.return_statement();
+                    stmts.push(Stmt::Line(stmt_start, text));
+                },
+                Kind::Call { .. } => {
+                    let text = self.call_statement(address);
+                    stmts.push(Stmt::Line(stmt_start, text));
+                },
+                Kind::Plain => {
+                    let text = self.statement(instruction);
+                    stmts.push(Stmt::Line(stmt_start, text));
+                },
+                Kind::Jump if instruction.is_never_taken_jump() => {},
+                Kind::Jump if instruction.is_unconditional_jump() => {
+                    let text = match instruction.jump_target() {
+                        Some(target) => self.jump(address, target, &context, last),
+                        None => Some(format!("goto *{}", self.operand(address, instruction.params[1])))
+                    };
+                    if let Some(text) = text {
+                        stmts.push(Stmt::Line(stmt_start, text));
+                    }
+                },
+                Kind::Jump => {
+                    let cond = self.condition(address);
+                    let target = match instruction.jump_target() {
+                        Some(target) => target,
+                        None => {
+                            let text = format!("goto *{}", self.operand(address, instruction.params[1]));
+                            stmts.push(Stmt::If(stmt_start, cond, vec![Stmt::Line(address, text)], vec![]));
+                            continue;
+                        }
+                    };
+                    let is_special = Some(target) == context.header || Some(target) == context.latch
+                        || Some(target) == context.exit || self.epilogues.contains(&target);
+                    // Jumps out of the decoded code, e.g. to a crash address,
+                    // are kept as gotos.
+                    let decoded = |target: i64| function.instructions.contains_key(&target)
+                        || function.self_modified.contains_key(&target) || target == end;
+                    if !is_special && decoded(target) && target > address && target <= end {
+                        let then_start = next;
+                        let else_jump = function.instructions.range(then_start..target).next_back()
+                            .filter(|&(&jump, instruction)| {
+                                function.kinds[&jump] == Kind::Jump && instruction.is_unconditional_jump()
+                                    && instruction.jump_target().is_some_and(|e| decoded(e) && e > target && e <= end)
+                            })
+                            .map(|(&jump, instruction)| (jump, instruction.jump_target().unwrap()))
+                            .filter(|_| {
+                                // The else branch must only be entered by this jump.
+                                !function.instructions.range(then_start..target).any(|(&other, instruction)| {
+                                    function.kinds[&other] == Kind::Jump && instruction.jump_target() == Some(target)
+                                })
+                            });
+                        match else_jump {
+                            Some((jump, else_end)) => {
+                                let inner = Context { follow: Some(else_end), ..context };
+                                let then_stmts = self.region(then_start, jump, inner);
+                                let else_stmts = self.region(target, else_end, inner);
+                                if then_stmts.is_empty() {
+                                    stmts.push(Stmt::If(stmt_start, cond, else_stmts, vec![]));
+                                } else {
+                                    stmts.push(Stmt::If(stmt_start, cond.negate(), then_stmts, else_stmts));
+                                }
+                                next = else_end;
+                            },
+                            None => {
+                                let inner = Context { follow: Some(target), ..context };
+                                let then_stmts = self.region(then_start, target, inner);
+                                stmts.push(Stmt::If(stmt_start, cond.negate(), then_stmts, vec![]));
+                                next = target;
+                            }
+                        }
+                    } else if let Some(text) = self.jump(address, target, &context, false) {
+                        stmts.push(Stmt::If(stmt_start, cond, vec![Stmt::Line(address, text)], vec![]));
+                    }
+                }
+            }
+        }
+
+        stmts.extend(self.self_modified(next, end));
+        stmts.into_iter().map(simplify_loop).collect()
+    }
+}
+
+// Turn "loop { if (c) break; ... }" into "while (!c) { ... }".
+fn simplify_loop(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Loop(start, mut body) => {
+            let is_break = |stmts: &Vec<Stmt>| matches!(&stmts[..], [Stmt::Line(_, text)] if text == "break");
+            let cond = match body.first() {
+                Some(Stmt::If(_, cond, then_stmts, else_stmts)) if is_break(then_stmts) && else_stmts.is_empty() => {
+                    Some(cond.negate())
+                },
+                _ => None
+            };
+            match cond {
+                Some(cond) => {
+                    body.remove(0);
+                    Stmt::While(start, cond, body)
+                },
+                None => Stmt::Loop(start, body)
+            }
+        },
+        stmt => stmt
+    }
+}
+
+fn render(stmts: &[Stmt], depth: usize, labels: &mut Vec<i64>, text: &mut String) {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        while labels.first().is_some_and(|&label| label <= stmt.start()) {
+            writeln!(text, "{}L_{:0>4}:", "    ".repeat(depth.saturating_sub(1)), labels.remove(0)).unwrap();
+        }
+        match stmt {
+            Stmt::Line(_, line) => writeln!(text, "{}{};", indent, line).unwrap(),
+            Stmt::Comment(_, comment) => writeln!(text, "{}/* {} */", indent, comment).unwrap(),
+            Stmt::If(_, cond, then_stmts, else_stmts) => {
+                writeln!(text, "{}if ({}) {{", indent, cond.format()).unwrap();
+                render(then_stmts, depth + 1, labels, text);
+                if !else_stmts.is_empty() {
+                    writeln!(text, "{}}} else {{", indent).unwrap();
+                    render(else_stmts, depth + 1, labels, text);
+                }
+                writeln!(text, "{}}}", indent).unwrap();
+            },
+            Stmt::Loop(_, body) => {
+                writeln!(text, "{}while (1) {{", indent).unwrap();
+                render(body, depth + 1, labels, text);
+                writeln!(text, "{}}}", indent).unwrap();
+            },
+            Stmt::While(_, cond, body) => {
+                writeln!(text, "{}while ({}) {{", indent, cond.format()).unwrap();
+                render(body, depth + 1, labels, text);
+                writeln!(text, "{}}}", indent).unwrap();
+            },
+            Stmt::DoWhile(_, body, cond) => {
+                writeln!(text, "{}do {{", indent).unwrap();
+                render(body, depth + 1, labels, text);
+                writeln!(text, "{}}} while ({});", indent, cond.format()).unwrap();
+            }
+        }
+    }
+}
+
+// Ranges of words outside the decoded code where several instructions in a
+// row decode. These are probably code that is only reached by computed jumps
+// the decompiler can't follow.
+fn undecoded_ranges(code: &[i64], owned: &BTreeSet<i64>) -> Vec<(i64, i64)> {
+    const MIN_INSTRUCTIONS: usize = 3;
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while start < code.len() as i64 {
+        if owned.contains(&start) {
+            start += 1;
+            continue;
+        }
+        let end = (start..code.len() as i64).find(|address| owned.contains(address)).unwrap_or(code.len() as i64);
+        let (mut ip, mut run, mut longest) = (start, 0, 0);
+        while ip < end {
+            match decode_instruction(code, ip) {
+                Some(instruction) if ip + instruction.size() <= end => {
+                    run += 1;
+                    longest = longest.max(run);
+                    ip += instruction.size();
+                },
+                _ => {
+                    run = 0;
+                    ip += 1;
+                }
+            }
+        }
+        if longest >= MIN_INSTRUCTIONS {
+            ranges.push((start, end - 1));
+        }
+        start = end;
+    }
+    ranges
+}
+
+// Decompile the program. Symbols (see disasm::parse_symbols()) name functions
+// by their entry address and global variables; entries are additional
+// function entry points, e.g. for functions only called through pointers
+// that are not detected.
+pub fn decompile(code: &[i64], symbols: &BTreeMap<i64, String>, entries: &[i64]) -> String {
+    let functions = find_functions(code, entries);
+    let owned: BTreeSet<i64> = functions.values()
+        .flat_map(|function| function.instructions.values())
+        .flat_map(|instruction| instruction.address..instruction.address + instruction.size())
+        .chain(functions.values()
+            .flat_map(|function| &function.self_modified)
+            .flat_map(|(&address, &(size, _))| address..address + size))
+        .collect();
+    let mut decompiler = Decompiler { functions: &functions, symbols, owned, reads: BTreeMap::new() };
+    decompiler.compute_reads();
+
+    let mut globals = BTreeSet::new();
+    let mut bodies = Vec::new();
+    for function in functions.values() {
+        let mut fd = FunctionDecompiler::new(&decompiler, function);
+        let mut stmts = fd.region(function.entry, i64::MAX, Context::default());
+        if function.instructions.range(..function.entry).next().is_some() {
+            // Code placed before the entry point, only reached by jumps.
+            stmts.extend(fd.region(i64::MIN, function.entry, Context::default()));
+        }
+
+        let params: Vec<String> = (1..=function.params).map(|slot| format!("a{}", slot)).collect();
+        let mut text = format!("func {}({}) {{\n", decompiler.function_name(function.entry), params.join(", "));
+        if !fd.locals.is_empty() {
+            let locals: Vec<String> = fd.locals.iter().map(|slot| format!("l{}", slot)).collect();
+            writeln!(text, "    var {};", locals.join(", ")).unwrap();
+        }
+        let mut labels: Vec<i64> = fd.labels.iter().cloned().collect();
+        render(&stmts, 1, &mut labels, &mut text);
+        for label in labels {
+            writeln!(text, "L_{:0>4}:", label).unwrap();
+        }
+        text.push_str("}\n");
+        globals.extend(fd.globals);
+        bodies.push(text);
+    }
+
+    let mut text = String::new();
+    if !globals.is_empty() {
+        let names: Vec<String> = globals.iter().map(|&address| decompiler.global_name(address)).collect();
+        writeln!(text, "var {};\n", names.join(", ")).unwrap();
+    }
+    text.push_str(&bodies.join("\n"));
+    for (start, end) in undecoded_ranges(code, &decompiler.owned) {
+        writeln!(text, "\n/* undecoded: addresses {}..{} */", start, end).unwrap();
+    }
+    text
+}
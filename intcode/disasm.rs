@@ -0,0 +1,535 @@
+// Convert Intcode programs into a human readable assembly-like language.
+//
+// Each line of the listing starts with the address of the instruction followed
+// by its mnemonic and parameters: [x] for position mode, x for immediate mode
+// and [$x] for relative mode. Words that are not decoded as instructions are
+// printed as .data directives, so the listing can be assembled back into the
+// original program with asm::assemble().
+//
+// disassemble() does a linear sweep and decodes every word that looks like an
+// instruction. disassemble_recursive() only decodes code reachable from
+// address 0 by following the control flow (see analyze()), which separates
+// code from the data cells embedded in puzzle programs.
+//
+// disassemble_symbolic() additionally gives names to jump targets (L_0310) and
+// to data cells accessed in position mode (v8), and prints instructions that
+// store a result as assignments: "[v10] = add [v10] 1". Names can be
+// overridden with a symbol file (see parse_symbols()).
+//
+// The *_with() variants also decode the custom opcodes of an OpcodeSet, and
+// the *_from() variants follow the control flow from other entry points than
+// address 0 too.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use super::*;
+use super::opcodes::OpcodeSet;
+
+pub const OPCODE_NAMES: [(i64, &str); 10] = [
+    (OP_HALT, "halt"),
+    (OP_ADD, "add"),
+    (OP_MULTIPLY, "multiply"),
+    (OP_INPUT, "input"),
+    (OP_OUTPUT, "output"),
+    (OP_JUMP_IF_TRUE, "jump_if_true"),
+    (OP_JUMP_IF_FALSE, "jump_if_false"),
+    (OP_LESS_THAN, "less_than"),
+    (OP_EQUALS, "equals"),
+    (OP_REL_BASE_OFFSET, "rel_base_offset")
+];
+
+pub fn opcode_name(opcode: i64) -> Option<&'static str> {
+    OPCODE_NAMES.iter().find(|&&(op, _)| op == opcode).map(|&(_, name)| name)
+}
+
+pub fn opcode_by_name(name: &str) -> Option<i64> {
+    OPCODE_NAMES.iter().find(|&&(_, n)| n == name).map(|&(op, _)| op)
+}
+
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub address: i64,
+    pub instr: i64,
+    pub opcode: i64,
+    pub params: Vec<(i64, i64)>
+}
+
+impl Instruction {
+    pub fn size(&self) -> i64 {
+        1 + self.params.len() as i64
+    }
+
+    // Returns the target of a jump instruction if it is in immediate mode.
+    pub fn jump_target(&self) -> Option<i64> {
+        match (self.opcode, self.params.get(1)) {
+            (OP_JUMP_IF_TRUE, Some(&(MODE_IMM, target)))
+                | (OP_JUMP_IF_FALSE, Some(&(MODE_IMM, target))) => Some(target),
+            _ => None
+        }
+    }
+
+    // Returns true if this is a jump whose condition is constant and always
+    // taken, e.g. "jump_if_true 1 x".
+    pub fn is_unconditional_jump(&self) -> bool {
+        match (self.opcode, &self.params[..]) {
+            (OP_JUMP_IF_TRUE, [(MODE_IMM, value), _]) => *value != 0,
+            (OP_JUMP_IF_FALSE, [(MODE_IMM, value), _]) => *value == 0,
+            _ => false
+        }
+    }
+
+    // Returns true if this is a jump whose condition is constant and never
+    // taken, e.g. "jump_if_false 1 x".
+    pub fn is_never_taken_jump(&self) -> bool {
+        match (self.opcode, &self.params[..]) {
+            (OP_JUMP_IF_TRUE, [(MODE_IMM, value), _]) => *value == 0,
+            (OP_JUMP_IF_FALSE, [(MODE_IMM, value), _]) => *value != 0,
+            _ => false
+        }
+    }
+
+    // Returns the address written by this instruction if it can be determined
+    // statically, i.e. the output parameter is in position mode.
+    pub fn write_address(&self) -> Option<i64> {
+        let index = match self.opcode {
+            OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => 2,
+            OP_INPUT => 0,
+            _ => return None
+        };
+        match self.params[index] {
+            (MODE_POS, address) => Some(address),
+            _ => None
+        }
+    }
+
+    // Returns the value stored by this instruction if it is a constant, e.g.
+    // "add 0 x [y]" or "multiply x 1 [y]".
+    pub fn stored_constant(&self) -> Option<i64> {
+        match (self.opcode, &self.params[..]) {
+            (OP_ADD, [(MODE_IMM, 0), (MODE_IMM, value), _])
+                | (OP_ADD, [(MODE_IMM, value), (MODE_IMM, 0), _])
+                | (OP_MULTIPLY, [(MODE_IMM, 1), (MODE_IMM, value), _])
+                | (OP_MULTIPLY, [(MODE_IMM, value), (MODE_IMM, 1), _]) => Some(*value),
+            _ => None
+        }
+    }
+}
+
+// Decode the instruction at the specified address. Returns None if the word
+// is not a valid instruction: the opcode is unknown, a parameter mode is
+// invalid, the word has extra digits or the parameters run past the end of
+// the program.
+pub fn decode_instruction(code: &[i64], address: i64) -> Option<Instruction> {
+    decode_instruction_with(code, address, &OpcodeSet::new())
+}
+
+pub fn decode_instruction_with(code: &[i64], address: i64, opcodes: &OpcodeSet) -> Option<Instruction> {
+    if address < 0 || address >= code.len() as i64 {
+        return None;
+    }
+
+    let instr = code[address as usize];
+    let opcode = read_opcode(instr);
+    let count = opcodes.params(opcode)?.len();
+    if instr < 0 || instr / 10_i64.pow(count as u32 + 2) != 0 {
+        return None;
+    }
+    if address + count as i64 >= code.len() as i64 {
+        return None;
+    }
+
+    let mut params = Vec::with_capacity(count);
+    for i in 0..count {
+        let mode = read_param_mode(instr, i as u32);
+        if mode != MODE_POS && mode != MODE_IMM && mode != MODE_REL {
+            return None;
+        }
+        params.push((mode, code[address as usize + 1 + i]));
+    }
+
+    Some(Instruction { address, instr, opcode, params })
+}
+
+pub fn format_param(mode: i64, value: i64) -> String {
+    match mode {
+        MODE_POS => format!("[{}]", value),
+        MODE_REL => format!("[${}]", value),
+        _ => format!("{}", value)
+    }
+}
+
+pub fn format_instruction(instruction: &Instruction) -> String {
+    format_instruction_with(instruction, &OpcodeSet::new())
+}
+
+pub fn format_instruction_with(instruction: &Instruction, opcodes: &OpcodeSet) -> String {
+    let mut line = format!("{:0>4}: {: >16}   ", instruction.address,
+        opcodes.name(instruction.opcode).unwrap());
+    for &(mode, value) in &instruction.params {
+        line.push(' ');
+        line.push_str(&format_param(mode, value));
+    }
+    line
+}
+
+// Result of the control flow analysis of a program.
+pub struct Analysis {
+    // Reachable instructions by address.
+    pub instructions: BTreeMap<i64, Instruction>,
+    // Addresses that are targets of jumps (including return addresses).
+    pub jump_targets: BTreeSet<i64>,
+    // Instructions that write into code, mapped to the address of the
+    // instruction whose word they overwrite. This includes writes to words
+    // reached by the control flow that only become valid instructions at run
+    // time.
+    pub code_writes: BTreeMap<i64, i64>,
+    // Words that the program writes to before executing them and that don't
+    // decode as they are, with the size of the instruction they become. The
+    // size is guessed from how well the code that follows decodes.
+    pub self_modified: BTreeMap<i64, i64>
+}
+
+// Number of instructions that decode one after another from the address, up
+// to a limit. Reaching a halt counts as reaching the limit.
+fn decoded_run(code: &[i64], mut address: i64, opcodes: &OpcodeSet) -> usize {
+    const LIMIT: usize = 16;
+    for count in 0..LIMIT {
+        match decode_instruction_with(code, address, opcodes) {
+            Some(instruction) if instruction.opcode == OP_HALT => return LIMIT,
+            Some(instruction) => address += instruction.size(),
+            None => return count
+        }
+    }
+    LIMIT
+}
+
+// Guess the size of the instruction that a self-modified word becomes. Its
+// parameter modes are usually left as they are, which gives the minimum
+// number of parameters.
+fn self_modified_size(code: &[i64], address: i64, opcodes: &OpcodeSet) -> i64 {
+    let mut modes = code[address as usize] / 100;
+    let mut min_params = 0;
+    while modes != 0 {
+        modes /= 10;
+        min_params += 1;
+    }
+    (min_params.min(3) + 1..=4)
+        .max_by_key(|&size| (decoded_run(code, address + size, opcodes), -size))
+        .unwrap()
+}
+
+// Find instructions reachable from address 0. Jumps with an immediate target
+// are followed, jumps with a computed target (e.g. returns via [$0]) end the
+// path. Since a call is done by storing the return address and jumping to the
+// function, a constant stored before an unconditional jump that points right
+// after the jump is also followed as a return address. A word that doesn't
+// decode but is written to by an instruction found earlier doesn't end the
+// path: the program modifies it before it is executed (see self_modified).
+pub fn analyze(code: &[i64]) -> Analysis {
+    analyze_with(code, &OpcodeSet::new())
+}
+
+pub fn analyze_with(code: &[i64], opcodes: &OpcodeSet) -> Analysis {
+    analyze_from(code, opcodes, &[0])
+}
+
+// Like analyze_with() but starting from the entries, in order, e.g. address 0
+// and the addresses executed by a run of the program.
+pub fn analyze_from(code: &[i64], opcodes: &OpcodeSet, entries: &[i64]) -> Analysis {
+    let mut instructions: BTreeMap<i64, Instruction> = BTreeMap::new();
+    let mut jump_targets = BTreeSet::new();
+    let mut owner: Vec<Option<i64>> = vec![None; code.len()];
+    let mut undecoded = BTreeSet::new();
+    let mut self_modified = BTreeMap::new();
+    // Constant addresses written to by the instructions found so far.
+    let mut written = BTreeSet::new();
+    let mut pending: Vec<i64> = entries.iter().rev().cloned().collect();
+
+    while let Some(start) = pending.pop() {
+        let mut ip = start;
+        let mut stored_constants = Vec::new();
+
+        while ip >= 0 && ip < code.len() as i64 && owner[ip as usize].is_none() {
+            let instruction = match decode_instruction_with(code, ip, opcodes) {
+                Some(instruction) => instruction,
+                None if written.contains(&ip) => {
+                    // Carry on after the instruction the word becomes at run
+                    // time, assuming that it doesn't jump.
+                    let end = (ip + self_modified_size(code, ip, opcodes)).min(code.len() as i64);
+                    if (ip..end).any(|address| owner[address as usize].is_some()) {
+                        break;
+                    }
+                    for address in ip..end {
+                        owner[address as usize] = Some(ip);
+                    }
+                    self_modified.insert(ip, end - ip);
+                    // The word may also become a jump, e.g. day 5 turns it
+                    // into an add or a jump depending on the input.
+                    if end - ip == 4 && read_param_mode(code[ip as usize], 1) == MODE_IMM {
+                        let target = code[ip as usize + 2];
+                        if decode_instruction_with(code, target, opcodes).is_some() {
+                            jump_targets.insert(target);
+                            pending.push(target);
+                        }
+                    }
+                    ip = end;
+                    continue;
+                },
+                None => {
+                    undecoded.insert(ip);
+                    break;
+                }
+            };
+            let end = ip + instruction.size();
+            if (ip..end).any(|address| owner[address as usize].is_some()) {
+                break;
+            }
+            for address in ip..end {
+                owner[address as usize] = Some(ip);
+            }
+
+            if let Some(value) = instruction.stored_constant() {
+                stored_constants.push(value);
+            }
+            if let Some(target) = instruction.write_address() {
+                written.insert(target);
+            }
+
+            let mut falls_through = instruction.opcode != OP_HALT;
+            if instruction.opcode == OP_JUMP_IF_TRUE || instruction.opcode == OP_JUMP_IF_FALSE {
+                if !instruction.is_never_taken_jump() {
+                    if let Some(target) = instruction.jump_target() {
+                        jump_targets.insert(target);
+                        pending.push(target);
+                    }
+                }
+                if instruction.is_unconditional_jump() {
+                    falls_through = false;
+                    if stored_constants.contains(&end) {
+                        jump_targets.insert(end);
+                        pending.push(end);
+                    }
+                }
+            }
+
+            instructions.insert(ip, instruction);
+            if !falls_through {
+                break;
+            }
+            ip = end;
+        }
+    }
+
+    let mut code_writes = BTreeMap::new();
+    for (&address, instruction) in &instructions {
+        if let Some(target) = instruction.write_address() {
+            if target >= 0 && target < code.len() as i64 {
+                if let Some(target_owner) = owner[target as usize] {
+                    code_writes.insert(address, target_owner);
+                } else if undecoded.contains(&target) {
+                    code_writes.insert(address, target);
+                }
+            }
+        }
+    }
+
+    Analysis { instructions, jump_targets, code_writes, self_modified }
+}
+
+pub fn format_data(address: i64, value: i64) -> String {
+    format!("{:0>4}: {: >16}    {}", address, ".data", value)
+}
+
+pub fn disassemble(code: &[i64]) -> String {
+    disassemble_with(code, &OpcodeSet::new())
+}
+
+pub fn disassemble_with(code: &[i64], opcodes: &OpcodeSet) -> String {
+    let mut listing = String::new();
+    let mut ip: i64 = 0;
+
+    while ip < code.len() as i64 {
+        match decode_instruction_with(code, ip, opcodes) {
+            Some(instruction) => {
+                writeln!(listing, "{}", format_instruction_with(&instruction, opcodes)).unwrap();
+                ip += instruction.size();
+            },
+            None => {
+                writeln!(listing, "{}", format_data(ip, code[ip as usize])).unwrap();
+                ip += 1;
+            }
+        }
+    }
+
+    listing
+}
+
+pub fn disassemble_recursive(code: &[i64]) -> String {
+    disassemble_recursive_with(code, &OpcodeSet::new())
+}
+
+pub fn disassemble_recursive_with(code: &[i64], opcodes: &OpcodeSet) -> String {
+    disassemble_recursive_from(code, opcodes, &[0])
+}
+
+pub fn disassemble_recursive_from(code: &[i64], opcodes: &OpcodeSet, entries: &[i64]) -> String {
+    let analysis = analyze_from(code, opcodes, entries);
+    let mut listing = String::new();
+    let mut ip: i64 = 0;
+
+    while ip < code.len() as i64 {
+        match analysis.instructions.get(&ip) {
+            Some(instruction) => {
+                write!(listing, "{}", format_instruction_with(instruction, opcodes)).unwrap();
+                if let Some(target) = analysis.code_writes.get(&ip) {
+                    write!(listing, "   ; writes into code at {:0>4}", target).unwrap();
+                }
+                writeln!(listing).unwrap();
+                ip += instruction.size();
+            },
+            None => {
+                write!(listing, "{}", format_data(ip, code[ip as usize])).unwrap();
+                if analysis.self_modified.contains_key(&ip) {
+                    write!(listing, "   ; modified at run time").unwrap();
+                }
+                writeln!(listing).unwrap();
+                ip += 1;
+            }
+        }
+    }
+
+    listing
+}
+
+// Parse a symbol file. Each line contains an address and a name separated by
+// whitespace; everything after a ';' is a comment:
+//
+//     8    input_value     ; the value read by the first instruction
+//     310  main_loop
+pub fn parse_symbols(text: &str) -> Result<BTreeMap<i64, String>, String> {
+    let mut symbols = BTreeMap::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = match line.find(';') {
+            Some(comment) => &line[..comment],
+            None => line
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            [] => {},
+            [address, name] => {
+                let address = address.parse::<i64>()
+                    .map_err(|_| format!("line {}: Invalid address '{}'", index + 1, address))?;
+                symbols.insert(address, name.to_string());
+            },
+            _ => return Err(format!("line {}: Expected an address and a name", index + 1))
+        }
+    }
+
+    Ok(symbols)
+}
+
+fn is_code(analysis: &Analysis, address: i64) -> bool {
+    match analysis.instructions.range(..=address).next_back() {
+        Some((&start, instruction)) => address < start + instruction.size(),
+        None => false
+    }
+}
+
+// Generate names for jump targets and variables, then apply the overrides.
+// Only addresses that start a line of the listing (instructions and data
+// words) get names, so that every name can be defined by a label.
+fn make_names(code: &[i64], analysis: &Analysis, symbols: &BTreeMap<i64, String>) -> BTreeMap<i64, String> {
+    let can_name = |address: i64| {
+        address >= 0 && address < code.len() as i64
+            && (analysis.instructions.contains_key(&address) || !is_code(analysis, address))
+    };
+    let mut names = BTreeMap::new();
+
+    for instruction in analysis.instructions.values() {
+        for &(mode, address) in &instruction.params {
+            if mode == MODE_POS && can_name(address) && !is_code(analysis, address) {
+                names.insert(address, format!("v{}", address));
+            }
+        }
+    }
+    for &target in &analysis.jump_targets {
+        if can_name(target) {
+            names.insert(target, format!("L_{:0>4}", target));
+        }
+    }
+    for (&address, name) in symbols {
+        if can_name(address) {
+            names.insert(address, name.clone());
+        }
+    }
+
+    names
+}
+
+fn format_symbolic_instruction(instruction: &Instruction, names: &BTreeMap<i64, String>,
+        opcodes: &OpcodeSet) -> String {
+    let jump_target = instruction.jump_target();
+    let params: Vec<String> = instruction.params.iter().enumerate()
+        .map(|(i, &(mode, value))| {
+            let name = names.get(&value);
+            match (mode, name) {
+                (MODE_POS, Some(name)) => format!("[{}]", name),
+                (MODE_IMM, Some(name)) if i == 1 && jump_target == Some(value) => name.clone(),
+                _ => format_param(mode, value)
+            }
+        })
+        .collect();
+    let name = opcodes.name(instruction.opcode).unwrap();
+
+    match instruction.opcode {
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => {
+            format!("{} = {} {}", params[2], name, params[..2].join(" "))
+        },
+        _ if params.is_empty() => name.to_string(),
+        _ => format!("{} {}", name, params.join(" "))
+    }
+}
+
+pub fn disassemble_symbolic(code: &[i64], symbols: &BTreeMap<i64, String>) -> String {
+    disassemble_symbolic_with(code, symbols, &OpcodeSet::new())
+}
+
+pub fn disassemble_symbolic_with(code: &[i64], symbols: &BTreeMap<i64, String>, opcodes: &OpcodeSet) -> String {
+    disassemble_symbolic_from(code, symbols, opcodes, &[0])
+}
+
+pub fn disassemble_symbolic_from(code: &[i64], symbols: &BTreeMap<i64, String>, opcodes: &OpcodeSet,
+        entries: &[i64]) -> String {
+    let analysis = analyze_from(code, opcodes, entries);
+    let names = make_names(code, &analysis, symbols);
+    let mut listing = String::new();
+    let mut ip: i64 = 0;
+
+    while ip < code.len() as i64 {
+        if let Some(name) = names.get(&ip) {
+            writeln!(listing, "{}:", name).unwrap();
+        }
+        match analysis.instructions.get(&ip) {
+            Some(instruction) => {
+                write!(listing, "{:0>4}:     {}", ip,
+                    format_symbolic_instruction(instruction, &names, opcodes)).unwrap();
+                if let Some(target) = analysis.code_writes.get(&ip) {
+                    write!(listing, "   ; writes into code at {:0>4}", target).unwrap();
+                }
+                writeln!(listing).unwrap();
+                ip += instruction.size();
+            },
+            None => {
+                write!(listing, "{:0>4}:     .data {}", ip, code[ip as usize]).unwrap();
+                if analysis.self_modified.contains_key(&ip) {
+                    write!(listing, "   ; modified at run time").unwrap();
+                }
+                writeln!(listing).unwrap();
+                ip += 1;
+            }
+        }
+    }
+
+    listing
+}
@@ -0,0 +1,311 @@
+// Reverse execution.
+//
+// step_program_logged() executes an instruction like step_program() and
+// records in a History how to undo it: the previous ip and rel_base, the old
+// values of the memory cells it wrote to and, for instructions that use the
+// data queue, the previous queue. seek() moves the program back to an earlier
+// step by undoing instructions.
+//
+// The undo log only keeps the most recent entries. To go back further the
+// history also takes a full checkpoint of the program state every so often,
+// along with a log of the input values consumed since the oldest checkpoint;
+// seek() then restores the nearest checkpoint and executes the program
+// forward to the requested step, feeding it the logged input values. Output
+// values produced on the way are dropped. Only the most recent checkpoints
+// are kept, so the memory used is bounded and so is how far back the program
+// can go.
+//
+// Changes made to the program other than by step_program_logged() are not
+// recorded, so the history should be cleared after them.
+
+use std::collections::VecDeque;
+use super::*;
+use super::opcodes::Param;
+
+// A memory write, as returned by History::last_write().
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Write {
+    // Number of instructions executed before the one that wrote the value.
+    pub step: u64,
+    // Address of the instruction.
+    pub ip: i64,
+    pub address: i64,
+    pub old_value: i64,
+    pub new_value: i64
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    ip: i64,
+    rel_base: i64,
+    // Address, old value and new value.
+    writes: Vec<(i64, i64, i64)>,
+    data: Option<VecDeque<i64>>
+}
+
+#[derive(Clone, Debug)]
+struct Checkpoint {
+    step: u64,
+    ip: i64,
+    rel_base: i64,
+    data: VecDeque<i64>,
+    cells: Vec<(i64, i64)>
+}
+
+#[derive(Clone, Debug)]
+pub struct History {
+    pub max_entries: usize,
+    pub checkpoint_interval: u64,
+    pub max_checkpoints: usize,
+    step: u64,
+    // The last entry is for the instruction executed at step - 1.
+    entries: VecDeque<Entry>,
+    checkpoints: VecDeque<Checkpoint>,
+    // Input values consumed since the oldest checkpoint and their steps.
+    inputs: VecDeque<(u64, i64)>
+}
+
+impl Default for History {
+    fn default() -> History {
+        History {
+            max_entries: 100_000,
+            checkpoint_interval: 10_000,
+            max_checkpoints: 100,
+            step: 0,
+            entries: VecDeque::new(),
+            checkpoints: VecDeque::new(),
+            inputs: VecDeque::new()
+        }
+    }
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> History {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn with_checkpoint_interval(mut self, checkpoint_interval: u64) -> History {
+        self.checkpoint_interval = checkpoint_interval.max(1);
+        self
+    }
+
+    pub fn with_max_checkpoints(mut self, max_checkpoints: usize) -> History {
+        self.max_checkpoints = max_checkpoints.max(1);
+        self
+    }
+
+    // Number of instructions executed so far.
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    // The earliest step seek() can go back to.
+    pub fn oldest_step(&self) -> u64 {
+        let undo = self.step - self.entries.len() as u64;
+        self.checkpoints.front().map_or(undo, |checkpoint| checkpoint.step.min(undo))
+    }
+
+    // Forget everything and start counting from zero, e.g. after the program
+    // was changed by hand.
+    pub fn clear(&mut self) {
+        self.step = 0;
+        self.entries.clear();
+        self.checkpoints.clear();
+        self.inputs.clear();
+    }
+
+    // The most recent write to the address that is still in the undo log.
+    pub fn last_write(&self, address: i64) -> Option<Write> {
+        let first_step = self.step - self.entries.len() as u64;
+        self.entries.iter().enumerate().rev().find_map(|(index, entry)| {
+            entry.writes.iter().rev()
+                .find(|&&(written, _, _)| written == address)
+                .map(|&(_, old_value, new_value)| Write {
+                    step: first_step + index as u64,
+                    ip: entry.ip,
+                    address,
+                    old_value,
+                    new_value
+                })
+        })
+    }
+
+    fn checkpoint<M: Memory>(&mut self, program: &Program<M>) {
+        if !self.step.is_multiple_of(self.checkpoint_interval)
+                || self.checkpoints.back().is_some_and(|checkpoint| checkpoint.step >= self.step) {
+            return;
+        }
+        self.checkpoints.push_back(Checkpoint {
+            step: self.step,
+            ip: program.ip,
+            rel_base: program.rel_base,
+            data: program.data.clone(),
+            cells: program.memory.cells()
+        });
+        if self.checkpoints.len() > self.max_checkpoints {
+            self.checkpoints.pop_front();
+            let oldest = self.checkpoints[0].step;
+            while self.inputs.front().is_some_and(|&(step, _)| step < oldest) {
+                self.inputs.pop_front();
+            }
+        }
+    }
+}
+
+// Addresses the instruction at the ip writes to. Errors are left for
+// step_program() to report.
+fn write_addresses<M: Memory>(program: &Program<M>) -> Vec<i64> {
+    let ip = program.ip;
+    if ip < 0 || ip >= program.code.len() as i64 {
+        return Vec::new();
+    }
+    let params = match program.opcodes.params(read_opcode(program.memory.read(ip))) {
+        Some(params) => params,
+        None => return Vec::new()
+    };
+    params.iter().enumerate()
+        .filter(|&(_, &param)| param == Param::Write)
+        .filter_map(|(index, _)| read_param_value_out(ip + 1, &program.memory, program.rel_base, program.arithmetic, index as u32).ok())
+        .filter(|&address| address >= 0)
+        .collect()
+}
+
+// Execute a single instruction like step_program() and record how to undo
+// it. Instructions that halt, fail or wait for input change nothing and are
+// not recorded.
+pub fn step_program_logged<M: Memory>(program: &mut Program<M>, history: &mut History)
+        -> Result<Option<StepResult>, VmError> {
+    history.checkpoint(program);
+
+    let ip = program.ip;
+    let rel_base = program.rel_base;
+    let opcode = if ip >= 0 && ip < program.code.len() as i64 {
+        read_opcode(program.memory.read(ip))
+    } else {
+        OP_HALT
+    };
+    let data = if opcode == OP_INPUT || opcode == OP_OUTPUT || program.opcodes.custom(opcode).is_some() {
+        Some(program.data.clone())
+    } else {
+        None
+    };
+    let addresses = write_addresses(program);
+    let old_values: Vec<i64> = addresses.iter().map(|&address| program.memory.read(address)).collect();
+
+    let result = step_program(program)?;
+    if result == Some(StepResult::NeedInput) || result == Some(StepResult::Halt) {
+        return Ok(result);
+    }
+
+    if let Some(data) = &data {
+        let pushed = (result == Some(StepResult::Output)) as usize;
+        let consumed = (data.len() + pushed).saturating_sub(program.data.len());
+        let step = history.step;
+        history.inputs.extend(data.iter().take(consumed).map(|&value| (step, value)));
+    }
+    let writes = addresses.iter().zip(old_values)
+        .map(|(&address, old_value)| (address, old_value, program.memory.read(address)))
+        .collect();
+    history.entries.push_back(Entry { ip, rel_base, writes, data });
+    if history.entries.len() > history.max_entries {
+        history.entries.pop_front();
+    }
+    history.step += 1;
+    Ok(result)
+}
+
+fn undo<M: Memory>(program: &mut Program<M>, history: &mut History) -> bool {
+    let entry = match history.entries.pop_back() {
+        Some(entry) => entry,
+        None => return false
+    };
+    for &(address, old_value, _) in entry.writes.iter().rev() {
+        program.memory.write(address, old_value);
+    }
+    program.ip = entry.ip;
+    program.rel_base = entry.rel_base;
+    if let Some(data) = entry.data {
+        program.data = data;
+    }
+    history.step -= 1;
+    true
+}
+
+// Execute the program forward from the latest checkpoint at or before the
+// step, feeding it the input values it consumed the first time.
+fn replay_from_checkpoint<M: Memory>(program: &mut Program<M>, history: &mut History, step: u64) -> bool {
+    let checkpoint = match history.checkpoints.iter().rev().find(|checkpoint| checkpoint.step <= step) {
+        Some(checkpoint) => checkpoint.clone(),
+        None => return false
+    };
+    program.ip = checkpoint.ip;
+    program.rel_base = checkpoint.rel_base;
+    program.data = checkpoint.data.clone();
+    program.memory.clear();
+    for &(address, value) in &checkpoint.cells {
+        program.memory.write(address, value);
+    }
+
+    let mut inputs: VecDeque<(u64, i64)> = VecDeque::new();
+    while history.inputs.back().is_some_and(|&(input_step, _)| input_step >= checkpoint.step) {
+        inputs.push_front(history.inputs.pop_back().unwrap());
+    }
+    history.entries.clear();
+    history.step = checkpoint.step;
+
+    while history.step < step {
+        match step_program_logged(program, history) {
+            Ok(None) => (),
+            Ok(Some(StepResult::Output)) => {
+                program.data.pop_back();
+            },
+            Ok(Some(StepResult::NeedInput)) => {
+                while inputs.front().is_some_and(|&(input_step, _)| input_step < history.step) {
+                    inputs.pop_front();
+                }
+                match inputs.front() {
+                    Some(&(input_step, value)) if input_step == history.step => program.data.push_back(value),
+                    _ => return false
+                }
+            },
+            _ => return false
+        }
+    }
+    true
+}
+
+// Move the program back to the state it was in after the given number of
+// steps. Later steps are forgotten. Returns false if the step is not in the
+// history or the program could not be brought back to it, in which case the
+// program may have been moved back only part of the way.
+pub fn seek<M: Memory>(program: &mut Program<M>, history: &mut History, step: u64) -> bool {
+    if step > history.step || step < history.oldest_step() {
+        return false;
+    }
+    if step < history.step - history.entries.len() as u64 && !replay_from_checkpoint(program, history, step) {
+        return false;
+    }
+    while history.step > step {
+        if !undo(program, history) {
+            return false;
+        }
+    }
+
+    while history.checkpoints.back().is_some_and(|checkpoint| checkpoint.step > step) {
+        history.checkpoints.pop_back();
+    }
+    while history.inputs.back().is_some_and(|&(input_step, _)| input_step >= step) {
+        history.inputs.pop_back();
+    }
+    true
+}
+
+// Undo the last executed instruction.
+pub fn reverse_step<M: Memory>(program: &mut Program<M>, history: &mut History) -> bool {
+    history.step > 0 && seek(program, history, history.step - 1)
+}
@@ -0,0 +1,384 @@
+// Intcode virtual machine shared by all Intcode puzzles and tools.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::num::ParseIntError;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+mod memory;
+pub mod amplifier;
+pub mod asm;
+pub mod big;
+pub mod budget;
+pub mod cache;
+pub mod coverage;
+pub mod decompile;
+pub mod disasm;
+pub mod history;
+pub mod network;
+pub mod opcodes;
+pub mod ports;
+pub mod profile;
+pub mod session;
+pub mod snapshot;
+pub mod solver;
+pub mod trace;
+pub mod transpile;
+
+pub use memory::{Memory, PagedMemory, SparseMemory};
+
+pub const MODE_POS: i64 = 0;
+pub const MODE_IMM: i64 = 1;
+pub const MODE_REL: i64 = 2;
+
+pub const OP_HALT: i64 = 99;
+pub const OP_ADD: i64 = 1;
+pub const OP_MULTIPLY: i64 = 2;
+pub const OP_INPUT: i64 = 3;
+pub const OP_OUTPUT: i64 = 4;
+pub const OP_JUMP_IF_TRUE: i64 = 5;
+pub const OP_JUMP_IF_FALSE: i64 = 6;
+pub const OP_LESS_THAN: i64 = 7;
+pub const OP_EQUALS: i64 = 8;
+pub const OP_REL_BASE_OFFSET: i64 = 9;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepResult {
+    Halt,
+    NeedInput,
+    Output
+}
+
+// Faults raised by the VM. The address is that of the faulting instruction;
+// the program's ip is left pointing at it, so after fixing up memory the host
+// can resume execution with run_program().
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VmError {
+    InvalidOpcode { address: i64, instr: i64, opcode: i64 },
+    InvalidMode { address: i64, instr: i64, opcode: i64, index: u32, mode: i64 },
+    ImmediateWrite { address: i64, instr: i64, opcode: i64, index: u32 },
+    NegativeAddress { address: i64, instr: i64, opcode: i64, index: u32, mode: i64, target: i64 },
+    IpOutOfRange { address: i64 },
+    Overflow { address: i64, instr: i64, opcode: i64 },
+    Handler { address: i64, instr: i64, opcode: i64, message: String },
+    // Not a fault of the program: see the budget module.
+    BudgetExceeded { address: i64, limit: budget::Limit }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::InvalidOpcode { address, instr, opcode } =>
+                write!(f, "Invalid opcode {} at address {} (instruction {})", opcode, address, instr),
+            VmError::InvalidMode { address, instr, opcode, index, mode } =>
+                write!(f, "Invalid mode {} for parameter {} of opcode {} at address {} (instruction {})",
+                    mode, index + 1, opcode, address, instr),
+            VmError::ImmediateWrite { address, instr, opcode, index } =>
+                write!(f, "Write in immediate mode by parameter {} of opcode {} at address {} (instruction {})",
+                    index + 1, opcode, address, instr),
+            VmError::NegativeAddress { address, instr, opcode, index, mode, target } =>
+                write!(f, "Access to negative address {} by parameter {} (mode {}) of opcode {} at address {} (instruction {})",
+                    target, index + 1, mode, opcode, address, instr),
+            VmError::IpOutOfRange { address } =>
+                write!(f, "Instruction pointer out of range: {}", address),
+            VmError::Overflow { address, instr, opcode } =>
+                write!(f, "Arithmetic overflow in opcode {} at address {} (instruction {})",
+                    opcode, address, instr),
+            VmError::Handler { address, instr, opcode, message } =>
+                write!(f, "Error in opcode {} at address {} (instruction {}): {}",
+                    opcode, address, instr, message),
+            VmError::BudgetExceeded { address, limit } =>
+                write!(f, "Budget exceeded ({}) at address {}", limit, address)
+        }
+    }
+}
+
+impl error::Error for VmError {}
+
+// How additions and multiplications that don't fit in an i64 are handled.
+// Checked arithmetic faults with VmError::Overflow. See the big module for
+// arbitrary precision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    #[default]
+    Wrapping,
+    Checked,
+    Saturating
+}
+
+impl Arithmetic {
+    // Returns None on overflow in checked mode.
+    pub fn add(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_add(b)),
+            Arithmetic::Checked => a.checked_add(b),
+            Arithmetic::Saturating => Some(a.saturating_add(b))
+        }
+    }
+
+    pub fn multiply(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            Arithmetic::Wrapping => Some(a.wrapping_mul(b)),
+            Arithmetic::Checked => a.checked_mul(b),
+            Arithmetic::Saturating => Some(a.saturating_mul(b))
+        }
+    }
+}
+
+// Program state. The data queue is used for both directions: input values
+// are consumed from the front and output values are appended to the back.
+#[derive(Clone)]
+pub struct Program<M: Memory = PagedMemory> {
+    pub code: Vec<i64>,
+    pub ip: i64,
+    pub memory: M,
+    pub rel_base: i64,
+    pub data: VecDeque<i64>,
+    pub arithmetic: Arithmetic,
+    // Custom opcodes, see the opcodes module.
+    pub opcodes: Arc<opcodes::OpcodeSet>
+}
+
+impl Program {
+    pub fn new(code: Vec<i64>) -> Program {
+        Program::with_memory(code, PagedMemory::new())
+    }
+}
+
+impl<M: Memory> Program<M> {
+    pub fn with_memory(code: Vec<i64>, memory: M) -> Program<M> {
+        let mut program = Program {
+            code,
+            ip: 0,
+            memory,
+            rel_base: 0,
+            data: VecDeque::new(),
+            arithmetic: Arithmetic::default(),
+            opcodes: Arc::new(opcodes::OpcodeSet::new())
+        };
+        init_program(&mut program);
+        program
+    }
+
+    pub fn with_arithmetic(mut self, arithmetic: Arithmetic) -> Program<M> {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    pub fn with_opcodes(mut self, opcodes: Arc<opcodes::OpcodeSet>) -> Program<M> {
+        self.opcodes = opcodes;
+        self
+    }
+}
+
+pub fn try_parse_code(text: &str) -> Result<Vec<i64>, ParseIntError> {
+    text.trim()
+        .split(',')
+        .map(|x| x.trim().parse::<i64>())
+        .collect()
+}
+
+pub fn parse_code(text: &str) -> Vec<i64> {
+    try_parse_code(text).unwrap()
+}
+
+pub fn load_code(path: &str) -> Vec<i64> {
+    parse_code(&fs::read_to_string(path).unwrap())
+}
+
+pub fn load_program(path: &str) -> Program {
+    Program::new(load_code(path))
+}
+
+// Reset the program to its initial state: copy the code into memory and clear
+// the instruction pointer, relative base and pending data.
+pub fn init_program<M: Memory>(program: &mut Program<M>) {
+    program.ip = 0;
+    program.memory.clear();
+    program.rel_base = 0;
+    program.data.clear();
+
+    for (i, &value) in program.code.iter().enumerate() {
+        program.memory.write(i as i64, value);
+    }
+}
+
+pub fn read_opcode(instr: i64) -> i64 {
+    instr % 100
+}
+
+pub fn read_param_mode(instr: i64, index: u32) -> i64 {
+    instr % 10_i64.pow(index + 3) / 10_i64.pow(index + 2)
+}
+
+pub fn param_count(opcode: i64) -> usize {
+    match opcode {
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => 3,
+        OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => 2,
+        OP_INPUT | OP_OUTPUT | OP_REL_BASE_OFFSET => 1,
+        _ => 0
+    }
+}
+
+// Execute a single instruction. Returns None if the program can continue,
+// otherwise the reason it stopped: same as for run_program().
+pub fn step_program<M: Memory>(program: &mut Program<M>) -> Result<Option<StepResult>, VmError> {
+    let memory = &mut program.memory;
+    let mut ip = program.ip;
+    let mut rel_base = program.rel_base;
+    let arithmetic = program.arithmetic;
+
+    if ip < 0 || ip >= program.code.len() as i64 {
+        return Err(VmError::IpOutOfRange { address: ip });
+    }
+
+    let instr = memory.read(ip);
+    let opcode = read_opcode(instr);
+    ip += 1;
+
+    match opcode {
+        OP_HALT => {
+            return Ok(Some(StepResult::Halt));
+        },
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => {
+            let param1 = read_param_value(ip, memory, rel_base, arithmetic, 0)?;
+            let param2 = read_param_value(ip, memory, rel_base, arithmetic, 1)?;
+            let result_address = read_param_value_out(ip, memory, rel_base, arithmetic, 2)?;
+            ip += 3;
+            let result = match opcode {
+                OP_ADD => program.arithmetic.add(param1, param2),
+                OP_MULTIPLY => program.arithmetic.multiply(param1, param2),
+                OP_LESS_THAN => Some((param1 < param2) as i64),
+                _ => Some((param1 == param2) as i64)
+            };
+            match result {
+                Some(result) => memory.write(result_address, result),
+                None => return Err(VmError::Overflow { address: program.ip, instr, opcode })
+            }
+        },
+        OP_INPUT => {
+            let param = read_param_value_out(ip, memory, rel_base, arithmetic, 0)?;
+            ip += 1;
+            match program.data.pop_front() {
+                Some(value) => {
+                    memory.write(param, value);
+                },
+                None => return Ok(Some(StepResult::NeedInput))
+            }
+        },
+        OP_OUTPUT => {
+            let param = read_param_value(ip, memory, rel_base, arithmetic, 0)?;
+            ip += 1;
+            program.data.push_back(param);
+            program.ip = ip;
+            return Ok(Some(StepResult::Output));
+        },
+        OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => {
+            let param1 = read_param_value(ip, memory, rel_base, arithmetic, 0)?;
+            let param2 = read_param_value(ip, memory, rel_base, arithmetic, 1)?;
+            ip += 2;
+            if (opcode == OP_JUMP_IF_TRUE && param1 != 0)
+                    || (opcode == OP_JUMP_IF_FALSE && param1 == 0) {
+                ip = param2;
+            }
+        },
+        OP_REL_BASE_OFFSET => {
+            let offset = read_param_value(ip, memory, rel_base, arithmetic, 0)?;
+            rel_base = match program.arithmetic.add(rel_base, offset) {
+                Some(rel_base) => rel_base,
+                None => return Err(VmError::Overflow { address: program.ip, instr, opcode })
+            };
+            ip += 1;
+        }
+        _ => {
+            return opcodes::step_custom(program, instr, opcode);
+        }
+    }
+
+    program.ip = ip;
+    program.rel_base = rel_base;
+    Ok(None)
+}
+
+// Run the program until it halts, produces an output value or needs an input
+// value that is not in the data queue. In the latter two cases the program can
+// be resumed by calling run_program() again.
+pub fn run_program<M: Memory>(program: &mut Program<M>) -> Result<StepResult, VmError> {
+    loop {
+        if let Some(result) = step_program(program)? {
+            return Ok(result);
+        }
+    }
+}
+
+// Add the relative base to a relative mode parameter. Overflows are handled
+// like in arithmetic instructions.
+fn relative_address(address: i64, instr: i64, param: i64, rel_base: i64, arithmetic: Arithmetic)
+        -> Result<i64, VmError> {
+    arithmetic.add(param, rel_base).ok_or(VmError::Overflow { address, instr, opcode: read_opcode(instr) })
+}
+
+fn read_param_value<M: Memory>(start: i64, memory: &M, rel_base: i64, arithmetic: Arithmetic, index: u32)
+        -> Result<i64, VmError> {
+    let instr = memory.read(start - 1);
+    let mode = read_param_mode(instr, index);
+    let param = memory.read(start + index as i64);
+    let address = match mode {
+        MODE_POS => param,
+        MODE_IMM => return Ok(param),
+        MODE_REL => relative_address(start - 1, instr, param, rel_base, arithmetic)?,
+        _ => return Err(VmError::InvalidMode {
+            address: start - 1, instr, opcode: read_opcode(instr), index, mode
+        })
+    };
+    if address < 0 {
+        return Err(VmError::NegativeAddress {
+            address: start - 1, instr, opcode: read_opcode(instr), index, mode, target: address
+        });
+    }
+    Ok(memory.read(address))
+}
+
+fn read_param_value_out<M: Memory>(start: i64, memory: &M, rel_base: i64, arithmetic: Arithmetic, index: u32)
+        -> Result<i64, VmError> {
+    let instr = memory.read(start - 1);
+    let mode = read_param_mode(instr, index);
+    let param = memory.read(start + index as i64);
+    let address = match mode {
+        MODE_POS => param,
+        MODE_REL => relative_address(start - 1, instr, param, rel_base, arithmetic)?,
+        MODE_IMM => return Err(VmError::ImmediateWrite {
+            address: start - 1, instr, opcode: read_opcode(instr), index
+        }),
+        _ => return Err(VmError::InvalidMode {
+            address: start - 1, instr, opcode: read_opcode(instr), index, mode
+        })
+    };
+    if address < 0 {
+        return Err(VmError::NegativeAddress {
+            address: start - 1, instr, opcode: read_opcode(instr), index, mode, target: address
+        });
+    }
+    Ok(address)
+}
+
+// Run the program interactively: prompt for input values on stdin and print
+// output values to stdout.
+pub fn run_program_interactive<M: Memory>(program: &mut Program<M>) {
+    match ports::run_program_io(program, &mut ports::StdinInput::new("> "), &mut ports::StdoutOutput) {
+        Ok(StepResult::Halt) => println!("Program exited"),
+        Ok(_) => {},
+        Err(error) => println!("Error: {}", error)
+    }
+}
+
+// Run the program as a text console: see AsciiInput and AsciiOutput.
+pub fn run_program_ascii<M: Memory>(program: &mut Program<M>) {
+    match ports::run_program_io(program, &mut ports::AsciiInput::stdin(), &mut ports::AsciiOutput::stdout()) {
+        Ok(StepResult::Halt) => println!("Program exited"),
+        Ok(_) => {},
+        Err(error) => println!("Error: {}", error)
+    }
+}
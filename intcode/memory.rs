@@ -0,0 +1,127 @@
+// Memory backends for the Intcode VM.
+//
+// The VM never passes a negative address to a memory backend: such accesses are
+// reported as VmError::NegativeAddress before they reach it.
+
+use std::collections::HashMap;
+
+pub trait Memory {
+    fn read(&self, address: i64) -> i64;
+    fn write(&mut self, address: i64, value: i64);
+    fn clear(&mut self);
+    // Non-zero cells in address order.
+    fn cells(&self) -> Vec<(i64, i64)>;
+}
+
+const PAGE_SIZE: usize = 1024;
+
+// Pages below this index are looked up in a vector, the rest in a hash map.
+const DENSE_PAGES: usize = 1024;
+
+type Page = Box<[i64; PAGE_SIZE]>;
+
+// Memory split into fixed-size pages that are allocated on first write.
+// Reads from unallocated pages return 0. The page table for the first
+// DENSE_PAGES pages is a plain vector, which is what programs normally use;
+// pages at higher addresses are kept in a hash map, so writing to a huge
+// address only allocates a single page.
+#[derive(Clone, Default)]
+pub struct PagedMemory {
+    pages: Vec<Option<Page>>,
+    high_pages: HashMap<usize, Page>
+}
+
+impl PagedMemory {
+    pub fn new() -> PagedMemory {
+        PagedMemory { pages: Vec::new(), high_pages: HashMap::new() }
+    }
+}
+
+impl Memory for PagedMemory {
+    fn read(&self, address: i64) -> i64 {
+        let address = address as usize;
+        let page_index = address / PAGE_SIZE;
+        let page = if page_index < DENSE_PAGES {
+            self.pages.get(page_index).and_then(|page| page.as_ref())
+        } else {
+            self.high_pages.get(&page_index)
+        };
+        match page {
+            Some(page) => page[address % PAGE_SIZE],
+            None => 0
+        }
+    }
+
+    fn write(&mut self, address: i64, value: i64) {
+        let address = address as usize;
+        let page_index = address / PAGE_SIZE;
+        let page = if page_index < DENSE_PAGES {
+            if page_index >= self.pages.len() {
+                self.pages.resize(page_index + 1, None);
+            }
+            self.pages[page_index].get_or_insert_with(|| Box::new([0; PAGE_SIZE]))
+        } else {
+            self.high_pages.entry(page_index).or_insert_with(|| Box::new([0; PAGE_SIZE]))
+        };
+        page[address % PAGE_SIZE] = value;
+    }
+
+    fn clear(&mut self) {
+        self.pages.clear();
+        self.high_pages.clear();
+    }
+
+    fn cells(&self) -> Vec<(i64, i64)> {
+        let mut high_pages: Vec<(&usize, &Page)> = self.high_pages.iter().collect();
+        high_pages.sort_unstable_by_key(|&(&page_index, _)| page_index);
+        let pages = self.pages.iter().enumerate()
+            .filter_map(|(page_index, page)| page.as_ref().map(|page| (page_index, page)))
+            .chain(high_pages.into_iter().map(|(&page_index, page)| (page_index, page)));
+
+        let mut cells = Vec::new();
+        for (page_index, page) in pages {
+            for (offset, &value) in page.iter().enumerate() {
+                if value != 0 {
+                    cells.push(((page_index * PAGE_SIZE + offset) as i64, value));
+                }
+            }
+        }
+        cells
+    }
+}
+
+// Sparse memory backed by a hash map. Slower than PagedMemory but suitable
+// for any address.
+#[derive(Clone, Default)]
+pub struct SparseMemory {
+    cells: HashMap<i64, i64>
+}
+
+impl SparseMemory {
+    pub fn new() -> SparseMemory {
+        SparseMemory { cells: HashMap::new() }
+    }
+}
+
+impl Memory for SparseMemory {
+    fn read(&self, address: i64) -> i64 {
+        *self.cells.get(&address).unwrap_or(&0)
+    }
+
+    fn write(&mut self, address: i64, value: i64) {
+        self.cells.insert(address, value);
+    }
+
+    fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    fn cells(&self) -> Vec<(i64, i64)> {
+        let mut cells: Vec<(i64, i64)> = self.cells.iter()
+            .filter(|&(_, &value)| value != 0)
+            .map(|(&address, &value)| (address, value))
+            .collect();
+        cells.sort_unstable();
+        cells
+    }
+}
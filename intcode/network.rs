@@ -0,0 +1,250 @@
+// Networks of programs connected by their inputs and outputs.
+//
+// Every node runs its own program. Output values of a node are appended to
+// the input queues of all nodes it is linked to, and are also kept in the
+// node's list of outputs. The network runs until all nodes have halted or
+// every node that hasn't halted is waiting for input that will never come.
+//
+// A network can be described by a config file where each line declares a
+// node with its program and initial input values, or a link between two
+// nodes; everything after a ';' is a comment. Program paths are relative to
+// the config file. The amplifier loop of day 7 looks like this:
+//
+//     node a input.txt 8,0
+//     node b input.txt 9
+//     node c input.txt 6
+//     node d input.txt 7
+//     node e input.txt 5
+//     link a b
+//     link b c
+//     link c d
+//     link d e
+//     link e a
+
+use std::collections::VecDeque;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use super::*;
+use super::ports::run_program_io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeStatus {
+    Ready,
+    Blocked,
+    Halted
+}
+
+#[derive(Clone)]
+pub struct Node {
+    pub name: String,
+    pub program: Program,
+    pub input: VecDeque<i64>,
+    pub outputs: Vec<i64>,
+    pub links: Vec<usize>,
+    pub status: NodeStatus
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheduler {
+    // Run the nodes one after another in the order they were added, each
+    // until it blocks or halts.
+    RoundRobin,
+    // Only run nodes that have received input since they last blocked.
+    EventDriven
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NetworkResult {
+    Halted,
+    // Names of the nodes that are waiting for input.
+    Deadlock(Vec<String>)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetworkError {
+    pub node: String,
+    pub error: VmError
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}: {}", self.node, self.error)
+    }
+}
+
+impl error::Error for NetworkError {}
+
+#[derive(Clone, Default)]
+pub struct Network {
+    pub nodes: Vec<Node>
+}
+
+impl Network {
+    pub fn new() -> Network {
+        Network { nodes: Vec::new() }
+    }
+
+    pub fn add_node(&mut self, name: &str, code: Vec<i64>, input: &[i64]) -> usize {
+        self.add_program(name, Program::new(code), input)
+    }
+
+    pub fn add_program(&mut self, name: &str, program: Program, input: &[i64]) -> usize {
+        self.nodes.push(Node {
+            name: name.to_string(),
+            program,
+            input: input.iter().cloned().collect(),
+            outputs: Vec::new(),
+            links: Vec::new(),
+            status: NodeStatus::Ready
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn add_link(&mut self, from: usize, to: usize) {
+        self.nodes[from].links.push(to);
+    }
+
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    pub fn node(&self, name: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    // Run a node until it blocks or halts and deliver its outputs. Returns the
+    // nodes that received input.
+    fn run_node(&mut self, index: usize) -> Result<Vec<usize>, NetworkError> {
+        let node = &mut self.nodes[index];
+        if node.status == NodeStatus::Halted {
+            return Ok(Vec::new());
+        }
+
+        let mut outputs = Vec::new();
+        let result = run_program_io(&mut node.program, &mut node.input, &mut outputs)
+            .map_err(|error| NetworkError { node: node.name.clone(), error })?;
+        node.status = match result {
+            StepResult::Halt => NodeStatus::Halted,
+            _ => NodeStatus::Blocked
+        };
+        if outputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        node.outputs.extend(&outputs);
+
+        let links = node.links.clone();
+        for &target in &links {
+            self.nodes[target].input.extend(&outputs);
+        }
+        Ok(links)
+    }
+
+    pub fn run(&mut self, scheduler: Scheduler) -> Result<NetworkResult, NetworkError> {
+        match scheduler {
+            Scheduler::RoundRobin => {
+                loop {
+                    for index in 0..self.nodes.len() {
+                        self.run_node(index)?;
+                    }
+                    if self.nodes.iter().all(|node| node.status == NodeStatus::Halted
+                            || node.input.is_empty()) {
+                        break;
+                    }
+                }
+            },
+            Scheduler::EventDriven => {
+                let mut ready: VecDeque<usize> = (0..self.nodes.len()).collect();
+                while let Some(index) = ready.pop_front() {
+                    for target in self.run_node(index)? {
+                        if !ready.contains(&target) {
+                            ready.push_back(target);
+                        }
+                    }
+                }
+            }
+        }
+
+        let blocked: Vec<String> = self.nodes.iter()
+            .filter(|node| node.status != NodeStatus::Halted)
+            .map(|node| node.name.clone())
+            .collect();
+        if blocked.is_empty() {
+            Ok(NetworkResult::Halted)
+        } else {
+            Ok(NetworkResult::Deadlock(blocked))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    pub line: usize,
+    pub message: String
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl error::Error for ConfigError {}
+
+// Build a network from the text of a config file. Program paths are relative
+// to base_dir.
+pub fn parse_network(text: &str, base_dir: &Path) -> Result<Network, ConfigError> {
+    let mut network = Network::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| ConfigError { line: index + 1, message };
+        let line = match line.find(';') {
+            Some(index) => &line[..index],
+            None => line
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens[..] {
+            [] => {},
+            ["node", name, path] | ["node", name, path, _] => {
+                if network.find_node(name).is_some() {
+                    return Err(error(format!("Duplicate node '{}'", name)));
+                }
+                let path = base_dir.join(path);
+                let text = match fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(e) => return Err(error(format!("Could not read {}: {}", path.display(), e)))
+                };
+                let code = match try_parse_code(&text) {
+                    Ok(code) => code,
+                    Err(e) => return Err(error(format!("Invalid program {}: {}", path.display(), e)))
+                };
+                let input = match tokens.get(3) {
+                    Some(values) => try_parse_code(values),
+                    None => Ok(Vec::new())
+                };
+                match input {
+                    Ok(input) => network.add_node(name, code, &input),
+                    Err(_) => return Err(error(format!("Invalid input values '{}'", tokens[3])))
+                };
+            },
+            ["link", from, to] => {
+                match (network.find_node(from), network.find_node(to)) {
+                    (Some(from), Some(to)) => network.add_link(from, to),
+                    (None, _) => return Err(error(format!("Unknown node '{}'", from))),
+                    (_, None) => return Err(error(format!("Unknown node '{}'", to)))
+                }
+            },
+            _ => return Err(error(format!("Invalid line '{}'", line.trim())))
+        }
+    }
+
+    Ok(network)
+}
+
+pub fn load_network(path: &str) -> Result<Network, ConfigError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| ConfigError { line: 0, message: format!("Could not read {}: {}", path, e) })?;
+    let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    parse_network(&text, base_dir)
+}
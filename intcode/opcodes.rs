@@ -0,0 +1,201 @@
+// Custom opcodes.
+//
+// An OpcodeSet describes the instructions a program may use: the built-in
+// ones plus any registered by the host. Each custom opcode has a name, a list
+// of parameters that are either read (their value is passed to the handler)
+// or written (the handler returns the values to store), and a handler that
+// decides what happens next:
+//
+//     let mut opcodes = OpcodeSet::new();
+//     opcodes.register(10, "divide", &[Param::Read, Param::Read, Param::Write], |args, _| {
+//         match args[1] {
+//             0 => Err("Division by zero".to_string()),
+//             b => Ok(Effect::Store(vec![args[0] / b]))
+//         }
+//     }).unwrap();
+//     let program = Program::new(code).with_opcodes(Arc::new(opcodes));
+//
+// Parameter modes work as for the built-in instructions. step_program() and
+// everything built on it run custom opcodes; the disassembler and assembler
+// use the names when given the set (see disasm::disassemble_with() and
+// asm::assemble_with()).
+
+use std::collections::{BTreeMap, VecDeque};
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+use super::*;
+use super::disasm::{opcode_by_name, opcode_name};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    Read,
+    Write
+}
+
+// What a custom instruction does after the handler returns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Effect {
+    // Store one value per Write parameter, in order, and go to the next
+    // instruction.
+    Store(Vec<i64>),
+    Jump(i64),
+    Output(i64),
+    // Stop with StepResult::NeedInput; the instruction is executed again when
+    // the program is resumed. Handlers take input values from the data queue
+    // and must leave it untouched in this case.
+    NeedInput,
+    Halt
+}
+
+// Handlers get the values of the Read parameters and the program's data
+// queue. Errors are reported as VmError::Handler.
+pub type Handler = dyn Fn(&[i64], &mut VecDeque<i64>) -> Result<Effect, String> + Send + Sync;
+
+#[derive(Clone)]
+pub struct CustomOpcode {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub handler: Arc<Handler>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OpcodeError {
+    // Opcodes must be between 1 and 98 and not used by a built-in instruction.
+    InvalidOpcode(i64),
+    InvalidName(String),
+    DuplicateOpcode(i64),
+    DuplicateName(String),
+    TooManyParams(usize)
+}
+
+impl fmt::Display for OpcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpcodeError::InvalidOpcode(opcode) => write!(f, "Opcode {} is reserved or out of range", opcode),
+            OpcodeError::InvalidName(name) => write!(f, "Invalid instruction name '{}'", name),
+            OpcodeError::DuplicateOpcode(opcode) => write!(f, "Opcode {} is already registered", opcode),
+            OpcodeError::DuplicateName(name) => write!(f, "Instruction name '{}' is already used", name),
+            OpcodeError::TooManyParams(count) =>
+                write!(f, "Too many parameters: {} (at most {})", count, MAX_PARAMS)
+        }
+    }
+}
+
+impl error::Error for OpcodeError {}
+
+// Parameter modes of more parameters don't fit in an i64 instruction.
+pub const MAX_PARAMS: usize = 16;
+
+pub fn builtin_params(opcode: i64) -> Option<&'static [Param]> {
+    use self::Param::*;
+    match opcode {
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => Some(&[Read, Read, Write]),
+        OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => Some(&[Read, Read]),
+        OP_INPUT => Some(&[Write]),
+        OP_OUTPUT | OP_REL_BASE_OFFSET => Some(&[Read]),
+        OP_HALT => Some(&[]),
+        _ => None
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct OpcodeSet {
+    custom: BTreeMap<i64, CustomOpcode>
+}
+
+impl OpcodeSet {
+    // The built-in instructions only.
+    pub fn new() -> OpcodeSet {
+        OpcodeSet::default()
+    }
+
+    pub fn register<F>(&mut self, opcode: i64, name: &str, params: &[Param], handler: F)
+            -> Result<(), OpcodeError>
+            where F: Fn(&[i64], &mut VecDeque<i64>) -> Result<Effect, String> + Send + Sync + 'static {
+        if !(1..=98).contains(&opcode) || builtin_params(opcode).is_some() {
+            return Err(OpcodeError::InvalidOpcode(opcode));
+        }
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(OpcodeError::InvalidName(name.to_string()));
+        }
+        if self.custom.contains_key(&opcode) {
+            return Err(OpcodeError::DuplicateOpcode(opcode));
+        }
+        if self.opcode_by_name(name).is_some() {
+            return Err(OpcodeError::DuplicateName(name.to_string()));
+        }
+        if params.len() > MAX_PARAMS {
+            return Err(OpcodeError::TooManyParams(params.len()));
+        }
+
+        let custom = CustomOpcode { name: name.to_string(), params: params.to_vec(), handler: Arc::new(handler) };
+        self.custom.insert(opcode, custom);
+        Ok(())
+    }
+
+    pub fn custom(&self, opcode: i64) -> Option<&CustomOpcode> {
+        self.custom.get(&opcode)
+    }
+
+    pub fn name(&self, opcode: i64) -> Option<&str> {
+        opcode_name(opcode).or_else(|| self.custom.get(&opcode).map(|op| op.name.as_str()))
+    }
+
+    pub fn opcode_by_name(&self, name: &str) -> Option<i64> {
+        opcode_by_name(name).or_else(|| self.custom.iter().find(|(_, op)| op.name == name).map(|(&opcode, _)| opcode))
+    }
+
+    pub fn params(&self, opcode: i64) -> Option<&[Param]> {
+        builtin_params(opcode).or_else(|| self.custom.get(&opcode).map(|op| &op.params[..]))
+    }
+}
+
+// Execute a custom instruction at the program's ip. Called by step_program()
+// for opcodes that are not built in.
+pub(crate) fn step_custom<M: Memory>(program: &mut Program<M>, instr: i64, opcode: i64)
+        -> Result<Option<StepResult>, VmError> {
+    let address = program.ip;
+    let opcodes = Arc::clone(&program.opcodes);
+    let custom = match opcodes.custom(opcode) {
+        Some(custom) => custom,
+        None => return Err(VmError::InvalidOpcode { address, instr, opcode })
+    };
+
+    let start = address + 1;
+    let mut args = Vec::new();
+    let mut targets = Vec::new();
+    for (i, param) in custom.params.iter().enumerate() {
+        match param {
+            Param::Read => args.push(read_param_value(start, &program.memory, program.rel_base, program.arithmetic, i as u32)?),
+            Param::Write => targets.push(read_param_value_out(start, &program.memory, program.rel_base, program.arithmetic, i as u32)?)
+        }
+    }
+
+    let error = |message| VmError::Handler { address, instr, opcode, message };
+    let next = start + custom.params.len() as i64;
+    match (custom.handler)(&args, &mut program.data).map_err(error)? {
+        Effect::Store(values) => {
+            if values.len() != targets.len() {
+                return Err(error(format!("Expected {} values to store, got {}", targets.len(), values.len())));
+            }
+            for (&target, &value) in targets.iter().zip(&values) {
+                program.memory.write(target, value);
+            }
+            program.ip = next;
+            Ok(None)
+        },
+        Effect::Jump(target) => {
+            program.ip = target;
+            Ok(None)
+        },
+        Effect::Output(value) => {
+            program.data.push_back(value);
+            program.ip = next;
+            Ok(Some(StepResult::Output))
+        },
+        Effect::NeedInput => Ok(Some(StepResult::NeedInput)),
+        Effect::Halt => Ok(Some(StepResult::Halt))
+    }
+}
@@ -0,0 +1,229 @@
+// Input and output ports that connect a program to its host.
+//
+// run_program_io() runs a program like run_program(), but takes input values
+// from an IntcodeInput when the data queue is empty and passes every output
+// value to an IntcodeOutput, so the host doesn't have to manage the data
+// queue itself. It returns when the program halts or the input has no more
+// values (StepResult::NeedInput); in the latter case the program can be
+// resumed later.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, StdinLock, Stdout, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use super::*;
+
+pub trait IntcodeInput {
+    // Return the next input value, or None if there is none available yet.
+    fn read(&mut self) -> Option<i64>;
+}
+
+pub trait IntcodeOutput {
+    fn write(&mut self, value: i64);
+}
+
+pub fn run_program_io<M, I, O>(program: &mut Program<M>, input: &mut I, output: &mut O)
+        -> Result<StepResult, VmError>
+        where M: Memory, I: IntcodeInput + ?Sized, O: IntcodeOutput + ?Sized {
+    loop {
+        match run_program(program)? {
+            StepResult::NeedInput => {
+                match input.read() {
+                    Some(value) => program.data.push_back(value),
+                    None => return Ok(StepResult::NeedInput)
+                }
+            },
+            StepResult::Output => output.write(program.data.pop_back().unwrap()),
+            StepResult::Halt => return Ok(StepResult::Halt)
+        }
+    }
+}
+
+// Queues: values are read from the front and written to the back.
+impl IntcodeInput for VecDeque<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl IntcodeOutput for VecDeque<i64> {
+    fn write(&mut self, value: i64) {
+        self.push_back(value);
+    }
+}
+
+impl IntcodeOutput for Vec<i64> {
+    fn write(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+// A fixed list of input values. Once they are exhausted the input either
+// stays empty or starts over from the first value.
+pub struct FixedInput {
+    values: Vec<i64>,
+    index: usize,
+    repeat: bool
+}
+
+impl FixedInput {
+    pub fn new(values: Vec<i64>) -> FixedInput {
+        FixedInput { values, index: 0, repeat: false }
+    }
+
+    pub fn with_repeat(mut self) -> FixedInput {
+        self.repeat = true;
+        self
+    }
+}
+
+impl IntcodeInput for FixedInput {
+    fn read(&mut self) -> Option<i64> {
+        if self.repeat && self.index == self.values.len() {
+            self.index = 0;
+        }
+        let value = self.values.get(self.index).cloned();
+        if value.is_some() {
+            self.index += 1;
+        }
+        value
+    }
+}
+
+// Prompt for input values on stdin. Invalid values are reported and asked
+// for again; end of input leaves the program waiting for input.
+pub struct StdinInput {
+    prompt: String
+}
+
+impl StdinInput {
+    pub fn new(prompt: &str) -> StdinInput {
+        StdinInput { prompt: prompt.to_string() }
+    }
+}
+
+impl IntcodeInput for StdinInput {
+    fn read(&mut self) -> Option<i64> {
+        let stdin = io::stdin();
+        loop {
+            print!("{}", self.prompt);
+            io::stdout().flush().unwrap();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap() == 0 {
+                return None;
+            }
+            match line.trim().parse::<i64>() {
+                Ok(value) => return Some(value),
+                Err(_) => println!("Input value is not an integer")
+            }
+        }
+    }
+}
+
+// Print each output value on its own line.
+pub struct StdoutOutput;
+
+impl IntcodeOutput for StdoutOutput {
+    fn write(&mut self, value: i64) {
+        println!("{}", value);
+    }
+}
+
+// ASCII console input: each line read from the reader is fed to the program
+// as character codes followed by a newline (10).
+pub struct AsciiInput<R: BufRead> {
+    reader: R,
+    pending: VecDeque<i64>
+}
+
+impl<R: BufRead> AsciiInput<R> {
+    pub fn new(reader: R) -> AsciiInput<R> {
+        AsciiInput { reader, pending: VecDeque::new() }
+    }
+}
+
+impl AsciiInput<StdinLock<'static>> {
+    pub fn stdin() -> AsciiInput<StdinLock<'static>> {
+        AsciiInput::new(io::stdin().lock())
+    }
+}
+
+impl<R: BufRead> IntcodeInput for AsciiInput<R> {
+    fn read(&mut self) -> Option<i64> {
+        if self.pending.is_empty() {
+            // Make sure the program's prompt is visible before waiting.
+            io::stdout().flush().unwrap();
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).unwrap() == 0 {
+                return None;
+            }
+            let line = line.trim_end_matches(&['\r', '\n'][..]);
+            self.pending.extend(line.chars().map(|c| c as i64));
+            self.pending.push_back(10);
+        }
+        self.pending.pop_front()
+    }
+}
+
+// ASCII console output: values in 0..=127 are written as characters and any
+// other value as a number on its own line.
+pub struct AsciiOutput<W: Write> {
+    out: W
+}
+
+impl<W: Write> AsciiOutput<W> {
+    pub fn new(out: W) -> AsciiOutput<W> {
+        AsciiOutput { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl AsciiOutput<Stdout> {
+    pub fn stdout() -> AsciiOutput<Stdout> {
+        AsciiOutput::new(io::stdout())
+    }
+}
+
+impl<W: Write> IntcodeOutput for AsciiOutput<W> {
+    fn write(&mut self, value: i64) {
+        if (0..=127).contains(&value) {
+            write!(self.out, "{}", value as u8 as char).unwrap();
+        } else {
+            writeln!(self.out, "{}", value).unwrap();
+        }
+    }
+}
+
+// Adapters for closures: InputFn(|| Some(1)), OutputFn(|value| ...).
+pub struct InputFn<F: FnMut() -> Option<i64>>(pub F);
+
+impl<F: FnMut() -> Option<i64>> IntcodeInput for InputFn<F> {
+    fn read(&mut self) -> Option<i64> {
+        (self.0)()
+    }
+}
+
+pub struct OutputFn<F: FnMut(i64)>(pub F);
+
+impl<F: FnMut(i64)> IntcodeOutput for OutputFn<F> {
+    fn write(&mut self, value: i64) {
+        (self.0)(value)
+    }
+}
+
+// Channels, for programs running on different threads. Reading blocks until
+// a value arrives or the sender is dropped; values written after the receiver
+// is dropped are discarded.
+impl IntcodeInput for Receiver<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+impl IntcodeOutput for Sender<i64> {
+    fn write(&mut self, value: i64) {
+        self.send(value).ok();
+    }
+}
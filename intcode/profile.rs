@@ -0,0 +1,178 @@
+// Execution profiler.
+//
+// Profile is a Tracer that counts how many times each instruction and each
+// opcode is executed and which jumps are taken. From these it finds the hot
+// basic blocks (straight-line runs of instructions entered at the top) and
+// loops (ranges closed by a backward jump), and can annotate a disassembly
+// listing with hit counts.
+
+use std::collections::{BTreeMap, BTreeSet};
+use super::*;
+use super::opcodes::OpcodeSet;
+use super::trace::{TraceRecord, Tracer};
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    // Number of executions by instruction address.
+    pub counts: BTreeMap<i64, u64>,
+    // Opcode last executed at each address.
+    pub opcodes: BTreeMap<i64, i64>,
+    // Size of the instruction last executed at each address, which also
+    // covers custom opcodes.
+    pub sizes: BTreeMap<i64, i64>,
+    pub opcode_counts: BTreeMap<i64, u64>,
+    // Number of times each jump was taken, by source and target address.
+    pub jumps: BTreeMap<(i64, i64), u64>,
+    // Jumps whose target is read from memory, such as returns from calls.
+    pub computed_jumps: BTreeSet<i64>,
+    pub total: u64
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    pub start: i64,
+    // Address of the last instruction.
+    pub end: i64,
+    // Number of times the block was entered.
+    pub runs: u64,
+    pub instructions: u64
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Loop {
+    pub start: i64,
+    // Address of the jump back to the start.
+    pub end: i64,
+    pub iterations: u64,
+    // Instructions executed within the address range of the loop.
+    pub instructions: u64
+}
+
+impl Tracer for Profile {
+    fn trace(&mut self, record: &TraceRecord) {
+        *self.counts.entry(record.ip).or_insert(0) += 1;
+        self.opcodes.insert(record.ip, record.opcode);
+        self.sizes.insert(record.ip, record.params.len() as i64 + 1);
+        *self.opcode_counts.entry(record.opcode).or_insert(0) += 1;
+        self.total += 1;
+
+        let taken = match record.opcode {
+            OP_JUMP_IF_TRUE => record.values[0] != 0,
+            OP_JUMP_IF_FALSE => record.values[0] == 0,
+            _ => false
+        };
+        if taken {
+            *self.jumps.entry((record.ip, record.values[1])).or_insert(0) += 1;
+            if record.modes[1] != MODE_IMM {
+                self.computed_jumps.insert(record.ip);
+            }
+        }
+    }
+}
+
+fn is_block_end(opcode: i64) -> bool {
+    matches!(opcode, OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE | OP_HALT)
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    // Basic blocks ordered by the number of instructions executed in them.
+    pub fn blocks(&self) -> Vec<Block> {
+        let targets: BTreeSet<i64> = self.jumps.keys().map(|&(_, to)| to).collect();
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut next_address = None;
+
+        for (&address, &count) in &self.counts {
+            let opcode = self.opcodes[&address];
+            match blocks.last_mut() {
+                Some(block) if next_address == Some(address) && !targets.contains(&address) => {
+                    block.end = address;
+                    block.instructions += count;
+                },
+                _ => blocks.push(Block { start: address, end: address, runs: count, instructions: count })
+            }
+            next_address = if is_block_end(opcode) {
+                None
+            } else {
+                Some(address + self.sizes[&address])
+            };
+        }
+
+        blocks.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.start.cmp(&b.start)));
+        blocks
+    }
+
+    // Loops formed by backward jumps to fixed addresses, ordered by the
+    // number of instructions executed in them.
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = self.jumps.iter()
+            .filter(|&(&(from, to), _)| to <= from && !self.computed_jumps.contains(&from))
+            .map(|(&(from, to), &iterations)| Loop {
+                start: to,
+                end: from,
+                iterations,
+                instructions: self.counts.range(to..=from).map(|(_, &count)| count).sum()
+            })
+            .collect();
+        loops.sort_by(|a, b| b.instructions.cmp(&a.instructions).then(a.start.cmp(&b.start)));
+        loops
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        if self.total == 0 { 0.0 } else { count as f64 * 100.0 / self.total as f64 }
+    }
+}
+
+// Summary of the profile showing at most top entries in each section.
+pub fn format_report(profile: &Profile, top: usize) -> String {
+    format_report_with(profile, top, &OpcodeSet::new())
+}
+
+pub fn format_report_with(profile: &Profile, top: usize, opcodes: &OpcodeSet) -> String {
+    let mut text = format!("Executed {} instructions\n", profile.total);
+
+    text.push_str("\nOpcodes:\n");
+    let mut counts: Vec<(&i64, &u64)> = profile.opcode_counts.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (&opcode, &count) in counts {
+        text.push_str(&format!("  {: <16} {: >12} {: >7.2}%\n",
+            opcodes.name(opcode).unwrap_or("?"), count, profile.percent(count)));
+    }
+
+    text.push_str("\nHot blocks:\n");
+    for block in profile.blocks().iter().take(top) {
+        text.push_str(&format!("  {:0>4}-{:0>4} {: >12} runs {: >12} instructions {: >7.2}%\n",
+            block.start, block.end, block.runs, block.instructions, profile.percent(block.instructions)));
+    }
+
+    text.push_str("\nLoops:\n");
+    for l in profile.loops().iter().take(top) {
+        text.push_str(&format!("  {:0>4}-{:0>4} {: >12} iterations {: >12} instructions {: >7.2}%\n",
+            l.start, l.end, l.iterations, l.instructions, profile.percent(l.instructions)));
+    }
+    text
+}
+
+// Prefix each line of a disassembly listing that starts with an executed
+// address ("0042: ...") with its hit count and share of all executed
+// instructions.
+pub fn annotate_listing(listing: &str, profile: &Profile) -> String {
+    let mut text = String::new();
+    for line in listing.lines() {
+        let address = line.find(':')
+            .map(|index| &line[..index])
+            .filter(|prefix| !prefix.is_empty() && prefix.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|prefix| prefix.parse::<i64>().ok());
+        match address.and_then(|address| profile.counts.get(&address)) {
+            Some(&count) => {
+                text.push_str(&format!("{: >12} {: >7.2}%  {}\n", count, profile.percent(count), line));
+            },
+            None if line.is_empty() => text.push('\n'),
+            None => text.push_str(&format!("{: >23}{}\n", "", line))
+        }
+    }
+    text
+}
@@ -0,0 +1,332 @@
+// Deterministic record and replay of Intcode sessions.
+//
+// A program's behaviour depends only on its code and on the input values it
+// is given, so a log of the values it consumes is enough to reproduce a whole
+// session, whatever the host did to produce them. run_program_recorded() runs
+// a program like run_program() and logs an event for every input value taken
+// from the data queue, every output value and halting, each tagged with the
+// number of instructions executed before it and the address of the
+// instruction that caused it:
+//
+//     intcode-session 1
+//     0 0 input 5
+//     4 24 output 7
+//     9 40 halt
+//
+// replay() runs a program from the same initial state, feeding it the logged
+// input values, and checks that every event happens again at the same
+// instruction. The first instruction that should have produced an event but
+// didn't, or that produced a different one, is reported as a Divergence.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use super::*;
+
+pub const SESSION_VERSION: u32 = 1;
+
+const SESSION_MAGIC: &str = "intcode-session";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    Input(i64),
+    Output(i64),
+    Halt
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EventKind::Input(value) => write!(f, "input {}", value),
+            EventKind::Output(value) => write!(f, "output {}", value),
+            EventKind::Halt => write!(f, "halt")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    // Number of instructions executed before the one that caused the event.
+    pub instruction: u64,
+    pub address: i64,
+    pub kind: EventKind
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at instruction {} (address {})", self.kind, self.instruction, self.address)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Session {
+    pub events: Vec<Event>
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    pub fn inputs(&self) -> Vec<i64> {
+        self.events.iter().filter_map(|event| match event.kind {
+            EventKind::Input(value) => Some(value),
+            _ => None
+        }).collect()
+    }
+
+    pub fn outputs(&self) -> Vec<i64> {
+        self.events.iter().filter_map(|event| match event.kind {
+            EventKind::Output(value) => Some(value),
+            _ => None
+        }).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    UnsupportedVersion(String),
+    Parse { line: usize, message: String }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::Io(error) => write!(f, "{}", error),
+            SessionError::UnsupportedVersion(version) =>
+                write!(f, "Unsupported session version '{}' (expected {})", version, SESSION_VERSION),
+            SessionError::Parse { line, message } => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(error: io::Error) -> SessionError {
+        SessionError::Io(error)
+    }
+}
+
+pub fn format_session(session: &Session) -> String {
+    let mut text = format!("{} {}\n", SESSION_MAGIC, SESSION_VERSION);
+    for event in &session.events {
+        text.push_str(&format!("{} {} {}\n", event.instruction, event.address, event.kind));
+    }
+    text
+}
+
+fn parse_error(line: usize, message: String) -> SessionError {
+    SessionError::Parse { line, message }
+}
+
+fn parse_number<T: std::str::FromStr>(line: usize, text: Option<&str>) -> Result<T, SessionError> {
+    let text = text.ok_or_else(|| parse_error(line, "Unexpected end of line".to_string()))?;
+    text.parse::<T>().map_err(|_| parse_error(line, format!("Invalid number '{}'", text)))
+}
+
+pub fn parse_session(text: &str) -> Result<Session, SessionError> {
+    let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+
+    match lines.next() {
+        Some((_, header)) if header.starts_with(SESSION_MAGIC) => {
+            let version = header[SESSION_MAGIC.len()..].trim();
+            if version != SESSION_VERSION.to_string() {
+                return Err(SessionError::UnsupportedVersion(version.to_string()));
+            }
+        },
+        _ => return Err(parse_error(1, "Not an Intcode session".to_string()))
+    }
+
+    let mut session = Session::new();
+    for (line, text) in lines {
+        if text.is_empty() {
+            continue;
+        }
+        let mut words = text.split_whitespace();
+        let instruction = parse_number(line, words.next())?;
+        let address = parse_number(line, words.next())?;
+        let kind = match words.next() {
+            Some("input") => EventKind::Input(parse_number(line, words.next())?),
+            Some("output") => EventKind::Output(parse_number(line, words.next())?),
+            Some("halt") => EventKind::Halt,
+            Some(word) => return Err(parse_error(line, format!("Unknown event '{}'", word))),
+            None => return Err(parse_error(line, "Unexpected end of line".to_string()))
+        };
+        if let Some(word) = words.next() {
+            return Err(parse_error(line, format!("Unexpected '{}'", word)));
+        }
+        session.events.push(Event { instruction, address, kind });
+    }
+    Ok(session)
+}
+
+pub fn save_session(session: &Session, path: &str) -> Result<(), SessionError> {
+    fs::write(path, format_session(session))?;
+    Ok(())
+}
+
+pub fn load_session(path: &str) -> Result<Session, SessionError> {
+    parse_session(&fs::read_to_string(path)?)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    pub session: Session,
+    instructions: u64
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    // Number of instructions executed so far. As with run_program_limited(),
+    // stopping to wait for input doesn't count.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+}
+
+// Execute a single instruction like step_program() and log its events.
+// Custom opcodes are supported as long as their handlers only take values
+// from the front of the data queue.
+pub fn step_program_recorded<M: Memory>(program: &mut Program<M>, recorder: &mut Recorder)
+        -> Result<Option<StepResult>, VmError> {
+    let address = program.ip;
+    let opcode = if address >= 0 && address < program.code.len() as i64 {
+        read_opcode(program.memory.read(address))
+    } else {
+        OP_HALT
+    };
+    // The values are gone once the instruction has run.
+    let queued: Vec<i64> = if opcode == OP_INPUT {
+        program.data.front().cloned().into_iter().collect()
+    } else if program.opcodes.custom(opcode).is_some() {
+        program.data.iter().cloned().collect()
+    } else {
+        Vec::new()
+    };
+    let queue_length = program.data.len();
+
+    let result = step_program(program)?;
+    if result == Some(StepResult::NeedInput) {
+        return Ok(result);
+    }
+
+    let pushed = (result == Some(StepResult::Output)) as usize;
+    let consumed = (queue_length + pushed).saturating_sub(program.data.len());
+    let mut log = |kind| recorder.session.events.push(Event { instruction: recorder.instructions, address, kind });
+    for &value in queued.iter().take(consumed) {
+        log(EventKind::Input(value));
+    }
+    match result {
+        Some(StepResult::Output) => log(EventKind::Output(*program.data.back().unwrap())),
+        Some(StepResult::Halt) => log(EventKind::Halt),
+        _ => ()
+    }
+    recorder.instructions += 1;
+    Ok(result)
+}
+
+// Run the program like run_program() while logging its events.
+pub fn run_program_recorded<M: Memory>(program: &mut Program<M>, recorder: &mut Recorder)
+        -> Result<StepResult, VmError> {
+    loop {
+        if let Some(result) = step_program_recorded(program, recorder)? {
+            return Ok(result);
+        }
+    }
+}
+
+// The first point where a replay differs from the recorded session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    // Index of the expected event in the session.
+    pub index: usize,
+    // The instruction where the replay went wrong.
+    pub instruction: u64,
+    pub address: i64,
+    pub expected: Event,
+    // What the instruction did instead. None if it didn't consume input,
+    // produce output or halt, e.g. because it stopped to wait for input.
+    pub actual: Option<EventKind>
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Replay diverged at instruction {} (address {}): expected {}, ",
+            self.instruction, self.address, self.expected)?;
+        match self.actual {
+            Some(kind) => write!(f, "got {}", kind),
+            None => write!(f, "got no input, output or halt")
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    Vm(VmError),
+    Diverged(Divergence)
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Vm(error) => write!(f, "{}", error),
+            ReplayError::Diverged(divergence) => write!(f, "{}", divergence)
+        }
+    }
+}
+
+impl error::Error for ReplayError {}
+
+impl From<VmError> for ReplayError {
+    fn from(error: VmError) -> ReplayError {
+        ReplayError::Vm(error)
+    }
+}
+
+// Run the program from the state the session was recorded in, feeding it the
+// logged input values, until all events have been matched. Output values are
+// removed from the data queue. Returns the number of instructions executed.
+pub fn replay<M: Memory>(program: &mut Program<M>, session: &Session) -> Result<u64, ReplayError> {
+    let mut recorder = Recorder::new();
+    let mut index = 0;
+
+    while index < session.events.len() {
+        let expected = session.events[index];
+        let instruction = recorder.instructions;
+        let address = program.ip;
+        let diverged = |actual| ReplayError::Diverged(Divergence { index, instruction, address, expected, actual });
+
+        match step_program_recorded(program, &mut recorder)? {
+            Some(StepResult::NeedInput) => match expected.kind {
+                EventKind::Input(value) if expected.instruction == instruction => program.data.push_back(value),
+                _ => return Err(diverged(None))
+            },
+            Some(StepResult::Output) => {
+                program.data.pop_back();
+            },
+            _ => ()
+        }
+
+        let recorded = &recorder.session.events;
+        if recorded.len() == index && instruction >= expected.instruction
+                && recorder.instructions > instruction {
+            return Err(diverged(None));
+        }
+        while index < recorded.len().min(session.events.len()) {
+            if recorded[index] != session.events[index] {
+                let expected = session.events[index];
+                return Err(ReplayError::Diverged(Divergence {
+                    index, instruction, address, expected, actual: Some(recorded[index].kind)
+                }));
+            }
+            index += 1;
+        }
+    }
+    Ok(recorder.instructions)
+}
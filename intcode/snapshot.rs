@@ -0,0 +1,199 @@
+// Saving and restoring the complete state of a program.
+//
+// A snapshot is a text file that starts with a version line followed by the
+// registers, the arithmetic mode, the pending data queue, the original code
+// and the non-zero memory cells grouped into runs of consecutive addresses:
+//
+//     intcode-snapshot 2
+//     ip 520
+//     rel_base 3000
+//     arithmetic checked
+//     data 1,2
+//     code 1,2,3,...
+//     memory 0 1,2,3,...
+//     memory 3000 5,7
+//
+// Restoring a snapshot replaces all of the program's state, including its
+// code, so the result doesn't depend on the program it is restored into.
+// Version 1 snapshots have no arithmetic mode and are restored in wrapping
+// mode.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::collections::VecDeque;
+use super::*;
+
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+const SNAPSHOT_MAGIC: &str = "intcode-snapshot";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    UnsupportedVersion(String),
+    Parse { line: usize, message: String }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "{}", error),
+            SnapshotError::UnsupportedVersion(version) =>
+                write!(f, "Unsupported snapshot version '{}' (expected {})", version, SNAPSHOT_VERSION),
+            SnapshotError::Parse { line, message } => write!(f, "line {}: {}", line, message)
+        }
+    }
+}
+
+impl error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> SnapshotError {
+        SnapshotError::Io(error)
+    }
+}
+
+fn arithmetic_name(arithmetic: Arithmetic) -> &'static str {
+    match arithmetic {
+        Arithmetic::Wrapping => "wrapping",
+        Arithmetic::Checked => "checked",
+        Arithmetic::Saturating => "saturating"
+    }
+}
+
+fn join(values: &[i64]) -> String {
+    values.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",")
+}
+
+pub fn format_snapshot<M: Memory>(program: &Program<M>) -> String {
+    let data: Vec<i64> = program.data.iter().cloned().collect();
+    let mut text = format!("{} {}\n", SNAPSHOT_MAGIC, SNAPSHOT_VERSION);
+    text.push_str(&format!("ip {}\n", program.ip));
+    text.push_str(&format!("rel_base {}\n", program.rel_base));
+    text.push_str(&format!("arithmetic {}\n", arithmetic_name(program.arithmetic)));
+    text.push_str(&format!("data {}\n", join(&data)));
+    text.push_str(&format!("code {}\n", join(&program.code)));
+
+    let mut run_start = 0;
+    let mut run: Vec<i64> = Vec::new();
+    for (address, value) in program.memory.cells() {
+        if !run.is_empty() && address != run_start + run.len() as i64 {
+            text.push_str(&format!("memory {} {}\n", run_start, join(&run)));
+            run.clear();
+        }
+        if run.is_empty() {
+            run_start = address;
+        }
+        run.push(value);
+    }
+    if !run.is_empty() {
+        text.push_str(&format!("memory {} {}\n", run_start, join(&run)));
+    }
+    text
+}
+
+fn parse_error(line: usize, message: String) -> SnapshotError {
+    SnapshotError::Parse { line, message }
+}
+
+fn parse_number(line: usize, text: &str) -> Result<i64, SnapshotError> {
+    text.parse::<i64>().map_err(|_| parse_error(line, format!("Invalid number '{}'", text)))
+}
+
+fn parse_list(line: usize, text: &str) -> Result<Vec<i64>, SnapshotError> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',').map(|x| parse_number(line, x.trim())).collect()
+}
+
+// Replace the state of the program with that stored in the snapshot. The
+// program is left unchanged if the snapshot is invalid.
+pub fn restore_snapshot<M: Memory>(program: &mut Program<M>, text: &str) -> Result<(), SnapshotError> {
+    let mut lines = text.lines().enumerate().map(|(index, line)| (index + 1, line.trim()));
+    let mut arithmetic = None;
+
+    match lines.next() {
+        Some((_, header)) if header.starts_with(SNAPSHOT_MAGIC) => {
+            let version = header[SNAPSHOT_MAGIC.len()..].trim();
+            if version != "1" && version != SNAPSHOT_VERSION.to_string() {
+                return Err(SnapshotError::UnsupportedVersion(version.to_string()));
+            }
+            if version == "1" {
+                arithmetic = Some(Arithmetic::Wrapping);
+            }
+        },
+        _ => return Err(parse_error(1, "Not an Intcode snapshot".to_string()))
+    }
+
+    let mut ip = None;
+    let mut rel_base = None;
+    let mut data = None;
+    let mut code = None;
+    let mut cells = Vec::new();
+
+    for (line, text) in lines {
+        if text.is_empty() {
+            continue;
+        }
+        let (key, value) = match text.find(' ') {
+            Some(index) => (&text[..index], text[index + 1..].trim()),
+            None => (text, "")
+        };
+        match key {
+            "ip" => ip = Some(parse_number(line, value)?),
+            "rel_base" => rel_base = Some(parse_number(line, value)?),
+            "arithmetic" => {
+                let mode = [Arithmetic::Wrapping, Arithmetic::Checked, Arithmetic::Saturating].iter()
+                    .find(|&&mode| arithmetic_name(mode) == value)
+                    .ok_or_else(|| parse_error(line, format!("Unknown arithmetic mode '{}'", value)))?;
+                arithmetic = Some(*mode);
+            },
+            "data" => data = Some(parse_list(line, value)?),
+            "code" => code = Some(parse_list(line, value)?),
+            "memory" => {
+                let (start, values) = match value.find(' ') {
+                    Some(index) => (&value[..index], &value[index + 1..]),
+                    None => (value, "")
+                };
+                let start = parse_number(line, start)?;
+                if start < 0 {
+                    return Err(parse_error(line, format!("Negative memory address {}", start)));
+                }
+                for (i, value) in parse_list(line, values)?.into_iter().enumerate() {
+                    cells.push((start + i as i64, value));
+                }
+            },
+            _ => return Err(parse_error(line, format!("Unknown field '{}'", key)))
+        }
+    }
+
+    let missing = |name: &str| parse_error(0, format!("Missing field '{}'", name));
+    let ip = ip.ok_or_else(|| missing("ip"))?;
+    let rel_base = rel_base.ok_or_else(|| missing("rel_base"))?;
+    let arithmetic = arithmetic.ok_or_else(|| missing("arithmetic"))?;
+    let data = data.ok_or_else(|| missing("data"))?;
+    let code = code.ok_or_else(|| missing("code"))?;
+
+    program.ip = ip;
+    program.rel_base = rel_base;
+    program.arithmetic = arithmetic;
+    program.data = data.into_iter().collect::<VecDeque<i64>>();
+    program.code = code;
+    program.memory.clear();
+    for (address, value) in cells {
+        program.memory.write(address, value);
+    }
+    Ok(())
+}
+
+pub fn save_snapshot<M: Memory>(program: &Program<M>, path: &str) -> Result<(), SnapshotError> {
+    fs::write(path, format_snapshot(program))?;
+    Ok(())
+}
+
+pub fn load_snapshot<M: Memory>(program: &mut Program<M>, path: &str) -> Result<(), SnapshotError> {
+    restore_snapshot(program, &fs::read_to_string(path)?)
+}
@@ -0,0 +1,558 @@
+// Search for the input cells that make a program produce a value.
+//
+// Puzzles like day 2 patch a few memory cells ("noun" and "verb") before
+// running the program and ask which values lead to a given result. Solver
+// first executes the program symbolically: the unknown cells hold variables
+// and every computed value is a polynomial in them, e.g.
+//
+//     position 0 = 460800*noun + verb + 337061
+//
+// which is then solved directly instead of running the program for every
+// combination of values. This only works for programs whose control flow and
+// addresses don't depend on the unknowns; for the others Solver falls back to
+// running the program for every combination in worker threads.
+//
+//     let solution = Solver::new(program, 0)
+//         .with_unknown("noun", 1, 0..=99)
+//         .with_unknown("verb", 2, 0..=99)
+//         .solve(19690720);
+//
+// Solutions found symbolically are checked by running the program. The
+// polynomial is evaluated without overflowing, so with checked arithmetic,
+// where overflows fault, both methods return the same results. With wrapping
+// or saturating arithmetic the symbolic method may miss combinations for
+// which the computation overflows. Each run is limited to a number of instructions; values for which the
+// program doesn't halt within the limit are not solutions.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::thread;
+use super::*;
+use super::budget::{Budget, Meter, run_program_limited};
+
+// Number of instructions executed symbolically before giving up.
+const MAX_STEPS: usize = 10_000_000;
+
+// Default number of instructions a run of the program may execute.
+const MAX_INSTRUCTIONS: u64 = 10_000_000;
+
+// Polynomial with integer coefficients in the unknowns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Polynomial {
+    // Coefficients by the exponent of each unknown; zero terms are omitted.
+    terms: BTreeMap<Vec<u32>, i64>,
+    vars: usize
+}
+
+impl Polynomial {
+    pub fn constant(value: i64, vars: usize) -> Polynomial {
+        let mut terms = BTreeMap::new();
+        if value != 0 {
+            terms.insert(vec![0; vars], value);
+        }
+        Polynomial { terms, vars }
+    }
+
+    pub fn variable(index: usize, vars: usize) -> Polynomial {
+        let mut exponents = vec![0; vars];
+        exponents[index] = 1;
+        let mut terms = BTreeMap::new();
+        terms.insert(exponents, 1);
+        Polynomial { terms, vars }
+    }
+
+    pub fn as_constant(&self) -> Option<i64> {
+        match self.terms.iter().next() {
+            None => Some(0),
+            Some((exponents, &value)) if self.terms.len() == 1 && exponents.iter().all(|&e| e == 0) => Some(value),
+            _ => None
+        }
+    }
+
+    // Highest exponent of the unknown.
+    pub fn degree(&self, var: usize) -> u32 {
+        self.terms.keys().map(|exponents| exponents[var]).max().unwrap_or(0)
+    }
+
+    fn add_term(&mut self, exponents: Vec<u32>, value: i64) -> Option<()> {
+        let sum = self.terms.get(&exponents).cloned().unwrap_or(0).checked_add(value)?;
+        if sum == 0 {
+            self.terms.remove(&exponents);
+        } else {
+            self.terms.insert(exponents, sum);
+        }
+        Some(())
+    }
+
+    // Returns None if a coefficient overflows.
+    pub fn checked_add(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut result = self.clone();
+        for (exponents, &value) in &other.terms {
+            result.add_term(exponents.clone(), value)?;
+        }
+        Some(result)
+    }
+
+    pub fn checked_mul(&self, other: &Polynomial) -> Option<Polynomial> {
+        let mut result = Polynomial::constant(0, self.vars);
+        for (a_exponents, &a) in &self.terms {
+            for (b_exponents, &b) in &other.terms {
+                let exponents = a_exponents.iter().zip(b_exponents).map(|(x, y)| x + y).collect();
+                result.add_term(exponents, a.checked_mul(b)?)?;
+            }
+        }
+        Some(result)
+    }
+
+    // Returns None on overflow.
+    pub fn evaluate(&self, values: &[i64]) -> Option<i64> {
+        let mut sum: i64 = 0;
+        for (exponents, &coefficient) in &self.terms {
+            let mut term = coefficient;
+            for (&value, &exponent) in values.iter().zip(exponents) {
+                term = term.checked_mul(value.checked_pow(exponent)?)?;
+            }
+            sum = sum.checked_add(term)?;
+        }
+        Some(sum)
+    }
+
+    // Split a polynomial of degree 1 in the unknown into (a, b) such that it
+    // equals a*var + b.
+    fn split_linear(&self, var: usize) -> (Polynomial, Polynomial) {
+        let mut a = Polynomial::constant(0, self.vars);
+        let mut b = Polynomial::constant(0, self.vars);
+        for (exponents, &value) in &self.terms {
+            if exponents[var] == 0 {
+                b.terms.insert(exponents.clone(), value);
+            } else {
+                let mut exponents = exponents.clone();
+                exponents[var] = 0;
+                a.terms.insert(exponents, value);
+            }
+        }
+        (a, b)
+    }
+
+    // Format the polynomial using the names of the unknowns, highest degree
+    // terms first: "3*noun^2 - verb + 5".
+    pub fn format(&self, names: &[String]) -> String {
+        let mut terms: Vec<(&Vec<u32>, i64)> = self.terms.iter().map(|(e, &v)| (e, v)).collect();
+        terms.sort_by(|a, b| {
+            let degree = |exponents: &Vec<u32>| exponents.iter().sum::<u32>();
+            degree(b.0).cmp(&degree(a.0)).then(b.0.cmp(a.0))
+        });
+
+        let mut text = String::new();
+        for (exponents, value) in terms {
+            let factors: Vec<String> = exponents.iter().zip(names)
+                .filter(|&(&exponent, _)| exponent > 0)
+                .map(|(&exponent, name)| match exponent {
+                    1 => name.clone(),
+                    _ => format!("{}^{}", name, exponent)
+                })
+                .collect();
+            let term = match (value.unsigned_abs(), factors.is_empty()) {
+                (magnitude, true) => magnitude.to_string(),
+                (1, false) => factors.join("*"),
+                (magnitude, false) => format!("{}*{}", magnitude, factors.join("*"))
+            };
+            match (text.is_empty(), value < 0) {
+                (true, true) => text.push('-'),
+                (true, false) => {},
+                (false, true) => text.push_str(" - "),
+                (false, false) => text.push_str(" + ")
+            }
+            text.push_str(&term);
+        }
+        if text.is_empty() {
+            text.push('0');
+        }
+        text
+    }
+}
+
+// Reason why a program can't be executed symbolically.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SymbolicError {
+    pub address: i64,
+    pub message: String
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "address {}: {}", self.address, self.message)
+    }
+}
+
+impl error::Error for SymbolicError {}
+
+// Values that can't be expressed as a polynomial, such as those read from an
+// address that depends on the unknowns, are only a problem if they are used.
+#[derive(Clone, Debug)]
+enum Value {
+    Known(Polynomial),
+    Opaque(String)
+}
+
+struct Machine {
+    memory: HashMap<i64, Value>,
+    ip: i64,
+    rel_base: i64,
+    vars: usize
+}
+
+impl Machine {
+    fn error(&self, message: String) -> SymbolicError {
+        SymbolicError { address: self.ip, message }
+    }
+
+    fn read(&self, address: i64) -> Value {
+        self.memory.get(&address).cloned().unwrap_or_else(|| Value::Known(Polynomial::constant(0, self.vars)))
+    }
+
+    fn constant(&self, value: Value, what: &str) -> Result<i64, SymbolicError> {
+        match value {
+            Value::Known(polynomial) => polynomial.as_constant()
+                .ok_or_else(|| self.error(format!("{} depends on the unknowns", what))),
+            Value::Opaque(reason) => Err(self.error(format!("{} is unknown: {}", what, reason)))
+        }
+    }
+
+    fn address(&self, instr: i64, index: u32) -> Result<Option<i64>, SymbolicError> {
+        let param = self.read(self.ip + 1 + index as i64);
+        let base = match read_param_mode(instr, index) {
+            MODE_POS => 0,
+            MODE_REL => self.rel_base,
+            MODE_IMM => return Ok(None),
+            mode => return Err(self.error(format!("Invalid mode {} for parameter {}", mode, index + 1)))
+        };
+        match param {
+            Value::Known(polynomial) => match polynomial.as_constant() {
+                Some(address) if address + base < 0 =>
+                    Err(self.error(format!("Access to negative address {}", address + base))),
+                Some(address) => Ok(Some(address + base)),
+                None => Ok(None)
+            },
+            Value::Opaque(_) => Ok(None)
+        }
+    }
+
+    fn param(&self, instr: i64, index: u32) -> Result<Value, SymbolicError> {
+        match (read_param_mode(instr, index), self.address(instr, index)?) {
+            (MODE_IMM, _) => Ok(self.read(self.ip + 1 + index as i64)),
+            (_, Some(address)) => Ok(self.read(address)),
+            (_, None) => Ok(Value::Opaque(format!("read from an address that depends on the unknowns at {}", self.ip)))
+        }
+    }
+
+    fn target(&self, instr: i64, index: u32) -> Result<i64, SymbolicError> {
+        if read_param_mode(instr, index) == MODE_IMM {
+            return Err(self.error(format!("Write in immediate mode by parameter {}", index + 1)));
+        }
+        match self.address(instr, index)? {
+            Some(address) => Ok(address),
+            None => Err(self.error("Write to an address that depends on the unknowns".to_string()))
+        }
+    }
+
+    fn step(&mut self, code_len: i64) -> Result<bool, SymbolicError> {
+        if self.ip < 0 || self.ip >= code_len {
+            return Err(self.error("Instruction pointer out of range".to_string()));
+        }
+        let instr = self.constant(self.read(self.ip), "Instruction")?;
+        let opcode = read_opcode(instr);
+        let ip = self.ip;
+
+        match opcode {
+            OP_HALT => return Ok(false),
+            OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => {
+                let (a, b) = (self.param(instr, 0)?, self.param(instr, 1)?);
+                let target = self.target(instr, 2)?;
+                let result = match (a, b) {
+                    (Value::Opaque(reason), _) | (_, Value::Opaque(reason)) => Value::Opaque(reason),
+                    (Value::Known(a), Value::Known(b)) => {
+                        let result = match opcode {
+                            OP_ADD => a.checked_add(&b),
+                            OP_MULTIPLY => a.checked_mul(&b),
+                            _ => match (a.as_constant(), b.as_constant()) {
+                                (Some(a), Some(b)) if opcode == OP_LESS_THAN => Some((a < b) as i64),
+                                (Some(a), Some(b)) => Some((a == b) as i64),
+                                _ if a == b => Some((opcode == OP_EQUALS) as i64),
+                                _ => None
+                            }.map(|value| Polynomial::constant(value, self.vars))
+                        };
+                        match result {
+                            Some(result) => Value::Known(result),
+                            None if opcode == OP_ADD || opcode == OP_MULTIPLY =>
+                                Value::Opaque(format!("overflow at {}", ip)),
+                            None => Value::Opaque(format!("comparison of unknowns at {}", ip))
+                        }
+                    }
+                };
+                self.memory.insert(target, result);
+                self.ip += 4;
+            },
+            OP_INPUT => return Err(self.error("Input is not supported".to_string())),
+            OP_OUTPUT => {
+                self.param(instr, 0)?;
+                self.ip += 2;
+            },
+            OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => {
+                let condition = self.param(instr, 0)?;
+                let condition = self.constant(condition, "Jump condition")?;
+                let target = self.param(instr, 1)?;
+                let target = self.constant(target, "Jump target")?;
+                if (opcode == OP_JUMP_IF_TRUE) == (condition != 0) {
+                    self.ip = target;
+                } else {
+                    self.ip += 3;
+                }
+            },
+            OP_REL_BASE_OFFSET => {
+                let offset = self.param(instr, 0)?;
+                self.rel_base += self.constant(offset, "Relative base offset")?;
+                self.ip += 2;
+            },
+            _ => return Err(self.error(format!("Invalid opcode {}", opcode)))
+        }
+        Ok(true)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unknown {
+    pub name: String,
+    pub address: i64,
+    pub values: RangeInclusive<i64>
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Method {
+    Symbolic(Polynomial),
+    // Symbolic execution failed for this reason.
+    BruteForce(SymbolicError)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    pub method: Method,
+    // Values of the unknowns, in the order they were added, sorted.
+    pub inputs: Vec<Vec<i64>>
+}
+
+pub struct Solver {
+    program: Program,
+    target: i64,
+    unknowns: Vec<Unknown>,
+    threads: usize,
+    max_instructions: u64
+}
+
+// Number of values in the range, None if it doesn't fit in a u64.
+fn range_size(range: &RangeInclusive<i64>) -> Option<u64> {
+    if range.is_empty() {
+        Some(0)
+    } else {
+        (range.end().wrapping_sub(*range.start()) as u64).checked_add(1)
+    }
+}
+
+// Number of combinations, None if there are too many to count.
+fn combination_count(ranges: &[&RangeInclusive<i64>]) -> Option<u64> {
+    ranges.iter().try_fold(1u64, |count, range| count.checked_mul(range_size(range)?))
+}
+
+// Values of the combination with the specified index; the last range varies
+// fastest.
+fn combination(ranges: &[&RangeInclusive<i64>], mut index: u64) -> Vec<i64> {
+    let mut values = vec![0; ranges.len()];
+    for (value, range) in values.iter_mut().zip(ranges).rev() {
+        let size = range_size(range).unwrap();
+        *value = range.start().wrapping_add((index % size) as i64);
+        index /= size;
+    }
+    values
+}
+
+impl Solver {
+    // Solve for the value at the target address when the program halts.
+    pub fn new(program: Program, target: i64) -> Solver {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Solver { program, target, unknowns: Vec::new(), threads, max_instructions: MAX_INSTRUCTIONS }
+    }
+
+    pub fn with_unknown(mut self, name: &str, address: i64, values: RangeInclusive<i64>) -> Solver {
+        self.unknowns.push(Unknown { name: name.to_string(), address, values });
+        self
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Solver {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // Limit the number of instructions each run of the program may execute.
+    pub fn with_max_instructions(mut self, max_instructions: u64) -> Solver {
+        self.max_instructions = max_instructions;
+        self
+    }
+
+    pub fn unknowns(&self) -> &[Unknown] {
+        &self.unknowns
+    }
+
+    // Number of combinations of values of the unknowns, None if there are too
+    // many to count, let alone to try.
+    pub fn combinations(&self) -> Option<u64> {
+        let ranges: Vec<&RangeInclusive<i64>> = self.unknowns.iter().map(|unknown| &unknown.values).collect();
+        combination_count(&ranges)
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.unknowns.iter().map(|unknown| unknown.name.clone()).collect()
+    }
+
+    // Execute the program symbolically and return the value at the target
+    // address in terms of the unknowns.
+    pub fn expression(&self) -> Result<Polynomial, SymbolicError> {
+        let vars = self.unknowns.len();
+        let mut machine = Machine {
+            memory: self.program.memory.cells().into_iter()
+                .map(|(address, value)| (address, Value::Known(Polynomial::constant(value, vars))))
+                .collect(),
+            ip: self.program.ip,
+            rel_base: self.program.rel_base,
+            vars
+        };
+        for (index, unknown) in self.unknowns.iter().enumerate() {
+            machine.memory.insert(unknown.address, Value::Known(Polynomial::variable(index, vars)));
+        }
+
+        let mut steps = 0;
+        while machine.step(self.program.code.len() as i64)? {
+            steps += 1;
+            if steps == MAX_STEPS {
+                return Err(machine.error(format!("Program didn't halt after {} instructions", MAX_STEPS)));
+            }
+        }
+        let result = machine.read(self.target);
+        match result {
+            Value::Known(polynomial) => Ok(polynomial),
+            Value::Opaque(reason) => Err(machine.error(format!("Result is unknown: {}", reason)))
+        }
+    }
+
+    // Run the program with the unknowns set to the values and check the
+    // result. Programs that fault, wait for input or run out of instructions
+    // have no result.
+    pub fn check(&self, values: &[i64], value: i64) -> bool {
+        let mut program = self.program.clone();
+        for (unknown, &x) in self.unknowns.iter().zip(values) {
+            program.memory.write(unknown.address, x);
+        }
+        let mut meter = Meter::new(Budget::new().with_instructions(self.max_instructions));
+        loop {
+            match run_program_limited(&mut program, &mut meter) {
+                Ok(StepResult::Halt) => return program.memory.read(self.target) == value,
+                Ok(StepResult::Output) => {
+                    program.data.pop_back();
+                },
+                _ => return false
+            }
+        }
+    }
+
+    pub fn solve(&self, value: i64) -> Solution {
+        match self.expression() {
+            Ok(polynomial) => {
+                let inputs = self.solve_expression(&polynomial, value);
+                Solution { method: Method::Symbolic(polynomial), inputs }
+            },
+            Err(error) => Solution { method: Method::BruteForce(error), inputs: self.solve_brute_force(value) }
+        }
+    }
+
+    // Find the values of the unknowns for which the polynomial equals the
+    // value. If it has degree 1 in some unknown, only the combinations of
+    // the others are enumerated and that one is computed. Nothing is found if
+    // there are too many combinations to count.
+    fn solve_expression(&self, polynomial: &Polynomial, value: i64) -> Vec<Vec<i64>> {
+        let var = (0..self.unknowns.len())
+            .filter(|&var| polynomial.degree(var) == 1)
+            .max_by_key(|&var| combination_count(&[&self.unknowns[var].values]).unwrap_or(u64::MAX));
+        let ranges: Vec<&RangeInclusive<i64>> = self.unknowns.iter().enumerate()
+            .filter(|&(index, _)| Some(index) != var)
+            .map(|(_, unknown)| &unknown.values)
+            .collect();
+        let mut candidates = Vec::new();
+
+        for index in 0..combination_count(&ranges).unwrap_or(0) {
+            let mut values = combination(&ranges, index);
+            let var = match var {
+                Some(var) => var,
+                None => {
+                    if polynomial.evaluate(&values) == Some(value) {
+                        candidates.push(values);
+                    }
+                    continue;
+                }
+            };
+            values.insert(var, 0);
+            let (a, b) = polynomial.split_linear(var);
+            let (a, b) = match (a.evaluate(&values), b.evaluate(&values)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue
+            };
+            let range = &self.unknowns[var].values;
+            if a == 0 {
+                if b == value {
+                    for x in range.clone() {
+                        values[var] = x;
+                        candidates.push(values.clone());
+                    }
+                }
+            } else if let Some(difference) = value.checked_sub(b) {
+                if difference.checked_rem(a) == Some(0) {
+                    match difference.checked_div(a) {
+                        Some(x) if range.contains(&x) => {
+                            values[var] = x;
+                            candidates.push(values);
+                        },
+                        _ => ()
+                    }
+                }
+            }
+        }
+
+        candidates.retain(|values| self.check(values, value));
+        candidates.sort();
+        candidates
+    }
+
+    // Run the program for every combination of values of the unknowns.
+    // Nothing is found if there are too many combinations to count, see
+    // combinations().
+    pub fn solve_brute_force(&self, value: i64) -> Vec<Vec<i64>> {
+        let ranges: Vec<&RangeInclusive<i64>> = self.unknowns.iter().map(|unknown| &unknown.values).collect();
+        let count = combination_count(&ranges).unwrap_or(0);
+        let threads = (self.threads as u64).min(count).max(1);
+
+        let mut inputs: Vec<Vec<i64>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|worker| {
+                let ranges = &ranges;
+                scope.spawn(move || {
+                    (worker..count).step_by(threads as usize)
+                        .map(|index| combination(ranges, index))
+                        .filter(|values| self.check(values, value))
+                        .collect::<Vec<Vec<i64>>>()
+                })
+            }).collect();
+            workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+        });
+        inputs.sort();
+        inputs
+    }
+}
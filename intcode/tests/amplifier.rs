@@ -0,0 +1,63 @@
+use intcode::*;
+use intcode::amplifier::*;
+
+fn day7_program() -> Program {
+    load_program(&format!("{}/../7/input.txt", env!("CARGO_MANIFEST_DIR")))
+}
+
+#[test]
+fn phase_permutations() {
+    assert_eq!(permutations(&[1, 2, 3], 2).collect::<Vec<_>>(), vec![
+        vec![1, 2], vec![1, 3], vec![2, 1], vec![2, 3], vec![3, 1], vec![3, 2]
+    ]);
+    assert_eq!(permutations(&[0, 1, 2, 3, 4], 5).count(), 120);
+    assert_eq!(permutations(&[1, 2], 0).collect::<Vec<_>>(), vec![Vec::<i64>::new()]);
+    assert_eq!(permutations(&[1, 2], 3).next(), None);
+
+    // Too many to store or even count.
+    let values: Vec<i64> = (0..30).collect();
+    assert_eq!(permutation_count(30, 30), None);
+    assert_eq!(permutation_count(30, 3), Some(24360));
+    let mut permutations = permutations(&values, 30);
+    assert_eq!(permutations.next(), Some(values.clone()));
+    let second = permutations.next().unwrap();
+    assert_eq!(second[28..], [29, 28]);
+    assert_eq!(nth_permutation(&values, 3, 24359), vec![29, 28, 27]);
+}
+
+#[test]
+fn phase_search() {
+    let results = PhaseSearch::new(day7_program(), (0..=4).collect()).run().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].signal, 30940);
+
+    let search = PhaseSearch::new(day7_program(), (5..=9).collect()).with_feedback().with_top(5);
+    let results = search.run().unwrap();
+    assert_eq!(results[0], PhaseResult { signal: 76211147, phases: vec![8, 9, 6, 7, 5] });
+    assert_eq!(results.len(), 5);
+
+    // The results don't depend on the number of threads and match a sequential
+    // evaluation of every candidate.
+    let program = day7_program();
+    let mut expected: Vec<PhaseResult> = permutations(&[5, 6, 7, 8, 9], 5)
+        .map(|phases| PhaseResult { signal: run_chain(&program, &phases, true).unwrap().unwrap(), phases })
+        .collect();
+    expected.sort_by(|a, b| b.signal.cmp(&a.signal).then_with(|| a.phases.cmp(&b.phases)));
+    expected.truncate(5);
+    assert_eq!(results, expected);
+    assert_eq!(search.with_threads(1).run().unwrap(), expected);
+
+    let search = PhaseSearch::new(day7_program(), vec![0, 2, 4, 6]).with_length(3).with_top(2);
+    assert_eq!(search.candidates(), Some(24));
+    assert_eq!(search.with_length(5).candidates(), Some(0));
+    assert_eq!(PhaseSearch::new(day7_program(), (0..25).collect()).candidates(), None);
+    let results = PhaseSearch::new(day7_program(), vec![0, 2, 4, 6]).with_length(3).with_top(2).run().unwrap();
+    assert_eq!(format_results_json(&results), "[{\"signal\":572,\"phases\":[0,4,2]},{\"signal\":570,\"phases\":[0,2,4]}]");
+}
+
+#[test]
+fn phase_search_errors() {
+    let program = Program::new(parse_code("3,0,3,1,42"));
+    assert_eq!(PhaseSearch::new(program, vec![1, 2]).run(),
+        Err(VmError::InvalidOpcode { address: 4, instr: 42, opcode: 42 }));
+}
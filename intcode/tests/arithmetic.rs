@@ -0,0 +1,105 @@
+use intcode::*;
+use intcode::big::*;
+use intcode::cache::{DecodeCache, run_program_cached};
+
+// 3^100 computed by repeated multiplication.
+const POWER_PROGRAM: &str = "1002,20,3,20,1001,21,1,21,1007,21,100,22,1005,22,0,4,20,99,0,0,1,0,0";
+const POWER_RESULT: &str = "515377520732011331036461129765621272702107522001";
+
+fn run_modes(code: &str) -> Vec<(Result<StepResult, VmError>, Program)> {
+    [Arithmetic::Wrapping, Arithmetic::Checked, Arithmetic::Saturating].iter()
+        .map(|&arithmetic| {
+            let mut program = Program::new(parse_code(code)).with_arithmetic(arithmetic);
+            let mut cached = program.clone();
+            let result = run_program(&mut program);
+            assert_eq!(run_program_cached(&mut cached, &mut DecodeCache::new()), result);
+            assert_eq!(cached.memory.cells(), program.memory.cells());
+            assert_eq!(cached.rel_base, program.rel_base);
+            (result, program)
+        })
+        .collect()
+}
+
+#[test]
+fn arithmetic_modes() {
+    let results = run_modes("1102,9223372036854775807,2,5,99,0");
+    assert_eq!(results[0].0, Ok(StepResult::Halt));
+    assert_eq!(results[0].1.memory.read(5), -2);
+    assert_eq!(results[1].0, Err(VmError::Overflow { address: 0, instr: 1102, opcode: 2 }));
+    assert_eq!(results[1].1.ip, 0);
+    assert_eq!(results[1].1.memory.read(5), 0);
+    assert_eq!(results[2].1.memory.read(5), i64::MAX);
+
+    let results = run_modes("1101,-9223372036854775807,-2,5,99,0");
+    assert_eq!(results[0].1.memory.read(5), i64::MAX);
+    assert_eq!(results[1].0, Err(VmError::Overflow { address: 0, instr: 1101, opcode: 1 }));
+    assert_eq!(results[2].1.memory.read(5), i64::MIN);
+
+    let results = run_modes("109,9223372036854775807,109,1,99");
+    assert_eq!(results[0].1.rel_base, i64::MIN);
+    assert_eq!(results[1].0, Err(VmError::Overflow { address: 2, instr: 109, opcode: 9 }));
+    assert_eq!(results[1].1.rel_base, i64::MAX);
+    assert_eq!(results[2].1.rel_base, i64::MAX);
+
+    // Relative mode addresses past i64::MAX.
+    let results = run_modes("109,9223372036854775800,204,100,99");
+    assert!(matches!(results[0].0, Err(VmError::NegativeAddress { address: 2, .. })));
+    assert_eq!(results[1].0, Err(VmError::Overflow { address: 2, instr: 204, opcode: 4 }));
+    assert_eq!(results[2].0, Ok(StepResult::Output));
+    assert_eq!(results[2].1.data.back(), Some(&0));
+    let results = run_modes("109,9223372036854775800,21101,1,2,100,99");
+    assert_eq!(results[1].0, Err(VmError::Overflow { address: 2, instr: 21101, opcode: 1 }));
+    assert_eq!(results[2].1.memory.read(i64::MAX), 3);
+
+    let results = run_modes(POWER_PROGRAM);
+    assert_eq!(results[1].0, Err(VmError::Overflow { address: 0, instr: 1002, opcode: 2 }));
+    assert_eq!(results[1].1.memory.read(21), 39);
+}
+
+#[test]
+fn big_integers() {
+    let parse = |text: &str| text.parse::<BigInt>().unwrap();
+    for text in ["0", "-1", "4294967296", "-9223372036854775808", POWER_RESULT].iter() {
+        assert_eq!(parse(text).to_string(), *text);
+    }
+    assert_eq!(parse("+7"), BigInt::from(7));
+    assert_eq!(parse("-0"), BigInt::from(0));
+    assert!("12a".parse::<BigInt>().is_err());
+    assert!("-".parse::<BigInt>().is_err());
+
+    let max = BigInt::from(i64::MAX);
+    assert_eq!((&max * &max).to_string(), "85070591730234615847396907784232501249");
+    assert_eq!((&-&max * &max).to_string(), "-85070591730234615847396907784232501249");
+    assert_eq!((&parse("-18446744073709551616") + &BigInt::from(5)).to_string(), "-18446744073709551611");
+    assert_eq!(&max + &-&max, BigInt::from(0));
+    assert_eq!((&max + &BigInt::from(1)).to_i64(), None);
+    assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+    assert_eq!((&BigInt::from(i64::MIN) + &BigInt::from(-1)).to_i64(), None);
+    assert!(parse("-5") < parse("3"));
+    assert!(parse("-5") < parse("-3"));
+    assert!(parse("18446744073709551616") > max);
+}
+
+#[test]
+fn big_program() {
+    let mut program = BigProgram::new(parse_big_code(POWER_PROGRAM).unwrap());
+    assert_eq!(run_big_program(&mut program), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_front().unwrap().to_string(), POWER_RESULT);
+    assert_eq!(run_big_program(&mut program), Ok(StepResult::Halt));
+
+    // Same results as the i64 VM on the day 9 BOOST program.
+    let text = std::fs::read_to_string(format!("{}/../9/input.txt", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    for &input in [1, 2].iter() {
+        let mut program = BigProgram::new(parse_big_code(&text).unwrap());
+        program.data.push_back(BigInt::from(input));
+        let mut expected = Program::new(parse_code(&text));
+        expected.data.push_back(input);
+        assert_eq!(run_big_program(&mut program), run_program(&mut expected));
+        assert_eq!(program.data.pop_front().unwrap().to_i64(), expected.data.pop_front());
+    }
+
+    let mut program = BigProgram::new(parse_big_code("1105,1,18446744073709551616").unwrap());
+    assert_eq!(run_big_program(&mut program), Err(VmError::Overflow { address: 0, instr: 1105, opcode: 5 }));
+    let mut program = BigProgram::new(parse_big_code("18446744073709551699").unwrap());
+    assert_eq!(run_big_program(&mut program), Err(VmError::Overflow { address: 0, instr: i64::MAX, opcode: 99 }));
+}
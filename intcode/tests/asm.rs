@@ -0,0 +1,102 @@
+use intcode::*;
+use intcode::asm::assemble;
+use std::collections::BTreeMap;
+use intcode::disasm::*;
+
+const PUZZLE_DAYS: [&str; 6] = ["2", "5", "7", "9", "11", "13"];
+
+fn puzzle_input(day: &str) -> Vec<i64> {
+    load_code(&format!("{}/../{}/input.txt", env!("CARGO_MANIFEST_DIR"), day))
+}
+
+#[test]
+fn disasm_round_trip() {
+    for day in PUZZLE_DAYS.iter() {
+        let code = puzzle_input(day);
+        let listing = disassemble(&code);
+        assert_eq!(assemble(&listing).unwrap(), code, "day {}", day);
+        let listing = disassemble_recursive(&code);
+        assert_eq!(assemble(&listing).unwrap(), code, "day {}", day);
+        let listing = disassemble_symbolic(&code, &BTreeMap::new());
+        assert_eq!(assemble(&listing).unwrap(), code, "day {}", day);
+    }
+}
+
+#[test]
+fn symbolic_disasm() {
+    let code = load_code(&format!("{}/../7/test_input_4.txt", env!("CARGO_MANIFEST_DIR")));
+    let symbols = parse_symbols("26 r1\n27 r2 ; second input\n\n28 r3\n6 loop").unwrap();
+    let listing = disassemble_symbolic(&code, &symbols);
+    assert!(listing.contains("0002:     [r1] = add [r1] -4\n"));
+    assert!(listing.contains("loop:\n0006:     input [r2]\n"));
+    assert!(listing.contains("0022:     jump_if_true [r3] loop\n"));
+    assert!(listing.contains("r3:\n0028:     .data 5\n"));
+    assert_eq!(assemble(&listing).unwrap(), code);
+
+    let listing = disassemble_symbolic(&puzzle_input("11"), &BTreeMap::new());
+    assert!(listing.contains("0002:     jump_if_true [v8] L_0310\n"));
+    assert!(listing.contains("0021:     [v10] = add [v10] 1\n"));
+
+    assert!(parse_symbols("x 1").is_err());
+    assert!(parse_symbols("1 a b").is_err());
+}
+
+#[test]
+fn recursive_disasm_skips_data() {
+    let code = puzzle_input("11");
+    let analysis = analyze(&code);
+    assert!(analysis.instructions.contains_key(&5));
+    assert!(!analysis.instructions.contains_key(&8));
+    assert!(analysis.instructions.contains_key(&11));
+    assert!(analysis.jump_targets.contains(&310));
+    assert_eq!(analysis.code_writes.get(&33), Some(&27));
+}
+
+#[test]
+fn recursive_disasm_self_modified_code() {
+    // Day 5 turns the word at 6 into an add or a jump depending on the input.
+    let code = puzzle_input("5");
+    let analysis = analyze(&code);
+    assert_eq!(analysis.self_modified.get(&6), Some(&4));
+    assert_eq!(analysis.code_writes.get(&2), Some(&6));
+    assert!(analysis.instructions.contains_key(&10));
+    assert!(analysis.instructions.contains_key(&222));
+    assert!(analysis.jump_targets.contains(&238));
+    assert!(analysis.instructions.contains_key(&238));
+
+    let listing = disassemble_symbolic(&code, &BTreeMap::new());
+    assert!(listing.contains("0006:     .data 1100   ; modified at run time\n0007:     .data 1\n"));
+    assert!(listing.contains("0010:     output 0\n"));
+    assert_eq!(assemble(&listing).unwrap(), code);
+}
+
+#[test]
+fn labels_data_and_comments() {
+    let source = "
+        ; echo input values until a zero is read
+        loop:   input [value]
+                output [value]
+                jump_if_true [value] loop   ; loop while non-zero
+                halt
+        value:  .data 0
+    ";
+    let code = assemble(source).unwrap();
+    assert_eq!(code, vec![3, 8, 4, 8, 1005, 8, 0, 99, 0]);
+
+    let mut program = Program::new(code);
+    for &value in &[5, 0] {
+        program.data.push_back(value);
+        assert_eq!(run_program(&mut program), Ok(StepResult::Output));
+        assert_eq!(program.data.pop_front(), Some(value));
+    }
+    assert_eq!(run_program(&mut program), Ok(StepResult::Halt));
+}
+
+#[test]
+fn relative_mode_and_errors() {
+    assert_eq!(assemble("add [$1] 2 [$-3]").unwrap(), vec![21201, 1, 2, -3]);
+    assert_eq!(assemble("\nfoo [1]").unwrap_err().line, 2);
+    assert!(assemble("jump_if_true 1 nowhere").is_err());
+    assert!(assemble("add 1 2").is_err());
+    assert!(assemble("x: .data 1\nx: .data 2").is_err());
+}
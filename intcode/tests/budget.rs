@@ -0,0 +1,86 @@
+use std::time::Duration;
+use intcode::*;
+use intcode::asm::assemble;
+use intcode::budget::{Budget, Limit, Meter, run_program_limited};
+
+const ECHO: &str = "
+        input [x]
+        output [x]
+        halt
+x:      .data 0
+";
+
+// Writes to a new cell far away on every iteration.
+const SPRAY: &str = "
+        rel_base_offset 1000
+loop:   [$0] = add 0 1
+        rel_base_offset 1000000
+        jump_if_true 1 loop
+";
+
+#[test]
+fn counts_executed_instructions() {
+    let mut program = Program::new(assemble(ECHO).unwrap());
+    let mut meter = Meter::new(Budget::new().with_instructions(3));
+    assert_eq!(run_program_limited(&mut program, &mut meter), Ok(StepResult::NeedInput));
+    assert_eq!(meter.instructions(), 0);
+    program.data.push_back(5);
+    assert_eq!(run_program_limited(&mut program, &mut meter), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_back(), Some(5));
+    assert_eq!(run_program_limited(&mut program, &mut meter), Ok(StepResult::Halt));
+    assert_eq!(meter.instructions(), 3);
+}
+
+#[test]
+fn instruction_limit() {
+    let mut program = Program::new(assemble("loop: jump_if_true 1 loop").unwrap());
+    let mut meter = Meter::new(Budget::new().with_instructions(1000));
+    let result = run_program_limited(&mut program, &mut meter);
+    assert_eq!(result, Err(VmError::BudgetExceeded { address: 0, limit: Limit::Instructions }));
+    assert_eq!(result.unwrap_err().to_string(), "Budget exceeded (instruction limit) at address 0");
+    assert_eq!(meter.instructions(), 1000);
+
+    // Raise the limit and resume.
+    meter.budget.instructions = Some(1500);
+    assert!(run_program_limited(&mut program, &mut meter).is_err());
+    assert_eq!(meter.instructions(), 1500);
+}
+
+#[test]
+fn memory_limit() {
+    let code = assemble(SPRAY).unwrap();
+    let mut program = Program::with_memory(code, SparseMemory::new());
+    let mut meter = Meter::new(Budget::new().with_memory_cells(10));
+    let result = run_program_limited(&mut program, &mut meter);
+    assert_eq!(result, Err(VmError::BudgetExceeded { address: 2, limit: Limit::MemoryCells }));
+    assert_eq!(meter.memory_cells(), 10);
+    assert_eq!(program.rel_base, 10_001_000);
+    let code_len = program.code.len() as i64;
+    assert_eq!(program.memory.cells().iter().filter(|&&(address, _)| address >= code_len).count(), 10);
+
+    meter.budget.memory_cells = Some(20);
+    assert!(run_program_limited(&mut program, &mut meter).is_err());
+    assert_eq!(meter.memory_cells(), 20);
+    assert_eq!(program.memory.read(19_001_000), 1);
+    assert_eq!(program.memory.read(20_001_000), 0);
+
+    // Writes to the code itself don't count.
+    let mut program = load_program(concat!(env!("CARGO_MANIFEST_DIR"), "/../2/input.txt"));
+    let mut meter = Meter::new(Budget::new().with_memory_cells(0));
+    assert_eq!(run_program_limited(&mut program, &mut meter), Ok(StepResult::Halt));
+}
+
+#[test]
+fn time_limit() {
+    let mut program = Program::new(assemble("loop: jump_if_true 1 loop").unwrap());
+    let mut meter = Meter::new(Budget::new().with_time(Duration::from_millis(50)));
+    let result = run_program_limited(&mut program, &mut meter);
+    assert_eq!(result, Err(VmError::BudgetExceeded { address: 0, limit: Limit::Time }));
+    assert!(meter.elapsed() >= Duration::from_millis(50));
+    assert!(meter.instructions() > 0);
+
+    // The time already spent counts against the limit.
+    assert!(run_program_limited(&mut program, &mut meter).is_err());
+    meter.reset();
+    assert_eq!((meter.instructions(), meter.elapsed()), (0, Duration::default()));
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use intcode::*;
+use intcode::cache::*;
+use intcode::opcodes::{Effect, OpcodeSet};
+
+fn puzzle_input(day: &str) -> Vec<i64> {
+    load_code(&format!("{}/../{}/input.txt", env!("CARGO_MANIFEST_DIR"), day))
+}
+
+// Everything observable about a run: outputs, how it ended and the final state.
+#[derive(Debug, PartialEq)]
+struct Run {
+    outputs: Vec<i64>,
+    result: Result<StepResult, VmError>,
+    ip: i64,
+    rel_base: i64,
+    memory: Vec<(i64, i64)>
+}
+
+// Run the program until it halts or fails, computing each input value from the
+// outputs so far.
+fn run<F: Fn(&[i64]) -> i64>(mut program: Program, input: F, cached: bool) -> Run {
+    let mut cache = DecodeCache::new();
+    let mut outputs = Vec::new();
+    let result = loop {
+        let result = if cached {
+            run_program_cached(&mut program, &mut cache)
+        } else {
+            run_program(&mut program)
+        };
+        match result {
+            Ok(StepResult::Output) => outputs.push(program.data.pop_front().unwrap()),
+            Ok(StepResult::NeedInput) => program.data.push_back(input(&outputs)),
+            _ => break result
+        }
+    };
+    Run { outputs, result, ip: program.ip, rel_base: program.rel_base, memory: program.memory.cells() }
+}
+
+fn assert_conforms<F: Fn(&[i64]) -> i64>(program: Program, input: F) -> Run {
+    let expected = run(program.clone(), &input, false);
+    assert_eq!(run(program, &input, true), expected);
+    expected
+}
+
+// Track the ball with the paddle.
+fn breakout_input(outputs: &[i64]) -> i64 {
+    let position = |tile| outputs.chunks_exact(3).rev()
+        .find(|t| t[2] == tile && t[0] >= 0)
+        .map_or(0, |t| t[0]);
+    (position(4) - position(3)).signum()
+}
+
+#[test]
+fn puzzle_conformance() {
+    let mut program = Program::new(puzzle_input("2"));
+    program.memory.write(1, 12);
+    program.memory.write(2, 2);
+    assert_eq!(assert_conforms(program, |_| 0).memory[0], (0, 4023471));
+
+    for &input in [1, 5].iter() {
+        assert_conforms(Program::new(puzzle_input("5")), |_| input);
+    }
+    assert_conforms(Program::new(puzzle_input("7")), |outputs| outputs.len() as i64);
+    for &input in [1, 2].iter() {
+        assert_conforms(Program::new(puzzle_input("9")), |_| input);
+    }
+    assert_conforms(Program::new(puzzle_input("11")), |outputs| (outputs.len() / 2 % 3 == 0) as i64);
+
+    let mut program = Program::new(puzzle_input("13"));
+    program.memory.write(0, 2);
+    assert_eq!(assert_conforms(program, breakout_input).result, Ok(StepResult::Halt));
+}
+
+#[test]
+fn self_modifying_code() {
+    // Output a parameter that is incremented by the loop.
+    let run = assert_conforms(Program::new(parse_code("104,0,1001,1,1,1,1007,1,5,14,1005,14,0,99,0")), |_| 0);
+    assert_eq!(run.outputs, vec![0, 1, 2, 3, 4]);
+
+    // Turn an immediate mode add that has already run into a position mode one.
+    let code = "1101,0,0,21,1101,1,0,0,4,21,1005,22,20,1101,1,0,22,1105,1,0,99,0,0";
+    let run = assert_conforms(Program::new(parse_code(code)), |_| 0);
+    assert_eq!(run.outputs, vec![0, 2]);
+}
+
+#[test]
+fn error_conformance() {
+    let programs = [
+        "42",
+        "1,0,0",
+        "11101,0,0,0,99",
+        "301,0,0,0,99",
+        "1,-1,0,0,99",
+        "109,-5,2201,0,0,0,99",
+        "3,-1,99",
+        "203,-1,99",
+        "109,10,204,-11,99",
+        "1105,1,100",
+        "4,0,4,2,1106,0,-1",
+        "1101,1,0,6,1105,1,7,42"
+    ];
+    for code in programs.iter() {
+        let run = assert_conforms(Program::new(parse_code(code)), |_| 7);
+        assert!(run.result.is_err(), "{}", code);
+    }
+}
+
+#[test]
+fn host_writes() {
+    let mut program = Program::new(parse_code("104,1,1105,1,0"));
+    let mut cache = DecodeCache::new();
+    assert_eq!(run_program_cached(&mut program, &mut cache), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_front(), Some(1));
+
+    program.memory.write(1, 5);
+    cache.invalidate(1);
+    assert_eq!(run_program_cached(&mut program, &mut cache), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_front(), Some(5));
+
+    program.memory.write(0, 99);
+    cache.clear();
+    assert_eq!(run_program_cached(&mut program, &mut cache), Ok(StepResult::Halt));
+}
+
+#[test]
+fn custom_opcode_loop() {
+    // Run a custom no-op a million times: each one is handed over to
+    // step_program().
+    let mut opcodes = OpcodeSet::new();
+    opcodes.register(10, "nop", &[], |_, _| Ok(Effect::Store(vec![]))).unwrap();
+    let mut code = parse_code("10,1001,20,1,20,1007,20,1000000,21,1005,21,0,99");
+    code.resize(22, 0);
+    let mut program = Program::new(code).with_opcodes(Arc::new(opcodes));
+    let mut cache = DecodeCache::new();
+    assert_eq!(run_program_cached(&mut program, &mut cache), Ok(StepResult::Halt));
+    assert_eq!(program.memory.read(20), 1_000_000);
+}
+
+#[test]
+fn invalidate_empty_cache() {
+    let mut cache = DecodeCache::new();
+    for address in 0..6 {
+        cache.invalidate(address);
+    }
+    let mut program = Program::new(parse_code("104,1,99"));
+    program.memory.write(1, 2);
+    cache.invalidate(1);
+    assert_eq!(run_program_cached(&mut program, &mut cache), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_back(), Some(2));
+}
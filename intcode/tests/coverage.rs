@@ -0,0 +1,114 @@
+use intcode::*;
+use intcode::coverage::*;
+use intcode::disasm::{disassemble, disassemble_recursive_from};
+use intcode::opcodes::OpcodeSet;
+use intcode::trace::run_program_traced;
+
+// Output 0..4 by incrementing a counter until it reaches 5.
+const LOOP: &str = "104,0,1001,1,1,1,1007,1,5,14,1005,14,0,99,0";
+
+fn run(code: &[i64], input: i64, coverage: &mut Coverage) {
+    let mut program = Program::new(code.to_vec());
+    program.data.push_back(input);
+    while run_program_traced(&mut program, coverage).unwrap() == StepResult::Output {
+        program.data.pop_back();
+    }
+}
+
+fn day9() -> Vec<i64> {
+    load_code(&format!("{}/../9/input.txt", env!("CARGO_MANIFEST_DIR")))
+}
+
+#[test]
+fn coverage_loop() {
+    let code = parse_code(LOOP);
+    let mut coverage = Coverage::new();
+    run(&code, 0, &mut coverage);
+
+    assert_eq!(coverage.hits.values().cloned().collect::<Vec<u64>>(), vec![5, 5, 5, 5, 1]);
+    assert_eq!(coverage.branches.clone().into_iter().collect::<Vec<_>>(), vec![(10, (4, 1))]);
+    assert_eq!(coverage.summary(&code), Summary { instructions: 5, instructions_hit: 5, branches: 2, branches_hit: 2 });
+}
+
+#[test]
+fn merge_runs() {
+    let code = day9();
+    let mut test_mode = Coverage::new();
+    run(&code, 1, &mut test_mode);
+    let mut sensor_boost = Coverage::new();
+    run(&code, 2, &mut sensor_boost);
+
+    let summary = test_mode.summary(&code);
+    assert_eq!(summary, Summary { instructions: 298, instructions_hit: 209, branches: 66, branches_hit: 30 });
+    assert_eq!(format_summary(&summary),
+        "Instructions: 209 of 298 executed (70.13%)\nBranches: 30 of 66 taken (45.45%)\n");
+
+    let mut merged = test_mode.clone();
+    merged.merge(&sensor_boost);
+    let merged_summary = merged.summary(&code);
+    assert!(merged_summary.instructions_hit > summary.instructions_hit);
+    assert!(merged_summary.instructions_hit > sensor_boost.summary(&code).instructions_hit);
+    assert_eq!(merged.hits[&0], test_mode.hits[&0] + sensor_boost.hits[&0]);
+}
+
+#[test]
+fn day5_inputs() {
+    // Input 1 runs the first diagnostic, input 5 the jump tests after a
+    // self-modified instruction.
+    let code = load_code(&format!("{}/../5/input.txt", env!("CARGO_MANIFEST_DIR")));
+    let mut air_conditioner = Coverage::new();
+    run(&code, 1, &mut air_conditioner);
+    let mut radiator = Coverage::new();
+    run(&code, 5, &mut radiator);
+
+    let first = air_conditioner.summary(&code);
+    let second = radiator.summary(&code);
+    assert!(first.instructions_hit < first.instructions);
+    assert!(first.instructions_hit < second.instructions_hit);
+    assert!(first.branches_hit < second.branches_hit);
+
+    let listing = annotate_listing(&disassemble_recursive_from(&code, &OpcodeSet::new(), &radiator.entries()),
+        &radiator, &code);
+    assert!(listing.contains("         1  0294:    jump_if_false    0 300\n"), "{}", listing);
+    assert!(listing.contains("     #####  0010:           output    0\n"), "{}", listing);
+}
+
+#[test]
+fn lcov_round_trip() {
+    let code = day9();
+    let mut coverage = Coverage::new();
+    run(&code, 1, &mut coverage);
+
+    let text = format_lcov(&coverage, &code, "9/input.txt");
+    assert!(text.starts_with("TN:\nSF:9/input.txt\nDA:0,1\n"));
+    assert!(text.contains("\nBRDA:8,0,0,0\nBRDA:8,0,1,1\n"));
+    assert!(text.contains("\nBRDA:38,0,0,-\nBRDA:38,0,1,-\n"));
+    assert!(text.ends_with("BRF:66\nBRH:30\nLF:298\nLH:209\nend_of_record\n"));
+
+    let mut parsed = parse_lcov(&text).unwrap();
+    assert_eq!(format_lcov(&parsed, &code, "9/input.txt"), text);
+
+    // Adding a run to the tracefile.
+    parsed.merge(&coverage);
+    let merged = parse_lcov(&format_lcov(&parsed, &code, "9/input.txt")).unwrap();
+    assert_eq!(merged.hits[&0], 2);
+    assert_eq!(merged.branches[&8], (0, 2));
+    assert_eq!(merged.branches[&38], (0, 0));
+
+    assert_eq!(parse_lcov("TN:\nDA:1,x\n"), Err(CoverageError { line: 2, message: "Invalid number 'x'".to_string() }));
+    assert!(parse_lcov("BRDA:1,0,2,1\n").is_err());
+    assert!(parse_lcov("DA:1\n").is_err());
+}
+
+#[test]
+fn annotated_listing() {
+    let code = day9();
+    let mut coverage = Coverage::new();
+    run(&code, 1, &mut coverage);
+
+    let listing = annotate_listing(&disassemble(&code), &coverage, &code);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert!(lines.iter().any(|line| line.starts_with("         1  0008:") && line.ends_with("   ; taken 0, not taken 1")));
+    assert!(lines.iter().any(|line| line.starts_with("     #####  0038:")));
+    assert!(lines.iter().any(|line| line.starts_with("            ") && line.contains(".data")));
+}
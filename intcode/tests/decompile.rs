@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use intcode::*;
+use intcode::asm::assemble;
+use intcode::decompile::{decompile, find_functions};
+use intcode::disasm::parse_symbols;
+
+fn puzzle_input(day: &str) -> Vec<i64> {
+    load_code(&format!("{}/../{}/input.txt", env!("CARGO_MANIFEST_DIR"), day))
+}
+
+// sum(n) returns 1 + 2 + ... + n, or -1 if n is negative.
+const SUM: &str = "
+        rel_base_offset stack
+        input [n]
+        [$1] = add [n] 0
+        [$0] = add 0 back
+        jump_if_true 1 sum
+back:   output [$1]
+        halt
+sum:    rel_base_offset 3
+        [flag] = less_than [$-2] 0
+        jump_if_false [flag] positive
+        [$-2] = add -1 0
+        jump_if_true 1 done
+positive:
+        [$-1] = add 0 0
+loop:   [flag] = less_than 0 [$-2]
+        jump_if_false [flag] end
+        [$-1] = add [$-1] [$-2]
+        [$-2] = add [$-2] -1
+        jump_if_true 1 loop
+end:    [$-2] = add [$-1] 0
+done:   rel_base_offset -3
+        jump_if_true 1 [$0]
+n:      .data 0
+flag:   .data 0
+stack:  .data 0
+";
+
+const SUM_DECOMPILED: &str = "\
+var n;
+
+func main() {
+    rel_base += 67;
+    n = input();
+    out1 = sum(n);
+    output(out1);
+    halt();
+}
+
+func sum(a1) {
+    var l2;
+    if (a1 < 0) {
+        a1 = -1;
+    } else {
+        l2 = 0;
+        while (0 < a1) {
+            l2 = l2 + a1;
+            a1 = a1 - 1;
+        }
+        a1 = l2;
+    }
+    return a1;
+}
+";
+
+#[test]
+fn structured_function() {
+    let code = assemble(SUM).unwrap();
+    let symbols = parse_symbols("18 sum\n65 n\n66 flag").unwrap();
+    assert_eq!(decompile(&code, &symbols, &[]), SUM_DECOMPILED);
+
+    let functions = find_functions(&code, &[]);
+    assert_eq!(functions.keys().cloned().collect::<Vec<_>>(), [0, 18]);
+    let sum = &functions[&18];
+    assert_eq!((sum.frame, sum.params, sum.returns_value), (3, 1, true));
+
+    let mut program = Program::new(code);
+    program.data.push_back(10);
+    assert_eq!(run_program(&mut program), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_back(), Some(55));
+}
+
+#[test]
+fn day11_functions() {
+    let code = puzzle_input("11");
+    let text = decompile(&code, &BTreeMap::new(), &[]);
+    assert!(text.contains("    f_0431(838479487636);\n"), "{}", text);
+    assert!(text.contains("func f_0431(a1) {\n    f_0495(a1, 40, f_0462);\n    return;\n}\n"), "{}", text);
+    assert!(text.contains("func f_0536(a1, a2, a3) {\n    var l4;\n"), "{}", text);
+    assert!(text.contains("    a1 = f_0536(a1, a2 - 1, a3 * 2);\n"), "{}", text);
+    assert!(text.contains("        (*v494)(l4);\n"), "{}", text);
+
+    // 462 is only called through a pointer.
+    let functions = find_functions(&code, &[]);
+    assert_eq!(functions.keys().cloned().collect::<Vec<_>>(), [0, 431, 462, 495, 536]);
+    assert!(functions[&462].address_taken);
+    assert_eq!(functions[&462].params, 1);
+}
+
+#[test]
+fn day13_loops() {
+    let text = decompile(&puzzle_input("13"), &BTreeMap::new(), &[]);
+    assert!(text.contains("
+        do {
+            l5 = l5 + l7;
+        } while (l5 >= l6);
+"), "{}", text);
+    assert!(text.contains("        } while (v382 < 42);\n"), "{}", text);
+}
+
+#[test]
+fn self_modified_code() {
+    let text = decompile(&puzzle_input("5"), &BTreeMap::new(), &[]);
+    assert!(text.contains("    mem[6] = v225 + mem[6];\n    /* self-modified instruction at 6..9, may jump to L_0238 */\n    output(0);\n"),
+        "{}", text);
+    assert!(text.contains("    output(v224);\n"), "{}", text);
+    assert!(text.contains("    output(v223);\n    halt();\n"), "{}", text);
+    assert!(text.contains("L_0238:\n    if (v227) {\n        goto *99999;\n    }\n"), "{}", text);
+
+    // Day 7 jumps through a table that is indexed by the input.
+    let text = decompile(&puzzle_input("7"), &BTreeMap::new(), &[]);
+    assert!(text.ends_with("    goto *mem[0];\n}\n\n/* undecoded: addresses 9..502 */\n"), "{}", text);
+}
+
+#[test]
+fn all_days() {
+    for day in &["2", "5", "7", "9", "11", "13"] {
+        let text = decompile(&puzzle_input(day), &BTreeMap::new(), &[]);
+        assert!(text.contains("func main() {\n"), "day {}", day);
+    }
+}
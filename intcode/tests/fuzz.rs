@@ -0,0 +1,441 @@
+// Differential fuzzing of the Intcode VM implementations.
+//
+// The separate interpreters of days 2, 5 and 9 were replaced by the library
+// when it was introduced, so the programs are run by the library's backends
+// instead: the plain interpreter with paged and sparse memory, the decode
+// cache, checked arithmetic and the arbitrary precision VM (plus the stepping,
+// tracing and port-based runners, which share the interpreter's core). They
+// must agree on the outputs, how the program stopped and the final state.
+// Since most of them go through step_program(), they are also checked against
+// a small reference interpreter written from the puzzle descriptions.
+//
+// Random valid programs are generated for the opcode subsets of days 2, 5 and
+// 9. Some statements patch the following instruction's opcode or parameter
+// before it runs, so that the decode cache has to notice writes into code.
+// Loops are bounded by counters, so every program terminates. The number of
+// programs and the seed can be changed with the INTCODE_FUZZ_ITERATIONS and
+// INTCODE_FUZZ_SEED environment variables.
+
+use std::collections::BTreeMap;
+use std::env;
+use intcode::*;
+use intcode::big::{BigInt, BigProgram, run_big_program};
+use intcode::cache::{DecodeCache, run_program_cached};
+use intcode::disasm::analyze;
+use intcode::ports::{FixedInput, run_program_io};
+use intcode::profile::Profile;
+use intcode::trace::{TraceRecord, Tracer, run_program_traced};
+
+const DAY2_OPCODES: [i64; 3] = [OP_ADD, OP_MULTIPLY, OP_HALT];
+const DAY5_OPCODES: [i64; 9] = [OP_ADD, OP_MULTIPLY, OP_INPUT, OP_OUTPUT, OP_JUMP_IF_TRUE,
+    OP_JUMP_IF_FALSE, OP_LESS_THAN, OP_EQUALS, OP_HALT];
+const DAY9_OPCODES: [i64; 10] = [OP_ADD, OP_MULTIPLY, OP_INPUT, OP_OUTPUT, OP_JUMP_IF_TRUE,
+    OP_JUMP_IF_FALSE, OP_LESS_THAN, OP_EQUALS, OP_REL_BASE_OFFSET, OP_HALT];
+
+// Data cells used by the generated programs, followed by loop counters and
+// flags for each nesting level.
+const DATA_START: i64 = 2000;
+const DATA_SIZE: i64 = 12;
+const MAX_DEPTH: usize = 3;
+
+// xorshift64* generator.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Random number in the inclusive range.
+    fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + (self.next() % (high - low + 1) as u64) as i64
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next() % 100 < percent
+    }
+
+    fn choose<T: Copy>(&mut self, values: &[T]) -> T {
+        values[self.next() as usize % values.len()]
+    }
+}
+
+struct Generator<'a> {
+    rng: &'a mut Rng,
+    opcodes: &'a [i64],
+    code: Vec<i64>,
+    rel_base: i64,
+    budget: usize
+}
+
+impl<'a> Generator<'a> {
+    fn allows(&self, opcodes: &[i64]) -> bool {
+        opcodes.iter().all(|opcode| self.opcodes.contains(opcode))
+    }
+
+    fn emit(&mut self, opcode: i64, params: &[(i64, i64)]) {
+        let mut instr = opcode;
+        for (i, &(mode, _)) in params.iter().enumerate() {
+            instr += mode * 10_i64.pow(i as u32 + 2);
+        }
+        self.code.push(instr);
+        self.code.extend(params.iter().map(|&(_, value)| value));
+    }
+
+    // Parameter referring to a data cell in position or relative mode.
+    fn cell(&mut self, address: i64) -> (i64, i64) {
+        if self.allows(&[OP_REL_BASE_OFFSET]) && self.rng.chance(40) {
+            (MODE_REL, address - self.rel_base)
+        } else {
+            (MODE_POS, address)
+        }
+    }
+
+    fn data_cell(&mut self) -> (i64, i64) {
+        let address = DATA_START + self.rng.range(0, DATA_SIZE - 1);
+        self.cell(address)
+    }
+
+    fn source(&mut self) -> (i64, i64) {
+        if self.rng.chance(35) {
+            (MODE_IMM, self.rng.range(-20, 20))
+        } else {
+            self.data_cell()
+        }
+    }
+
+    fn block(&mut self, depth: usize) {
+        let count = self.rng.range(1, 5);
+        for _ in 0..count {
+            if self.budget == 0 {
+                break;
+            }
+            self.budget -= 1;
+            self.statement(depth);
+        }
+    }
+
+    fn statement(&mut self, depth: usize) {
+        let nested = depth < MAX_DEPTH && self.rng.chance(25);
+        let loops = self.allows(&[OP_ADD, OP_LESS_THAN, OP_JUMP_IF_TRUE]);
+        let branches = self.allows(&[OP_JUMP_IF_TRUE, OP_JUMP_IF_FALSE]);
+        let rel_base = self.allows(&[OP_REL_BASE_OFFSET]);
+
+        if nested && loops && self.rng.chance(40) {
+            // counter = 0; do { ... } while (++counter < n)
+            let counter = DATA_START + DATA_SIZE + 2 * depth as i64;
+            let flag = counter + 1;
+            let iterations = self.rng.range(1, 4);
+            self.emit(OP_ADD, &[(MODE_IMM, 0), (MODE_IMM, 0), (MODE_POS, counter)]);
+            let start = self.code.len() as i64;
+            self.block(depth + 1);
+            self.emit(OP_ADD, &[(MODE_POS, counter), (MODE_IMM, 1), (MODE_POS, counter)]);
+            self.emit(OP_LESS_THAN, &[(MODE_POS, counter), (MODE_IMM, iterations), (MODE_POS, flag)]);
+            self.emit(OP_JUMP_IF_TRUE, &[(MODE_POS, flag), (MODE_IMM, start)]);
+        } else if nested && branches && self.rng.chance(50) {
+            // Skip a block depending on a condition.
+            let opcode = self.rng.choose(&[OP_JUMP_IF_TRUE, OP_JUMP_IF_FALSE]);
+            let condition = self.source();
+            self.emit(opcode, &[condition, (MODE_IMM, 0)]);
+            let patch = self.code.len() - 1;
+            self.block(depth + 1);
+            self.code[patch] = self.code.len() as i64;
+        } else if nested && rel_base {
+            // Move the relative base and restore it after the block.
+            let offset = self.rng.range(-5, 5);
+            self.emit(OP_REL_BASE_OFFSET, &[(MODE_IMM, offset)]);
+            self.rel_base += offset;
+            self.block(depth + 1);
+            self.emit(OP_REL_BASE_OFFSET, &[(MODE_IMM, -offset)]);
+            self.rel_base -= offset;
+        } else if self.rng.chance(15) {
+            // Rewrite the opcode or the first parameter of the next
+            // instruction before it runs. Inside loops it has already been
+            // decoded by then.
+            let arithmetic: Vec<i64> = [OP_ADD, OP_MULTIPLY, OP_LESS_THAN, OP_EQUALS].iter()
+                .cloned()
+                .filter(|opcode| self.opcodes.contains(opcode))
+                .collect();
+            let next = self.code.len() as i64 + 4;
+            let (opcode, b, target) = (self.rng.choose(&arithmetic), self.source(), self.data_cell());
+            if self.rng.chance(50) {
+                let value = self.source();
+                self.emit(OP_ADD, &[value, (MODE_IMM, 0), (MODE_POS, next + 1)]);
+            } else {
+                let replacement = self.rng.choose(&arithmetic) + MODE_IMM * 100 + b.0 * 1000 + target.0 * 10000;
+                self.emit(OP_ADD, &[(MODE_IMM, replacement), (MODE_IMM, 0), (MODE_POS, next)]);
+            }
+            let a = (MODE_IMM, self.rng.range(-20, 20));
+            self.emit(opcode, &[a, b, target]);
+        } else {
+            let simple: Vec<i64> = [OP_ADD, OP_MULTIPLY, OP_LESS_THAN, OP_EQUALS, OP_INPUT, OP_OUTPUT].iter()
+                .cloned()
+                .filter(|opcode| self.opcodes.contains(opcode))
+                .collect();
+            match self.rng.choose(&simple) {
+                OP_INPUT => {
+                    let target = self.data_cell();
+                    self.emit(OP_INPUT, &[target]);
+                },
+                OP_OUTPUT => {
+                    let value = self.source();
+                    self.emit(OP_OUTPUT, &[value]);
+                },
+                opcode => {
+                    let (a, b, target) = (self.source(), self.source(), self.data_cell());
+                    self.emit(opcode, &[a, b, target]);
+                }
+            }
+        }
+    }
+}
+
+fn generate(rng: &mut Rng, opcodes: &[i64]) -> Vec<i64> {
+    let mut generator = Generator { rng, opcodes, code: Vec::new(), rel_base: 0, budget: 40 };
+    if generator.allows(&[OP_REL_BASE_OFFSET]) {
+        generator.emit(OP_REL_BASE_OFFSET, &[(MODE_IMM, DATA_START)]);
+        generator.rel_base = DATA_START;
+    }
+    generator.block(0);
+    while generator.budget > 0 && generator.rng.chance(70) {
+        generator.block(0);
+    }
+    generator.emit(OP_HALT, &[]);
+    generator.code
+}
+
+// Everything observable about a run.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    outputs: Vec<i64>,
+    result: Result<StepResult, VmError>,
+    ip: i64,
+    rel_base: i64,
+    memory: Vec<(i64, i64)>
+}
+
+// Run until the program halts, fails or needs more input than available.
+fn run_with<M: Memory, F>(mut program: Program<M>, input: &[i64], mut run: F) -> Outcome
+        where F: FnMut(&mut Program<M>) -> Result<StepResult, VmError> {
+    let mut input = input.iter();
+    let mut outputs = Vec::new();
+    let result = loop {
+        match run(&mut program) {
+            Ok(StepResult::Output) => outputs.push(program.data.pop_back().unwrap()),
+            Ok(StepResult::NeedInput) => match input.next() {
+                Some(&value) => program.data.push_back(value),
+                None => break Ok(StepResult::NeedInput)
+            },
+            result => break result
+        }
+    };
+    Outcome { outputs, result, ip: program.ip, rel_base: program.rel_base, memory: program.memory.cells() }
+}
+
+struct NullTracer;
+
+impl Tracer for NullTracer {
+    fn trace(&mut self, _record: &TraceRecord) {}
+}
+
+fn run_big(code: &[i64], input: &[i64]) -> Outcome {
+    let mut program = BigProgram::new(code.iter().map(|&x| BigInt::from(x)).collect());
+    let mut input = input.iter();
+    let mut outputs = Vec::new();
+    let result = loop {
+        match run_big_program(&mut program) {
+            Ok(StepResult::Output) => outputs.push(program.data.pop_front().unwrap().to_i64().unwrap()),
+            Ok(StepResult::NeedInput) => match input.next() {
+                Some(&value) => program.data.push_back(BigInt::from(value)),
+                None => break Ok(StepResult::NeedInput)
+            },
+            result => break result
+        }
+    };
+    let mut memory: Vec<(i64, i64)> = program.memory.iter()
+        .map(|(&address, value)| (address, value.to_i64().unwrap()))
+        .collect();
+    memory.sort_unstable();
+    Outcome { outputs, result, ip: program.ip, rel_base: program.rel_base, memory }
+}
+
+// Independent implementation of the day 9 machine. Faults are reported as
+// Err with the address of the failing instruction, without the details.
+fn run_reference(code: &[i64], input: &[i64]) -> Result<Outcome, i64> {
+    let mut memory: BTreeMap<i64, i64> = code.iter().cloned().enumerate().map(|(a, x)| (a as i64, x)).collect();
+    let mut input = input.iter();
+    let mut outputs = Vec::new();
+    let (mut ip, mut rel_base) = (0_i64, 0_i64);
+    let result = loop {
+        if ip < 0 || ip >= code.len() as i64 {
+            return Err(ip);
+        }
+        let instr = memory.get(&ip).cloned().unwrap_or(0);
+        let (opcode, count, writes) = match instr % 100 {
+            1 | 2 | 7 | 8 => (instr % 100, 3, true),
+            5 | 6 => (instr % 100, 2, false),
+            3 => (3, 1, true),
+            4 | 9 => (instr % 100, 1, false),
+            99 => break Ok(StepResult::Halt),
+            _ => return Err(ip)
+        };
+        // Parameter values, with the address of the last parameter for
+        // instructions that write to memory.
+        let mut values = Vec::new();
+        for i in 0..count {
+            let param = memory.get(&(ip + 1 + i)).cloned().unwrap_or(0);
+            let address = match instr / 10_i64.pow(i as u32 + 2) % 10 {
+                0 => param,
+                1 if writes && i == count - 1 => return Err(ip),
+                1 => {
+                    values.push(param);
+                    continue;
+                },
+                2 => param.wrapping_add(rel_base),
+                _ => return Err(ip)
+            };
+            if address < 0 {
+                return Err(ip);
+            }
+            values.push(if writes && i == count - 1 { address } else { memory.get(&address).cloned().unwrap_or(0) });
+        }
+        if instr / 10_i64.pow(count as u32 + 2) != 0 {
+            return Err(ip);
+        }
+
+        let next = ip + 1 + count;
+        match opcode {
+            1 => { memory.insert(values[2], values[0].wrapping_add(values[1])); },
+            2 => { memory.insert(values[2], values[0].wrapping_mul(values[1])); },
+            3 => match input.next() {
+                Some(&value) => { memory.insert(values[0], value); },
+                None => break Ok(StepResult::NeedInput)
+            },
+            4 => outputs.push(values[0]),
+            5 if values[0] != 0 => {
+                ip = values[1];
+                continue;
+            },
+            6 if values[0] == 0 => {
+                ip = values[1];
+                continue;
+            },
+            7 => { memory.insert(values[2], (values[0] < values[1]) as i64); },
+            8 => { memory.insert(values[2], (values[0] == values[1]) as i64); },
+            9 => rel_base = rel_base.wrapping_add(values[0]),
+            _ => {}
+        }
+        ip = next;
+    };
+    let memory = memory.into_iter().filter(|&(_, value)| value != 0).collect();
+    Ok(Outcome { outputs, result, ip, rel_base, memory })
+}
+
+// Run the program with every implementation and check that they agree.
+fn check(code: &[i64], input: &[i64]) {
+    let expected = run_with(Program::new(code.to_vec()), input, run_program);
+    let describe = |name: &str| format!("{} differs for program {:?} with input {:?}", name, code, input);
+
+    let sparse = run_with(Program::with_memory(code.to_vec(), SparseMemory::new()), input, run_program);
+    assert_eq!(sparse, expected, "{}", describe("sparse memory"));
+
+    let stepped = run_with(Program::new(code.to_vec()), input, |p| loop {
+        if let Some(result) = step_program(p)? {
+            return Ok(result);
+        }
+    });
+    assert_eq!(stepped, expected, "{}", describe("step_program"));
+
+    let traced = run_with(Program::new(code.to_vec()), input, |p| run_program_traced(p, &mut NullTracer));
+    assert_eq!(traced, expected, "{}", describe("run_program_traced"));
+
+    let mut cache = DecodeCache::new();
+    let cached = run_with(Program::new(code.to_vec()), input, |p| run_program_cached(p, &mut cache));
+    assert_eq!(cached, expected, "{}", describe("run_program_cached"));
+
+    let mut program = Program::new(code.to_vec());
+    let mut outputs = Vec::new();
+    let result = run_program_io(&mut program, &mut FixedInput::new(input.to_vec()), &mut outputs);
+    let ported = Outcome { outputs, result, ip: program.ip, rel_base: program.rel_base, memory: program.memory.cells() };
+    assert_eq!(ported, expected, "{}", describe("run_program_io"));
+
+    match run_reference(code, input) {
+        Ok(reference) => assert_eq!(reference, expected, "{}", describe("reference interpreter")),
+        Err(ip) => assert!(expected.result.is_err() && expected.ip == ip, "{}", describe("reference interpreter"))
+    }
+
+    // Without overflows the results must match those of arbitrary precision.
+    let checked = run_with(Program::new(code.to_vec()).with_arithmetic(Arithmetic::Checked), input, run_program);
+    if let Err(VmError::Overflow { .. }) = checked.result {
+        return;
+    }
+    assert_eq!(checked, expected, "{}", describe("checked arithmetic"));
+    assert_eq!(run_big(code, input), expected, "{}", describe("big"));
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn fuzz(opcodes: &[i64], seed: u64) {
+    let iterations: u64 = env_or("INTCODE_FUZZ_ITERATIONS", 300);
+    let mut rng = Rng(env_or("INTCODE_FUZZ_SEED", seed) | 1);
+    for _ in 0..iterations {
+        let code = generate(&mut rng, opcodes);
+        let input_count = rng.range(0, 8);
+        let input: Vec<i64> = (0..input_count).map(|_| rng.range(-100, 100)).collect();
+        check(&code, &input);
+    }
+}
+
+#[test]
+fn fuzz_day2_opcodes() {
+    fuzz(&DAY2_OPCODES, 2);
+}
+
+#[test]
+fn fuzz_day5_opcodes() {
+    fuzz(&DAY5_OPCODES, 5);
+}
+
+#[test]
+fn fuzz_day9_opcodes() {
+    fuzz(&DAY9_OPCODES, 9);
+}
+
+#[test]
+fn generated_programs_terminate() {
+    let mut rng = Rng(17);
+    let mut outputs = 0;
+    let mut loops = 0;
+    let mut self_modifying = 0;
+    for _ in 0..100 {
+        let code = generate(&mut rng, &DAY9_OPCODES);
+        if !analyze(&code).code_writes.is_empty() {
+            self_modifying += 1;
+        }
+        let mut program = Program::new(code.clone());
+        program.data.extend(vec![1; 100]);
+        let mut profile = Profile::new();
+        loop {
+            match run_program_traced(&mut program, &mut profile) {
+                Ok(StepResult::Output) => outputs += 1,
+                result => {
+                    assert_eq!(result, Ok(StepResult::Halt), "{:?}", code);
+                    break;
+                }
+            }
+        }
+        if !profile.loops().is_empty() {
+            loops += 1;
+        }
+    }
+    // Make sure the generator actually exercises outputs, loops and writes
+    // into code.
+    assert!(outputs > 0);
+    assert!(loops > 10);
+    assert!(self_modifying > 10);
+}
@@ -0,0 +1,120 @@
+use intcode::*;
+use intcode::asm::assemble;
+use intcode::history::*;
+use intcode::snapshot::format_snapshot;
+
+const COUNTER: &str = "
+        input [n]
+loop:   [x] = add [x] [n]
+        [n] = add [n] -1
+        jump_if_true [n] loop
+        output [x]
+        halt
+n:      .data 0
+x:      .data 0
+";
+
+fn day(day: u32) -> Vec<i64> {
+    load_code(&format!("{}/../{}/input.txt", env!("CARGO_MANIFEST_DIR"), day))
+}
+
+// Run the program to the end, returning the state before every step.
+fn run_logged(program: &mut Program, history: &mut History, input: i64) -> Vec<String> {
+    let mut states = Vec::new();
+    loop {
+        states.push(format_snapshot(program));
+        match step_program_logged(program, history).unwrap() {
+            None => (),
+            Some(StepResult::Output) => {
+                program.data.pop_back();
+            },
+            Some(StepResult::NeedInput) => {
+                states.pop();
+                program.data.push_back(input);
+            },
+            Some(StepResult::Halt) => {
+                states.pop();
+                return states;
+            }
+        }
+    }
+}
+
+#[test]
+fn step_backwards() {
+    let mut program = Program::new(day(5));
+    let mut history = History::new();
+    let states = run_logged(&mut program, &mut history, 5);
+    assert_eq!(history.step(), states.len() as u64);
+    assert_eq!(history.oldest_step(), 0);
+
+    for state in states.iter().rev() {
+        assert!(reverse_step(&mut program, &mut history));
+        assert_eq!(&format_snapshot(&program), state);
+    }
+    assert_eq!(history.step(), 0);
+    assert!(!reverse_step(&mut program, &mut history));
+
+    // Going forward again consumes the same input value.
+    assert_eq!(run_program(&mut program), Ok(StepResult::Output));
+    assert_eq!(program.data.back(), Some(&12648139));
+}
+
+#[test]
+fn seek_through_checkpoints() {
+    let mut program = Program::new(day(9));
+    let mut history = History::new().with_max_entries(10).with_checkpoint_interval(20);
+    let states = run_logged(&mut program, &mut history, 1);
+    assert_eq!(states.len(), 208);
+    assert_eq!(history.oldest_step(), 0);
+
+    for step in (0..states.len()).rev().step_by(3) {
+        assert!(seek(&mut program, &mut history, step as u64));
+        assert_eq!(history.step(), step as u64);
+        assert_eq!(format_snapshot(&program), states[step]);
+    }
+
+    // The program can be run forward again after going back.
+    assert!(seek(&mut program, &mut history, 0));
+    let again = run_logged(&mut program, &mut history, 1);
+    assert_eq!(again, states);
+}
+
+#[test]
+fn bounded_history() {
+    let mut program = Program::new(assemble(COUNTER).unwrap());
+    let mut history = History::new()
+        .with_max_entries(20)
+        .with_checkpoint_interval(100)
+        .with_max_checkpoints(3);
+    let states = run_logged(&mut program, &mut history, 1000);
+    assert_eq!(states.len(), 1 + 3 * 1000 + 1);
+    assert_eq!(history.oldest_step(), 2800);
+
+    assert!(!seek(&mut program, &mut history, 2799));
+    assert_eq!(history.step(), states.len() as u64);
+    assert!(seek(&mut program, &mut history, 2800));
+    assert_eq!(format_snapshot(&program), states[2800]);
+}
+
+#[test]
+fn last_write() {
+    let code = assemble(COUNTER).unwrap();
+    let mut program = Program::new(code.clone());
+    let mut history = History::new();
+    program.data.push_back(3);
+    for _ in 0..7 {
+        step_program_logged(&mut program, &mut history).unwrap();
+    }
+
+    let n = code.len() as i64 - 2;
+    let x = code.len() as i64 - 1;
+    assert_eq!(history.last_write(n), Some(Write { step: 5, ip: 6, address: n, old_value: 2, new_value: 1 }));
+    assert_eq!(history.last_write(x), Some(Write { step: 4, ip: 2, address: x, old_value: 3, new_value: 5 }));
+    assert_eq!(history.last_write(0), None);
+
+    assert!(seek(&mut program, &mut history, 5));
+    assert_eq!(program.memory.read(n), 2);
+    assert_eq!(history.last_write(n), Some(Write { step: 2, ip: 6, address: n, old_value: 3, new_value: 2 }));
+    assert_eq!(history.last_write(x).map(|write| write.step), Some(4));
+}
@@ -0,0 +1,23 @@
+use intcode::*;
+
+#[test]
+fn paged_memory_huge_addresses() {
+    // [2^50] = 7; [2^40] = [2^50] + 1; output [2^40]
+    let code = parse_code("1101,0,7,1125899906842624,1001,1125899906842624,1,1099511627776,4,1099511627776,99");
+    let mut program = Program::new(code);
+    assert_eq!(run_program(&mut program), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_back(), Some(8));
+
+    let mut memory = PagedMemory::new();
+    memory.write(1 << 50, 3);
+    memory.write(5000, 2);
+    memory.write(1 << 40, 4);
+    memory.write(1, 1);
+    assert_eq!(memory.read(1 << 50), 3);
+    assert_eq!(memory.read((1 << 50) + 1), 0);
+    assert_eq!(memory.read(1 << 45), 0);
+    assert_eq!(memory.cells(), vec![(1, 1), (5000, 2), (1 << 40, 4), (1 << 50, 3)]);
+    memory.clear();
+    assert_eq!(memory.cells(), vec![]);
+    assert_eq!(memory.read(1 << 50), 0);
+}
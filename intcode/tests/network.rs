@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::Path;
+use intcode::*;
+use intcode::network::*;
+
+const SCHEDULERS: [Scheduler; 2] = [Scheduler::RoundRobin, Scheduler::EventDriven];
+
+#[test]
+fn amplifier_loop() {
+    let base_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../7");
+    let config = fs::read_to_string(base_dir.join("network.txt")).unwrap();
+    for &scheduler in SCHEDULERS.iter() {
+        let mut network = parse_network(&config, &base_dir).unwrap();
+        assert_eq!(network.run(scheduler), Ok(NetworkResult::Halted));
+        assert_eq!(network.node("e").unwrap().outputs.last(), Some(&76211147));
+    }
+}
+
+#[test]
+fn deadlock() {
+    // Each node reads a value, outputs it and halts; c is never given input.
+    let echo = parse_code("3,0,4,0,99");
+    for &scheduler in SCHEDULERS.iter() {
+        let mut network = Network::new();
+        let a = network.add_node("a", echo.clone(), &[1]);
+        let b = network.add_node("b", echo.clone(), &[]);
+        network.add_node("c", echo.clone(), &[]);
+        network.add_link(a, b);
+        assert_eq!(network.run(scheduler), Ok(NetworkResult::Deadlock(vec!["c".to_string()])));
+        assert_eq!(network.nodes[b].outputs, vec![1]);
+        assert_eq!(network.nodes[b].status, NodeStatus::Halted);
+    }
+
+    let mut network = Network::new();
+    network.add_node("a", parse_code("3,0,99"), &[]);
+    network.add_node("b", parse_code("1,0,0,0,42"), &[]);
+    let error = network.run(Scheduler::RoundRobin).unwrap_err();
+    assert_eq!(error.node, "b");
+}
+
+#[test]
+fn config_errors() {
+    let base_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../7");
+    let error = |text: &str| parse_network(text, &base_dir).err().unwrap();
+    assert_eq!(error("node a input.txt\nnode a input.txt").line, 2);
+    assert_eq!(error("node a input.txt\n\nlink a b").line, 3);
+    assert_eq!(error("node a missing.txt").line, 1);
+    assert_eq!(error("node a input.txt 1,x").line, 1);
+    assert_eq!(error("; comment\nnodes a").line, 2);
+
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("network");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("bad.txt"), "1,2,x,99").unwrap();
+    let error = parse_network("\nnode a bad.txt", &dir).err().unwrap();
+    assert_eq!(error.line, 2);
+    assert!(error.message.starts_with("Invalid program "));
+
+    // Programs and input values are parsed like parse_code() does.
+    assert_eq!(try_parse_code(" 1, -2 ,3\n"), Ok(vec![1, -2, 3]));
+    assert!(try_parse_code("1,,2").is_err());
+    fs::write(dir.join("spaces.txt"), "3, 0,\n4, 0, 99\n").unwrap();
+    assert!(parse_network("node a spaces.txt 5", &dir).is_ok());
+}
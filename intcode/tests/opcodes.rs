@@ -0,0 +1,183 @@
+use std::sync::Arc;
+use intcode::*;
+use intcode::asm::assemble_with;
+use intcode::cache::{DecodeCache, run_program_cached};
+use intcode::disasm::{disassemble, disassemble_with};
+use intcode::opcodes::{Effect, OpcodeError, OpcodeSet, Param};
+use intcode::trace::{TraceRecord, Tracer, run_program_traced};
+
+const DIVIDE: i64 = 10;
+const DIVMOD: i64 = 11;
+const JUMP: i64 = 12;
+const READ_PAIR: i64 = 13;
+
+fn opcodes() -> OpcodeSet {
+    use Param::*;
+    let mut opcodes = OpcodeSet::new();
+    opcodes.register(DIVIDE, "divide", &[Read, Read, Write], |args, _| {
+        match args[1] {
+            0 => Err("Division by zero".to_string()),
+            b => Ok(Effect::Store(vec![args[0] / b]))
+        }
+    }).unwrap();
+    opcodes.register(DIVMOD, "divmod", &[Read, Read, Write, Write], |args, _| {
+        Ok(Effect::Store(vec![args[0] / args[1], args[0] % args[1]]))
+    }).unwrap();
+    opcodes.register(JUMP, "jump", &[Read], |args, _| Ok(Effect::Jump(args[0]))).unwrap();
+    // Read two input values at once and output their sum.
+    opcodes.register(READ_PAIR, "read_pair", &[], |_, data| {
+        if data.len() < 2 {
+            return Ok(Effect::NeedInput);
+        }
+        let (a, b) = (data.pop_front().unwrap(), data.pop_front().unwrap());
+        Ok(Effect::Output(a + b))
+    }).unwrap();
+    opcodes
+}
+
+const SOURCE: &str = "
+        input [a]
+        input [b]
+        [q] = divide [a] [b]
+        output [q]
+        divmod [a] [b] [q] [$r]
+        output [q]
+        output [r]
+        jump end
+        output 666
+end:    read_pair
+        halt
+a:      .data 0
+b:      .data 0
+q:      .data 0
+r:      .data 0
+";
+
+fn run_all<M: Memory>(program: &mut Program<M>, input: &[i64]) -> (Vec<i64>, Result<StepResult, VmError>) {
+    program.data.extend(input);
+    let mut outputs = Vec::new();
+    loop {
+        match run_program(program) {
+            Ok(StepResult::Output) => outputs.push(program.data.pop_back().unwrap()),
+            result => return (outputs, result)
+        }
+    }
+}
+
+fn program(opcodes: &Arc<OpcodeSet>) -> Program {
+    let code = assemble_with(SOURCE, opcodes).unwrap();
+    Program::new(code).with_opcodes(Arc::clone(opcodes))
+}
+
+#[test]
+fn custom_opcodes() {
+    let opcodes = Arc::new(opcodes());
+    let mut program = program(&opcodes);
+    let (outputs, result) = run_all(&mut program, &[47, 5, 1, 2]);
+    assert_eq!(outputs, [9, 9, 2, 3]);
+    assert_eq!(result, Ok(StepResult::Halt));
+}
+
+#[test]
+fn custom_opcode_needs_input() {
+    let opcodes = Arc::new(opcodes());
+    let mut program = program(&opcodes);
+    let (outputs, result) = run_all(&mut program, &[47, 5, 1]);
+    assert_eq!(outputs, [9, 9, 2]);
+    assert_eq!(result, Ok(StepResult::NeedInput));
+
+    // The instruction is retried once the input is there.
+    program.data.push_back(10);
+    assert_eq!(run_program(&mut program), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_back(), Some(11));
+    assert_eq!(run_program(&mut program), Ok(StepResult::Halt));
+}
+
+#[test]
+fn handler_errors() {
+    let opcodes = Arc::new(opcodes());
+    let mut program = program(&opcodes);
+    let (_, result) = run_all(&mut program, &[47, 0]);
+    assert_eq!(result, Err(VmError::Handler {
+        address: 4, instr: DIVIDE, opcode: DIVIDE, message: "Division by zero".to_string()
+    }));
+    assert_eq!(program.ip, 4);
+    assert_eq!(result.unwrap_err().to_string(),
+        "Error in opcode 10 at address 4 (instruction 10): Division by zero");
+
+    let mut bad = OpcodeSet::new();
+    bad.register(20, "bad", &[Param::Write], |_, _| Ok(Effect::Store(vec![]))).unwrap();
+    let mut program = Program::new(vec![20, 5, 99, 0, 0, 0]).with_opcodes(Arc::new(bad));
+    assert!(matches!(run_program(&mut program), Err(VmError::Handler { .. })));
+
+    // Modes are checked as for built-in instructions.
+    let mut program = Program::new(vec![10100 + DIVIDE, 7, 1, 2, 99]).with_opcodes(opcodes);
+    assert!(matches!(run_program(&mut program), Err(VmError::ImmediateWrite { index: 2, .. })));
+}
+
+#[test]
+fn unknown_opcodes_still_fail() {
+    let mut program = Program::new(vec![DIVIDE, 0, 0, 0, 99]);
+    assert_eq!(run_program(&mut program), Err(VmError::InvalidOpcode { address: 0, instr: DIVIDE, opcode: DIVIDE }));
+}
+
+#[test]
+fn registration_errors() {
+    let mut opcodes = opcodes();
+    let nop = |_: &[i64], _: &mut std::collections::VecDeque<i64>| Ok(Effect::Store(vec![]));
+    assert_eq!(opcodes.register(OP_ADD, "add2", &[], nop), Err(OpcodeError::InvalidOpcode(OP_ADD)));
+    assert_eq!(opcodes.register(0, "zero", &[], nop), Err(OpcodeError::InvalidOpcode(0)));
+    assert_eq!(opcodes.register(99, "stop", &[], nop), Err(OpcodeError::InvalidOpcode(99)));
+    assert_eq!(opcodes.register(DIVIDE, "div", &[], nop), Err(OpcodeError::DuplicateOpcode(DIVIDE)));
+    assert_eq!(opcodes.register(30, "divide", &[], nop), Err(OpcodeError::DuplicateName("divide".to_string())));
+    assert_eq!(opcodes.register(30, "output", &[], nop), Err(OpcodeError::DuplicateName("output".to_string())));
+    assert_eq!(opcodes.register(30, "two words", &[], nop), Err(OpcodeError::InvalidName("two words".to_string())));
+    assert_eq!(opcodes.register(30, "many", &[Param::Read; 17], nop), Err(OpcodeError::TooManyParams(17)));
+    assert_eq!(opcodes.register(30, "nop", &[], nop), Ok(()));
+    assert_eq!(opcodes.name(30), Some("nop"));
+    assert_eq!(opcodes.opcode_by_name("nop"), Some(30));
+    assert_eq!(opcodes.params(DIVMOD), Some(&[Param::Read, Param::Read, Param::Write, Param::Write][..]));
+}
+
+#[test]
+fn disassemble_custom_opcodes() {
+    let opcodes = opcodes();
+    let code = assemble_with(SOURCE, &opcodes).unwrap();
+    let listing = disassemble_with(&code, &opcodes);
+    assert!(listing.contains("0004:           divide    [25] [26] [27]"), "{}", listing);
+    assert!(listing.contains("0010:           divmod    [25] [26] [27] [$28]"), "{}", listing);
+    assert!(listing.contains("read_pair"), "{}", listing);
+    assert_eq!(assemble_with(&listing, &opcodes).unwrap(), code);
+
+    // Without the set they are data.
+    assert!(disassemble(&code).contains("0004:            .data    10"));
+}
+
+struct Writes(Vec<Option<(i64, i64)>>);
+
+impl Tracer for Writes {
+    fn trace(&mut self, record: &TraceRecord) {
+        if record.opcode == DIVIDE {
+            self.0.push(record.write);
+        }
+    }
+}
+
+#[test]
+fn other_engines() {
+    let opcodes = Arc::new(opcodes());
+    let mut program = program(&opcodes);
+    program.data.extend(&[47, 5, 1, 2]);
+    let mut cache = DecodeCache::new();
+    let mut outputs = Vec::new();
+    while let Ok(StepResult::Output) = run_program_cached(&mut program, &mut cache) {
+        outputs.push(program.data.pop_back().unwrap());
+    }
+    assert_eq!(outputs, [9, 9, 2, 3]);
+
+    let mut program = self::program(&opcodes);
+    program.data.extend(&[47, 5, 1, 2]);
+    let mut writes = Writes(Vec::new());
+    while let Ok(StepResult::Output) = run_program_traced(&mut program, &mut writes) {}
+    assert_eq!(writes.0, [Some((27, 9))]);
+}
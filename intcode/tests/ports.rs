@@ -0,0 +1,91 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::sync::mpsc;
+use std::thread;
+use intcode::*;
+use intcode::ports::*;
+
+fn puzzle_input(day: &str) -> Vec<i64> {
+    load_code(&format!("{}/../{}/input.txt", env!("CARGO_MANIFEST_DIR"), day))
+}
+
+#[test]
+fn queue_and_fixed_input() {
+    // Echo input values until a zero is read.
+    let code = parse_code("3,9,4,9,1005,9,0,99,0,0");
+    let mut program = Program::new(code.clone());
+    let mut input = VecDeque::from(vec![1, 2]);
+    let mut output = Vec::new();
+    assert_eq!(run_program_io(&mut program, &mut input, &mut output), Ok(StepResult::NeedInput));
+    assert_eq!(output, vec![1, 2]);
+    input.push_back(0);
+    assert_eq!(run_program_io(&mut program, &mut input, &mut output), Ok(StepResult::Halt));
+    assert_eq!(output, vec![1, 2, 0]);
+
+    let mut program = Program::new(code);
+    let mut input = FixedInput::new(vec![3, 4]).with_repeat();
+    let count = Cell::new(0);
+    let mut output = OutputFn(|_| count.set(count.get() + 1));
+    let mut input = InputFn(|| if count.get() < 5 { input.read() } else { Some(0) });
+    assert_eq!(run_program_io(&mut program, &mut input, &mut output), Ok(StepResult::Halt));
+    assert_eq!(count.get(), 6);
+}
+
+#[test]
+fn channels() {
+    // Day 7 part 2: five amplifiers on their own threads connected in a loop.
+    let code = puzzle_input("7");
+    let phases = [8, 9, 6, 7, 5];
+    let channels: Vec<_> = phases.iter().map(|_| mpsc::channel::<i64>()).collect();
+    let (senders, receivers): (Vec<_>, Vec<_>) = channels.into_iter().unzip();
+    let (result_sender, result_receiver) = mpsc::channel();
+
+    for (i, (phase, input)) in phases.iter().zip(receivers).enumerate() {
+        senders[i].send(*phase).unwrap();
+        let mut program = Program::new(code.clone());
+        let mut input = input;
+        let mut output = senders[(i + 1) % phases.len()].clone();
+        let mut last = (i == phases.len() - 1).then(|| result_sender.clone());
+        thread::spawn(move || {
+            let mut last_value = 0;
+            let mut output = OutputFn(|value| {
+                last_value = value;
+                output.write(value);
+            });
+            assert_eq!(run_program_io(&mut program, &mut input, &mut output), Ok(StepResult::Halt));
+            if let Some(sender) = last.take() {
+                sender.send(last_value).unwrap();
+            }
+        });
+    }
+    senders[0].send(0).unwrap();
+    drop(senders);
+    assert_eq!(result_receiver.recv().unwrap(), 76211147);
+}
+
+#[test]
+fn ascii_console() {
+    // Echo characters up to a newline, then output 1000.
+    let code = parse_code("3,100,4,100,1008,100,10,101,1006,101,0,104,1000,99");
+    let mut program = Program::new(code.clone());
+    let mut input = AsciiInput::new(Cursor::new("hi there\r\nunused\n"));
+    let mut output = AsciiOutput::new(Vec::new());
+    assert_eq!(run_program_io(&mut program, &mut input, &mut output), Ok(StepResult::Halt));
+    assert_eq!(String::from_utf8(output.into_inner()).unwrap(), "hi there\n1000\n");
+
+    let mut program = Program::new(code);
+    let mut input = AsciiInput::new(Cursor::new("ab"));
+    let mut output = Vec::new();
+    assert_eq!(run_program_io(&mut program, &mut input, &mut output), Ok(StepResult::Halt));
+    assert_eq!(output, vec![97, 98, 10, 1000]);
+
+    let mut output = AsciiOutput::new(Vec::new());
+    for &value in [72, 105, 10, 128, -1, 0].iter() {
+        output.write(value);
+    }
+    assert_eq!(output.into_inner(), b"Hi\n128\n-1\n\0".to_vec());
+
+    let mut input = AsciiInput::new(Cursor::new(""));
+    assert_eq!(input.read(), None);
+}
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+use intcode::*;
+use intcode::opcodes::{Effect, OpcodeSet, Param};
+use intcode::disasm::disassemble;
+use intcode::profile::*;
+use intcode::trace::run_program_traced;
+
+#[test]
+fn profile_loop() {
+    // Output 0..4 by incrementing a counter until it reaches 5.
+    let code = parse_code("104,0,1001,1,1,1,1007,1,5,14,1005,14,0,99,0");
+    let mut program = Program::new(code.clone());
+    let mut profile = Profile::new();
+    while run_program_traced(&mut program, &mut profile) == Ok(StepResult::Output) {}
+
+    assert_eq!(profile.total, 21);
+    assert_eq!(profile.counts.values().cloned().collect::<Vec<u64>>(), vec![5, 5, 5, 5, 1]);
+    assert_eq!(profile.opcode_counts[&OP_OUTPUT], 5);
+    assert_eq!(profile.jumps[&(10, 0)], 4);
+    assert_eq!(profile.blocks(), vec![
+        Block { start: 0, end: 10, runs: 5, instructions: 20 },
+        Block { start: 13, end: 13, runs: 1, instructions: 1 }
+    ]);
+    assert_eq!(profile.loops(), vec![Loop { start: 0, end: 10, iterations: 4, instructions: 20 }]);
+
+    let listing = annotate_listing(&disassemble(&code), &profile);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], format!("           5   23.81%  {}", disassemble(&code).lines().next().unwrap()));
+    assert!(lines[4].starts_with("           1    4.76%  0013:"));
+    assert!(lines[5].starts_with("                       0014:"));
+
+    let report = format_report(&profile, 10);
+    assert!(report.starts_with("Executed 21 instructions\n"));
+    assert!(report.contains("  0000-0010            5 runs           20 instructions   95.24%\n"));
+    assert!(report.contains("  0000-0010            4 iterations           20 instructions   95.24%\n"));
+}
+
+#[test]
+fn profile_calls() {
+    // Returns through the stack are not loops.
+    let mut program = load_program(&format!("{}/../9/input.txt", env!("CARGO_MANIFEST_DIR")));
+    program.data.push_back(2);
+    let mut profile = Profile::new();
+    assert_eq!(run_program_traced(&mut program, &mut profile), Ok(StepResult::Output));
+    assert_eq!(program.data.pop_front(), Some(59095));
+    assert_eq!(run_program_traced(&mut program, &mut profile), Ok(StepResult::Halt));
+
+    assert_eq!(profile.total, 371206);
+    assert_eq!(profile.counts.values().sum::<u64>(), profile.total);
+    assert!(!profile.computed_jumps.is_empty());
+    assert!(profile.loops().iter().all(|l| !profile.computed_jumps.contains(&l.end)));
+}
+
+#[test]
+fn profile_custom_opcodes() {
+    let mut opcodes = OpcodeSet::new();
+    opcodes.register(20, "divmod", &[Param::Read, Param::Read, Param::Write, Param::Write], |args, _| {
+        Ok(Effect::Store(vec![args[0] / args[1], args[0] % args[1]]))
+    }).unwrap();
+    let opcodes = Arc::new(opcodes);
+
+    let mut program = Program::new(parse_code("1120,17,5,9,10,4,9,99,0,0,0")).with_opcodes(opcodes.clone());
+    let mut profile = Profile::new();
+    while run_program_traced(&mut program, &mut profile) == Ok(StepResult::Output) {}
+
+    assert_eq!(profile.blocks(), vec![Block { start: 0, end: 7, runs: 1, instructions: 3 }]);
+    assert!(format_report_with(&profile, 10, &opcodes).contains("  divmod                      1   33.33%\n"));
+}
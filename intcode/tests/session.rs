@@ -0,0 +1,160 @@
+use intcode::*;
+use intcode::asm::assemble;
+use intcode::session::*;
+
+const DOUBLE: &str = "
+        input [x]
+        [x] = multiply [x] 2
+        output [x]
+        input [y]
+        output [y]
+        halt
+x:      .data 0
+y:      .data 0
+";
+
+fn record_double(code: &[i64]) -> Session {
+    let mut program = Program::new(code.to_vec());
+    let mut recorder = Recorder::new();
+    program.data.push_back(5);
+    loop {
+        match run_program_recorded(&mut program, &mut recorder).unwrap() {
+            StepResult::NeedInput => program.data.push_back(7),
+            StepResult::Output => {
+                program.data.pop_back();
+            },
+            StepResult::Halt => return recorder.session
+        }
+    }
+}
+
+fn event(instruction: u64, address: i64, kind: EventKind) -> Event {
+    Event { instruction, address, kind }
+}
+
+#[test]
+fn record_events() {
+    let session = record_double(&assemble(DOUBLE).unwrap());
+    assert_eq!(session.events, vec![
+        event(0, 0, EventKind::Input(5)),
+        event(2, 6, EventKind::Output(10)),
+        event(3, 8, EventKind::Input(7)),
+        event(4, 10, EventKind::Output(7)),
+        event(5, 12, EventKind::Halt)
+    ]);
+    assert_eq!(session.inputs(), vec![5, 7]);
+    assert_eq!(session.outputs(), vec![10, 7]);
+
+    let text = format_session(&session);
+    assert_eq!(text, "intcode-session 1\n0 0 input 5\n2 6 output 10\n3 8 input 7\n4 10 output 7\n5 12 halt\n");
+    assert_eq!(parse_session(&text).unwrap(), session);
+}
+
+#[test]
+fn replay_breakout() {
+    let mut code = load_code(&format!("{}/../13/input.txt", env!("CARGO_MANIFEST_DIR")));
+    code[0] = 2;
+
+    // Follow the ball with the paddle for a while.
+    let mut program = Program::new(code.clone());
+    let mut recorder = Recorder::new();
+    let (mut ball, mut paddle) = (0i64, 0);
+    let mut moves = 0;
+    while moves < 200 {
+        match run_program_recorded(&mut program, &mut recorder).unwrap() {
+            StepResult::NeedInput => {
+                program.data.push_back((ball - paddle).signum());
+                moves += 1;
+            },
+            StepResult::Output if program.data.len() == 3 => {
+                let tile: Vec<i64> = program.data.drain(..).collect();
+                match tile[2] {
+                    3 => paddle = tile[0],
+                    4 => ball = tile[0],
+                    _ => ()
+                }
+            },
+            StepResult::Output => (),
+            StepResult::Halt => break
+        }
+    }
+    let session = parse_session(&format_session(&recorder.session)).unwrap();
+    // The last move hasn't been read by the program yet.
+    assert_eq!(session.inputs().len(), 199);
+
+    // The replay stops after the last event rather than where the host did.
+    let mut replayed = Program::new(code.clone());
+    let instructions = replay(&mut replayed, &session).unwrap();
+    assert_eq!(instructions, session.events.last().unwrap().instruction + 1);
+    assert_eq!(run_program(&mut replayed), Ok(StepResult::NeedInput));
+    assert_eq!(replayed.ip, program.ip);
+
+    // Moving the paddle differently changes what the game does next.
+    let mut changed = session.clone();
+    let index = changed.events.iter().rposition(|event| event.kind == EventKind::Input(0)).unwrap();
+    changed.events[index].kind = EventKind::Input(1);
+    match replay(&mut Program::new(code), &changed) {
+        Err(ReplayError::Diverged(divergence)) => assert!(divergence.index > index),
+        result => panic!("unexpected result: {:?}", result)
+    }
+}
+
+#[test]
+fn divergence() {
+    let code = assemble(DOUBLE).unwrap();
+    let session = record_double(&code);
+    assert_eq!(replay(&mut Program::new(code.clone()), &session), Ok(6));
+
+    // A different program computes a different output.
+    let patched = assemble(&DOUBLE.replace("multiply [x] 2", "add [x] 2")).unwrap();
+    let error = replay(&mut Program::new(patched), &session).unwrap_err();
+    assert_eq!(error, ReplayError::Diverged(Divergence {
+        index: 1,
+        instruction: 2,
+        address: 6,
+        expected: event(2, 6, EventKind::Output(10)),
+        actual: Some(EventKind::Output(7))
+    }));
+    assert_eq!(error.to_string(), "Replay diverged at instruction 2 (address 6): \
+        expected output 10 at instruction 2 (address 6), got output 7");
+
+    // An event that is expected earlier is reported at the instruction where
+    // it should have happened.
+    let mut early = session.clone();
+    early.events[1].instruction = 1;
+    let error = replay(&mut Program::new(code.clone()), &early).unwrap_err();
+    assert_eq!(error.to_string(), "Replay diverged at instruction 1 (address 2): \
+        expected output 10 at instruction 1 (address 6), got no input, output or halt");
+
+    // Asking for input before it was given is a divergence too.
+    let mut late = session.clone();
+    late.events[2].instruction = 4;
+    match replay(&mut Program::new(code.clone()), &late) {
+        Err(ReplayError::Diverged(divergence)) => {
+            assert_eq!((divergence.index, divergence.instruction, divergence.actual), (2, 3, None));
+        },
+        result => panic!("unexpected result: {:?}", result)
+    }
+
+    // Halting early.
+    let short = assemble(&DOUBLE.replace("input [y]", "halt")).unwrap();
+    match replay(&mut Program::new(short), &session) {
+        Err(ReplayError::Diverged(divergence)) => assert_eq!(divergence.actual, Some(EventKind::Halt)),
+        result => panic!("unexpected result: {:?}", result)
+    }
+}
+
+#[test]
+fn session_errors() {
+    match parse_session("intcode-session 2\n") {
+        Err(SessionError::UnsupportedVersion(version)) => assert_eq!(version, "2"),
+        result => panic!("unexpected result: {:?}", result)
+    }
+    match parse_session("intcode-session 1\n0 0 input 5\n1 2 jump 3\n") {
+        Err(SessionError::Parse { line, message }) => assert_eq!((line, message.as_str()), (3, "Unknown event 'jump'")),
+        result => panic!("unexpected result: {:?}", result)
+    }
+    assert!(parse_session("intcode-session 1\n0 0 output\n").is_err());
+    assert!(parse_session("intcode-session 1\n0 0 halt 1\n").is_err());
+    assert!(parse_session("1,2,3").is_err());
+}
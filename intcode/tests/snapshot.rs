@@ -0,0 +1,77 @@
+use intcode::*;
+use intcode::snapshot::*;
+
+fn breakout() -> Program {
+    let mut program = Program::new(load_code(&format!("{}/../13/input.txt", env!("CARGO_MANIFEST_DIR"))));
+    program.memory.write(0, 2);
+    program
+}
+
+// Play the game for a number of input requests, always moving right.
+fn play<M: Memory>(program: &mut Program<M>, moves: usize) -> Vec<i64> {
+    let mut outputs = Vec::new();
+    let mut moves_left = moves;
+    loop {
+        match run_program(program).unwrap() {
+            StepResult::Output => outputs.push(program.data.pop_front().unwrap()),
+            StepResult::NeedInput if moves_left > 0 => {
+                program.data.push_back(1);
+                moves_left -= 1;
+            },
+            _ => return outputs
+        }
+    }
+}
+
+#[test]
+fn snapshot_round_trip() {
+    let mut program = breakout();
+    play(&mut program, 50);
+    program.data.push_back(-1);
+    let text = format_snapshot(&program);
+
+    let mut restored = Program::with_memory(vec![99], SparseMemory::new());
+    restore_snapshot(&mut restored, &text).unwrap();
+    assert_eq!(format_snapshot(&restored), text);
+    assert_eq!(restored.code, program.code);
+    assert_eq!(restored.data, program.data);
+    assert_eq!(play(&mut restored, 100), play(&mut program, 100));
+    assert_eq!(format_snapshot(&restored), format_snapshot(&program));
+}
+
+#[test]
+fn snapshot_errors() {
+    let mut program = Program::new(parse_code("1,0,0,0,99"));
+    let text = format_snapshot(&program);
+
+    match restore_snapshot(&mut program, &text.replace("snapshot 2", "snapshot 3")) {
+        Err(SnapshotError::UnsupportedVersion(version)) => assert_eq!(version, "3"),
+        result => panic!("unexpected result: {:?}", result)
+    }
+    match restore_snapshot(&mut program, &text.replace("ip 0", "ip x")) {
+        Err(SnapshotError::Parse { line, .. }) => assert_eq!(line, 2),
+        result => panic!("unexpected result: {:?}", result)
+    }
+    assert!(restore_snapshot(&mut program, &text.replace("rel_base 0\n", "")).is_err());
+    assert!(restore_snapshot(&mut program, "1,0,0,0,99").is_err());
+    assert_eq!(format_snapshot(&program), text);
+}
+
+#[test]
+fn snapshot_arithmetic() {
+    let program = Program::new(parse_code("1,0,0,0,99")).with_arithmetic(Arithmetic::Saturating);
+    let text = format_snapshot(&program);
+    assert!(text.starts_with("intcode-snapshot 2\nip 0\nrel_base 0\narithmetic saturating\n"));
+
+    let mut restored = Program::new(vec![99]).with_arithmetic(Arithmetic::Checked);
+    restore_snapshot(&mut restored, &text).unwrap();
+    assert_eq!(restored.arithmetic, Arithmetic::Saturating);
+
+    // Version 1 snapshots were always taken in wrapping mode.
+    let version1 = text.replace("snapshot 2", "snapshot 1").replace("arithmetic saturating\n", "");
+    restore_snapshot(&mut restored, &version1).unwrap();
+    assert_eq!(restored.arithmetic, Arithmetic::Wrapping);
+
+    assert!(restore_snapshot(&mut restored, &text.replace("saturating", "exact")).is_err());
+    assert!(restore_snapshot(&mut restored, &text.replace("arithmetic saturating\n", "")).is_err());
+}
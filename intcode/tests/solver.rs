@@ -0,0 +1,167 @@
+use intcode::*;
+use intcode::asm::assemble;
+use intcode::solver::{Method, Polynomial, Solver};
+
+fn day2_solver() -> Solver {
+    let program = load_program(concat!(env!("CARGO_MANIFEST_DIR"), "/../2/input.txt"));
+    Solver::new(program, 0)
+        .with_unknown("noun", 1, 0..=99)
+        .with_unknown("verb", 2, 0..=99)
+}
+
+#[test]
+fn day2_expression() {
+    let solver = day2_solver();
+    let expression = solver.expression().unwrap();
+    assert_eq!(expression.format(&solver.names()), "230400*noun + verb + 1258669");
+    assert_eq!(expression.evaluate(&[12, 2]), Some(4023471));
+}
+
+#[test]
+fn day2_solve() {
+    let solver = day2_solver();
+    let solution = solver.solve(19690720);
+    assert!(matches!(solution.method, Method::Symbolic(_)));
+    assert_eq!(solution.inputs, [[80, 51]]);
+    assert_eq!(solver.solve_brute_force(19690720), solution.inputs);
+    assert!(solver.solve(1).inputs.is_empty());
+}
+
+// out and x are at addresses 3 and 4, the conditional jump at 9.
+const BRANCHING: &str = "
+        jump_if_true 1 start
+out:    .data 0
+x:      .data 0
+start:  [flag] = less_than [x] 5
+        jump_if_true [flag] small
+        [out] = multiply [x] 2
+        halt
+small:  [out] = add [x] 100
+        halt
+flag:   .data 0
+";
+
+#[test]
+fn branching_falls_back_to_brute_force() {
+    let solver = Solver::new(Program::new(assemble(BRANCHING).unwrap()), 3)
+        .with_unknown("x", 4, -10..=20)
+        .with_threads(3);
+    let error = solver.expression().unwrap_err();
+    assert_eq!(error.address, 9);
+    assert_eq!(error.message, "Jump condition is unknown: comparison of unknowns at 5");
+
+    let solution = solver.solve(104);
+    assert_eq!(solution.method, Method::BruteForce(error));
+    assert_eq!(solution.inputs, [[4]]);
+    assert_eq!(solver.solve(20).inputs, [[10]]);
+    assert_eq!(solver.solve(96).inputs, [[-4]]);
+    assert!(solver.solve(4).inputs.is_empty());
+}
+
+#[test]
+fn nonlinear_expression() {
+    let source = "
+        [out] = multiply [x] [x]
+        [out] = add [out] [y]
+        [out] = multiply [out] -3
+        [out] = add [out] 7
+        halt
+out:    .data 0
+x:      .data 0
+y:      .data 0
+";
+    let code = assemble(source).unwrap();
+    let out = code.len() as i64 - 3;
+    let solver = Solver::new(Program::new(code), out)
+        .with_unknown("x", out + 1, -5..=5)
+        .with_unknown("y", out + 2, 0..=10);
+    let expression = solver.expression().unwrap();
+    assert_eq!(expression.format(&solver.names()), "-3*x^2 - 3*y + 7");
+    assert_eq!(expression.degree(0), 2);
+
+    for value in &[-20, -50, 7, 8] {
+        let solution = solver.solve(*value);
+        assert!(matches!(solution.method, Method::Symbolic(_)));
+        assert_eq!(solution.inputs, solver.solve_brute_force(*value), "{}", value);
+    }
+    assert_eq!(solver.solve(-20).inputs, [[-3, 0], [-2, 5], [-1, 8], [0, 9], [1, 8], [2, 5], [3, 0]]);
+}
+
+#[test]
+fn computed_write_address() {
+    // [x] = add 1 2, with x unknown.
+    let solver = Solver::new(Program::new(vec![1101, 1, 2, 0, 99]), 0)
+        .with_unknown("x", 3, 0..=4);
+    assert_eq!(solver.expression().unwrap_err().message, "Write to an address that depends on the unknowns");
+    assert_eq!(solver.solve(3).inputs, [[0]]);
+}
+
+#[test]
+fn endless_loops_are_not_solutions() {
+    // Loop forever if x < 3.
+    let source = "
+        jump_if_true 1 start
+out:    .data 0
+x:      .data 0
+start:  [out] = add [x] 0
+        [flag] = less_than [x] 3
+loop:   jump_if_true [flag] loop
+        halt
+flag:   .data 0
+";
+    let solver = Solver::new(Program::new(assemble(source).unwrap()), 3)
+        .with_unknown("x", 4, 0..=9)
+        .with_max_instructions(1000);
+    let solution = solver.solve(5);
+    assert!(matches!(solution.method, Method::BruteForce(_)));
+    assert_eq!(solution.inputs, [[5]]);
+    assert!(solver.solve(1).inputs.is_empty());
+    assert!(!solver.check(&[1], 1));
+}
+
+#[test]
+fn huge_ranges() {
+    // out = -x, with x and y unknown.
+    let source = "
+        [out] = multiply [x] -1
+        halt
+out:    .data 0
+x:      .data 0
+y:      .data 0
+";
+    let full = i64::MIN..=i64::MAX;
+    let solver = Solver::new(Program::new(assemble(source).unwrap()), 5)
+        .with_unknown("x", 6, full.clone())
+        .with_unknown("y", 7, 0..=1);
+    assert_eq!(solver.combinations(), None);
+    assert_eq!(solver.solve(i64::MIN + 1).inputs, [[i64::MAX, 0], [i64::MAX, 1]]);
+    assert!(solver.solve(5).inputs.contains(&vec![-5, 1]));
+    assert!(solver.solve_brute_force(5).is_empty());
+
+    // -x = i64::MIN has no solution with checked arithmetic.
+    let program = Program::new(assemble(source).unwrap()).with_arithmetic(Arithmetic::Checked);
+    let solver = Solver::new(program, 5)
+        .with_unknown("x", 6, full.clone())
+        .with_unknown("y", 7, 0..=1);
+    assert!(solver.solve(i64::MIN).inputs.is_empty());
+
+    // Both unknowns are huge, so the other one can't be enumerated.
+    let solver = Solver::new(Program::new(assemble(source).unwrap()), 5)
+        .with_unknown("x", 6, full.clone())
+        .with_unknown("y", 7, full);
+    assert!(solver.solve(5).inputs.is_empty());
+}
+
+#[test]
+fn polynomial_arithmetic() {
+    let x = Polynomial::variable(0, 2);
+    let y = Polynomial::variable(1, 2);
+    let names = vec!["x".to_string(), "y".to_string()];
+    let sum = x.checked_add(&Polynomial::constant(-1, 2)).unwrap();
+    let product = sum.checked_mul(&sum).unwrap().checked_mul(&y).unwrap();
+    assert_eq!(product.format(&names), "x^2*y - 2*x*y + y");
+    assert_eq!(product.evaluate(&[3, 5]), Some(20));
+    assert_eq!(Polynomial::constant(0, 2).format(&names), "0");
+    assert_eq!(product.checked_add(&product.checked_mul(&Polynomial::constant(-1, 2)).unwrap()).unwrap().as_constant(), Some(0));
+    assert_eq!(Polynomial::constant(i64::MAX, 2).checked_add(&Polynomial::constant(1, 2)), None);
+}
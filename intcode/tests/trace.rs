@@ -0,0 +1,60 @@
+use std::sync::Arc;
+use intcode::*;
+use intcode::opcodes::{Effect, OpcodeSet, Param};
+use intcode::trace::*;
+
+#[test]
+fn trace_records() {
+    let mut program = Program::new(parse_code("3,9,1002,9,-2,10,4,10,99,0,0"));
+    program.data.push_back(7);
+
+    let mut tracer = TraceWriter::new(Vec::new(), TraceFormat::Text);
+    assert_eq!(run_program_traced(&mut program, &mut tracer), Ok(StepResult::Output));
+    assert_eq!(run_program_traced(&mut program, &mut tracer), Ok(StepResult::Halt));
+    assert_eq!(tracer.count(), 4);
+    assert_eq!(String::from_utf8(tracer.into_inner()).unwrap(), "\
+0000 input [9] -> [9]=7 rb=0
+0002 multiply [9]=7 -2 [10] -> [10]=-14 rb=0
+0006 output [10]=-14 rb=0
+0008 halt rb=0
+");
+}
+
+#[test]
+fn trace_filters() {
+    let mut program = Program::new(parse_code("1101,1,2,9,109,5,204,4,99,0"));
+
+    let mut tracer = TraceWriter::new(Vec::new(), TraceFormat::JsonLines)
+        .with_range(4..=8)
+        .with_limit(2);
+    assert_eq!(run_program_traced(&mut program, &mut tracer), Ok(StepResult::Output));
+    assert_eq!(String::from_utf8(tracer.into_inner()).unwrap(), "\
+{\"ip\":4,\"instr\":109,\"opcode\":\"rel_base_offset\",\"modes\":[1],\"params\":[5],\"values\":[5],\"write\":null,\"rel_base\":0}
+{\"ip\":6,\"instr\":204,\"opcode\":\"output\",\"modes\":[2],\"params\":[4],\"values\":[3],\"write\":null,\"rel_base\":5}
+");
+}
+
+#[test]
+fn trace_custom_opcodes() {
+    let mut opcodes = OpcodeSet::new();
+    opcodes.register(20, "divmod", &[Param::Read, Param::Read, Param::Write, Param::Write], |args, _| {
+        Ok(Effect::Store(vec![args[0] / args[1], args[0] % args[1]]))
+    }).unwrap();
+    opcodes.register(21, "store", &[Param::Write, Param::Read], |args, _| Ok(Effect::Store(vec![args[0]]))).unwrap();
+    let opcodes = Arc::new(opcodes);
+
+    // The written operand is not always the last one.
+    let mut program = Program::new(parse_code("1120,17,5,13,14,1021,15,42,4,13,4,15,99,0,0,0")).with_opcodes(opcodes.clone());
+    let mut tracer = TraceWriter::new(Vec::new(), TraceFormat::Text).with_opcodes(opcodes.clone());
+    assert_eq!(run_program_traced(&mut program, &mut tracer), Ok(StepResult::Output));
+    assert_eq!(String::from_utf8(tracer.into_inner()).unwrap(), "\
+0000 divmod 17 5 [13] [14] -> [13]=3 rb=0
+0005 store [15] 42 -> [15]=42 rb=0
+0008 output [13]=3 rb=0
+");
+
+    let mut program = Program::new(parse_code("1021,4,42,99,0")).with_opcodes(opcodes.clone());
+    let mut tracer = TraceWriter::new(Vec::new(), TraceFormat::JsonLines).with_opcodes(opcodes);
+    assert_eq!(run_program_traced(&mut program, &mut tracer), Ok(StepResult::Halt));
+    assert!(String::from_utf8(tracer.into_inner()).unwrap().starts_with("{\"ip\":0,\"instr\":1021,\"opcode\":\"store\","));
+}
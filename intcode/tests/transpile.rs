@@ -0,0 +1,132 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use intcode::*;
+use intcode::asm::assemble;
+use intcode::transpile::transpile;
+
+// Writes its input over the operand of a compiled instruction.
+const PATCHED: &str = "
+        rel_base_offset out
+        input [$1]
+out:    output 7
+        output [x]
+        halt
+x:      .data 5
+";
+
+const FAULT: &str = "
+        output 1
+        rel_base_offset -5
+        output [$0]
+        halt
+";
+
+// Uses a cell far beyond the code.
+const FAR: &str = "
+        add 7 0 [1000000000000]
+        output [1000000000000]
+        halt
+";
+
+const MAIN: &str = r#"
+mod day5;
+mod day9;
+mod far;
+mod fault;
+mod patched;
+
+macro_rules! run {
+    ($module:ident, $input:expr) => {{
+        let mut program = $module::Program::new();
+        program.data.extend($input);
+        let mut outputs = Vec::new();
+        loop {
+            match $module::run_program(&mut program) {
+                Ok($module::StepResult::Output) => outputs.push(program.data.pop_back().unwrap()),
+                result => break format!("{:?} {:?}", outputs, result)
+            }
+        }
+    }};
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let input: Vec<i64> = args[1..].iter().map(|x| x.parse().unwrap()).collect();
+    let result = match args[0].as_str() {
+        "day5" => run!(day5, input),
+        "day9" => run!(day9, input),
+        "far" => run!(far, input),
+        "fault" => run!(fault, input),
+        _ => run!(patched, input)
+    };
+    println!("{}", result);
+}
+"#;
+
+fn puzzle_input(day: &str) -> Vec<i64> {
+    load_code(&format!("{}/../{}/input.txt", env!("CARGO_MANIFEST_DIR"), day))
+}
+
+fn interpret(code: &[i64], input: &[i64]) -> String {
+    let mut program = Program::new(code.to_vec());
+    program.data.extend(input);
+    let mut outputs = Vec::new();
+    loop {
+        match run_program(&mut program) {
+            Ok(StepResult::Output) => outputs.push(program.data.pop_back().unwrap()),
+            result => return format!("{:?} {:?}", outputs, result)
+        }
+    }
+}
+
+#[test]
+fn transpiled_programs_match_interpreter() {
+    let programs = [
+        ("day5", puzzle_input("5")),
+        ("day9", puzzle_input("9")),
+        ("fault", assemble(FAULT).unwrap()),
+        ("patched", assemble(PATCHED).unwrap()),
+        ("far", assemble(FAR).unwrap())
+    ];
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("transpile");
+    fs::create_dir_all(&dir).unwrap();
+    for (name, code) in &programs {
+        fs::write(dir.join(format!("{}.rs", name)), transpile(code)).unwrap();
+    }
+    fs::write(dir.join("main.rs"), MAIN).unwrap();
+    let binary = dir.join("programs");
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let status = Command::new(rustc)
+        .args(["--edition", "2018", "-D", "warnings", "-o"])
+        .arg(&binary)
+        .arg(dir.join("main.rs"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let runs: [(&str, &[i64]); 7] = [
+        ("day5", &[1]), ("day5", &[5]), ("day9", &[1]), ("day9", &[2]), ("fault", &[]), ("patched", &[42]), ("far", &[])
+    ];
+    for (name, input) in runs.iter() {
+        let code = &programs.iter().find(|(n, _)| n == name).unwrap().1;
+        let output = Command::new(&binary).arg(name).args(input.iter().map(|x| x.to_string())).output().unwrap();
+        let text = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(text.trim_end(), interpret(code, input), "{} {:?}", name, input);
+    }
+    assert_eq!(interpret(&programs[1].1, &[2]), "[59095] Ok(Halt)");
+    assert_eq!(interpret(&programs[3].1, &[42]), "[42, 5] Ok(Halt)");
+    assert!(interpret(&programs[2].1, &[]).contains("NegativeAddress { address: 4, instr: 204"));
+    assert_eq!(interpret(&programs[4].1, &[]), "[7] Ok(Halt)");
+}
+
+#[test]
+fn self_modifying_code_is_interpreted() {
+    // Day 2 overwrites its own instructions, which are left to the interpreter.
+    let text = transpile(&puzzle_input("2"));
+    assert!(text.contains("// 5 of 48 reachable instructions are compiled"), "{}", text);
+    let text = transpile(&assemble(PATCHED).unwrap());
+    assert!(text.contains("// 5 of 5 reachable instructions are compiled"), "{}", text);
+}
@@ -0,0 +1,221 @@
+// Instruction-level execution tracing.
+//
+// run_program_traced() works like run_program() but passes a record of every
+// executed instruction to a Tracer. TraceWriter writes the records as JSON
+// Lines or as compact text, optionally limited to an address range and to a
+// number of records.
+
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use super::*;
+use super::opcodes::{OpcodeSet, Param};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub ip: i64,
+    pub instr: i64,
+    pub opcode: i64,
+    pub modes: Vec<i64>,
+    // Parameters as stored in memory.
+    pub params: Vec<i64>,
+    // Parameter values: the value read for input parameters and the resolved
+    // address for output parameters.
+    pub values: Vec<i64>,
+    // Address and value written by the instruction, if any.
+    pub write: Option<(i64, i64)>,
+    // Relative base at the time the instruction was executed.
+    pub rel_base: i64
+}
+
+pub trait Tracer {
+    fn trace(&mut self, record: &TraceRecord);
+}
+
+// Decode the instruction at ip without executing it. Parameter values are
+// resolved against the current memory, so this must be called before the
+// instruction is executed. Also returns the index of the output parameter.
+fn make_record<M: Memory>(program: &Program<M>) -> (TraceRecord, Option<usize>) {
+    let memory = &program.memory;
+    let ip = program.ip;
+    let instr = memory.read(ip);
+    let opcode = read_opcode(instr);
+    let kinds = program.opcodes.params(opcode).unwrap_or(&[]);
+    let count = kinds.len();
+    let write_index = kinds.iter().position(|&kind| kind == Param::Write);
+
+    let mut record = TraceRecord {
+        ip,
+        instr,
+        opcode,
+        modes: Vec::with_capacity(count),
+        params: Vec::with_capacity(count),
+        values: Vec::with_capacity(count),
+        write: None,
+        rel_base: program.rel_base
+    };
+
+    for (i, &kind) in kinds.iter().enumerate() {
+        let mode = read_param_mode(instr, i as u32);
+        let param = memory.read(ip + 1 + i as i64);
+        // An overflow faults in step_program(), so the record is not used.
+        let address = if mode == MODE_REL {
+            program.arithmetic.add(param, program.rel_base).unwrap_or(-1)
+        } else {
+            param
+        };
+        let value = if kind == Param::Write || mode == MODE_IMM || address < 0 {
+            address
+        } else {
+            memory.read(address)
+        };
+        record.modes.push(mode);
+        record.params.push(param);
+        record.values.push(value);
+    }
+
+    (record, write_index)
+}
+
+// Execute a single instruction like step_program() and pass its record to
+// the tracer. Instructions that fail or wait for input are not traced since
+// they are not executed.
+pub fn step_program_traced<M: Memory, T: Tracer + ?Sized>(program: &mut Program<M>, tracer: &mut T)
+        -> Result<Option<StepResult>, VmError> {
+    let (mut record, write_index) = make_record(program);
+    let result = step_program(program)?;
+    if result == Some(StepResult::NeedInput) {
+        return Ok(result);
+    }
+
+    if let Some(index) = write_index {
+        let address = record.values[index];
+        record.write = Some((address, program.memory.read(address)));
+    }
+    tracer.trace(&record);
+    Ok(result)
+}
+
+pub fn run_program_traced<M: Memory, T: Tracer + ?Sized>(program: &mut Program<M>, tracer: &mut T)
+        -> Result<StepResult, VmError> {
+    loop {
+        if let Some(result) = step_program_traced(program, tracer)? {
+            return Ok(result);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    JsonLines,
+    Text
+}
+
+pub struct TraceWriter<W: Write> {
+    out: W,
+    format: TraceFormat,
+    opcodes: Arc<OpcodeSet>,
+    range: Option<RangeInclusive<i64>>,
+    limit: Option<u64>,
+    count: u64
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W, format: TraceFormat) -> TraceWriter<W> {
+        TraceWriter { out, format, opcodes: Arc::new(OpcodeSet::new()), range: None, limit: None, count: 0 }
+    }
+
+    // Opcodes of the traced program, used for the names and operands of
+    // custom instructions.
+    pub fn with_opcodes(mut self, opcodes: Arc<OpcodeSet>) -> TraceWriter<W> {
+        self.opcodes = opcodes;
+        self
+    }
+
+    // Only trace instructions located in the address range.
+    pub fn with_range(mut self, range: RangeInclusive<i64>) -> TraceWriter<W> {
+        self.range = Some(range);
+        self
+    }
+
+    // Stop tracing after the specified number of records.
+    pub fn with_limit(mut self, limit: u64) -> TraceWriter<W> {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        if let Some(range) = &self.range {
+            if !range.contains(&record.ip) {
+                return;
+            }
+        }
+        if self.limit.is_some_and(|limit| self.count >= limit) {
+            return;
+        }
+        self.count += 1;
+
+        let line = match self.format {
+            TraceFormat::JsonLines => format_json_with(record, &self.opcodes),
+            TraceFormat::Text => format_text_with(record, &self.opcodes)
+        };
+        writeln!(self.out, "{}", line).unwrap();
+    }
+}
+
+fn join(values: &[i64]) -> String {
+    values.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(",")
+}
+
+pub fn format_json(record: &TraceRecord) -> String {
+    format_json_with(record, &OpcodeSet::new())
+}
+
+pub fn format_json_with(record: &TraceRecord, opcodes: &OpcodeSet) -> String {
+    let write = match record.write {
+        Some((address, value)) => format!("{{\"address\":{},\"value\":{}}}", address, value),
+        None => "null".to_string()
+    };
+    format!("{{\"ip\":{},\"instr\":{},\"opcode\":\"{}\",\"modes\":[{}],\"params\":[{}],\"values\":[{}],\"write\":{},\"rel_base\":{}}}",
+        record.ip, record.instr, opcodes.name(record.opcode).unwrap_or("?"),
+        join(&record.modes), join(&record.params), join(&record.values),
+        write, record.rel_base)
+}
+
+// Format a record as: ip, mnemonic, parameters with their values and the
+// written value, e.g. "0017 multiply -1 [8]=5 [10] -> [10]=-5 rb=0".
+pub fn format_text(record: &TraceRecord) -> String {
+    format_text_with(record, &OpcodeSet::new())
+}
+
+pub fn format_text_with(record: &TraceRecord, opcodes: &OpcodeSet) -> String {
+    let kinds = opcodes.params(record.opcode).unwrap_or(&[]);
+    let mut line = format!("{:0>4} {}", record.ip, opcodes.name(record.opcode).unwrap_or("?"));
+    for i in 0..record.params.len() {
+        let (mode, param, value) = (record.modes[i], record.params[i], record.values[i]);
+        let is_write = kinds.get(i) == Some(&Param::Write);
+        line.push(' ');
+        match mode {
+            MODE_IMM => line.push_str(&param.to_string()),
+            MODE_POS if is_write => line.push_str(&format!("[{}]", param)),
+            MODE_POS => line.push_str(&format!("[{}]={}", param, value)),
+            MODE_REL if is_write => line.push_str(&format!("[${}]", param)),
+            _ => line.push_str(&format!("[${}]={}", param, value))
+        }
+    }
+    if let Some((address, value)) = record.write {
+        line.push_str(&format!(" -> [{}]={}", address, value));
+    }
+    line.push_str(&format!(" rb={}", record.rel_base));
+    line
+}
@@ -0,0 +1,479 @@
+// Compile Intcode programs to Rust ahead of time.
+//
+// transpile() generates a self-contained Rust module with the same interface
+// as the VM: a Program with ip, rel_base, memory and the data queue, and
+// run_program() that stops when the program halts, produces an output value
+// or needs an input value.
+//
+// The instructions found by disasm::analyze() are compiled into basic blocks,
+// which are the arms of a match on the instruction pointer. Everything else is
+// run by an interpreter embedded in the module: instructions that are
+// overwritten by the program, jumps into the middle of a block and code that
+// is only reached through computed jumps. As soon as the program writes to a
+// word of a compiled instruction at run time, the compiled code is abandoned
+// and the rest of the program is interpreted. Arithmetic wraps around like
+// with Arithmetic::Wrapping.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use super::*;
+use super::disasm::{Instruction, analyze, format_instruction};
+
+const RUNTIME: &str = r#"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepResult {
+    Halt,
+    NeedInput,
+    Output
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VmError {
+    InvalidOpcode { address: i64, instr: i64, opcode: i64 },
+    InvalidMode { address: i64, instr: i64, opcode: i64, index: u32, mode: i64 },
+    ImmediateWrite { address: i64, instr: i64, opcode: i64, index: u32 },
+    NegativeAddress { address: i64, instr: i64, opcode: i64, index: u32, mode: i64, target: i64 },
+    IpOutOfRange { address: i64 }
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::InvalidOpcode { address, instr, opcode } =>
+                write!(f, "Invalid opcode {} at address {} (instruction {})", opcode, address, instr),
+            VmError::InvalidMode { address, instr, opcode, index, mode } =>
+                write!(f, "Invalid mode {} for parameter {} of opcode {} at address {} (instruction {})",
+                    mode, index + 1, opcode, address, instr),
+            VmError::ImmediateWrite { address, instr, opcode, index } =>
+                write!(f, "Write in immediate mode by parameter {} of opcode {} at address {} (instruction {})",
+                    index + 1, opcode, address, instr),
+            VmError::NegativeAddress { address, instr, opcode, index, mode, target } =>
+                write!(f, "Access to negative address {} by parameter {} (mode {}) of opcode {} at address {} (instruction {})",
+                    target, index + 1, mode, opcode, address, instr),
+            VmError::IpOutOfRange { address } =>
+                write!(f, "Instruction pointer out of range: {}", address)
+        }
+    }
+}
+
+impl error::Error for VmError {}
+
+const MODE_POS: i64 = 0;
+const MODE_IMM: i64 = 1;
+const MODE_REL: i64 = 2;
+
+// Memory below this address is a vector, cells above it are kept in a hash
+// map so that writing to a large address doesn't allocate everything below.
+const DENSE_MEMORY: usize = 1 << 20;
+
+// Program state. The data queue is used for both directions: input values
+// are consumed from the front and output values are appended to the back.
+#[derive(Clone)]
+pub struct Program {
+    pub ip: i64,
+    pub memory: Vec<i64>,
+    pub high_memory: HashMap<i64, i64>,
+    pub rel_base: i64,
+    pub data: VecDeque<i64>,
+    // Cleared when the program overwrites compiled code.
+    pub native: bool
+}
+
+impl Program {
+    pub fn new() -> Program {
+        Program {
+            ip: 0,
+            memory: CODE.to_vec(),
+            high_memory: HashMap::new(),
+            rel_base: 0,
+            data: VecDeque::new(),
+            native: true
+        }
+    }
+
+    pub fn read(&self, address: i64) -> i64 {
+        match self.memory.get(address as usize) {
+            Some(&value) => value,
+            None => self.high_memory.get(&address).cloned().unwrap_or(0)
+        }
+    }
+
+    pub fn write(&mut self, address: i64, value: i64) {
+        let index = address as usize;
+        if index >= DENSE_MEMORY {
+            self.high_memory.insert(address, value);
+            return;
+        }
+        if index >= self.memory.len() {
+            self.memory.resize(index + 1, 0);
+        }
+        self.memory[index] = value;
+        if index < COMPILED.len() && COMPILED[index] == b'1' {
+            self.native = false;
+        }
+    }
+}
+
+impl Default for Program {
+    fn default() -> Program {
+        Program::new()
+    }
+}
+
+fn param_address(program: &Program, index: u32, write: bool) -> Result<Option<i64>, VmError> {
+    let address = program.ip;
+    let instr = program.read(address);
+    let opcode = instr % 100;
+    let mode = instr % 10_i64.pow(index + 3) / 10_i64.pow(index + 2);
+    let param = program.read(address + 1 + index as i64);
+    let target = match mode {
+        MODE_POS => param,
+        MODE_IMM if write => return Err(VmError::ImmediateWrite { address, instr, opcode, index }),
+        MODE_IMM => return Ok(None),
+        MODE_REL => param.wrapping_add(program.rel_base),
+        _ => return Err(VmError::InvalidMode { address, instr, opcode, index, mode })
+    };
+    if target < 0 {
+        return Err(VmError::NegativeAddress { address, instr, opcode, index, mode, target });
+    }
+    Ok(Some(target))
+}
+
+fn param_value(program: &Program, index: u32) -> Result<i64, VmError> {
+    match param_address(program, index, false)? {
+        Some(address) => Ok(program.read(address)),
+        None => Ok(program.read(program.ip + 1 + index as i64))
+    }
+}
+
+// Execute a single instruction with the interpreter.
+fn step_program(program: &mut Program) -> Result<Option<StepResult>, VmError> {
+    let address = program.ip;
+    if address < 0 || address >= CODE.len() as i64 {
+        return Err(VmError::IpOutOfRange { address });
+    }
+    let instr = program.read(address);
+    match instr % 100 {
+        99 => return Ok(Some(StepResult::Halt)),
+        opcode @ 1..=2 | opcode @ 7..=8 => {
+            let a = param_value(program, 0)?;
+            let b = param_value(program, 1)?;
+            let target = param_address(program, 2, true)?.unwrap();
+            let value = match opcode {
+                1 => a.wrapping_add(b),
+                2 => a.wrapping_mul(b),
+                7 => (a < b) as i64,
+                _ => (a == b) as i64
+            };
+            program.write(target, value);
+            program.ip += 4;
+        },
+        3 => {
+            let target = param_address(program, 0, true)?.unwrap();
+            match program.data.pop_front() {
+                Some(value) => program.write(target, value),
+                None => return Ok(Some(StepResult::NeedInput))
+            }
+            program.ip += 2;
+        },
+        4 => {
+            let value = param_value(program, 0)?;
+            program.data.push_back(value);
+            program.ip += 2;
+            return Ok(Some(StepResult::Output));
+        },
+        opcode @ 5..=6 => {
+            let condition = param_value(program, 0)?;
+            let target = param_value(program, 1)?;
+            if (opcode == 5) == (condition != 0) {
+                program.ip = target;
+            } else {
+                program.ip += 3;
+            }
+        },
+        9 => {
+            let offset = param_value(program, 0)?;
+            program.rel_base = program.rel_base.wrapping_add(offset);
+            program.ip += 2;
+        },
+        opcode => return Err(VmError::InvalidOpcode { address, instr, opcode })
+    }
+    Ok(None)
+}
+
+// Run the program until it halts, produces an output value or needs an input
+// value that is not in the data queue. In the latter two cases the program can
+// be resumed by calling run_program() again.
+pub fn run_program(program: &mut Program) -> Result<StepResult, VmError> {
+    loop {
+        if program.native {
+            if let Some(result) = run_native(program)? {
+                return Ok(result);
+            }
+        }
+        if let Some(result) = step_program(program)? {
+            return Ok(result);
+        }
+    }
+}
+
+// Address of a relative mode parameter in compiled code.
+#[allow(unused_macros)]
+macro_rules! rel {
+    ($program:ident, $rb:ident, $address:expr, $instr:expr, $index:expr, $offset:expr) => {
+        match $rb.wrapping_add($offset) {
+            target if target < 0 => {
+                $program.ip = $address;
+                $program.rel_base = $rb;
+                return Err(VmError::NegativeAddress {
+                    address: $address, instr: $instr, opcode: $instr % 100, index: $index, mode: MODE_REL, target
+                });
+            },
+            target => target
+        }
+    };
+}
+"#;
+
+// Whether the instruction can be compiled: static addresses must be valid
+// and the output parameter can't be in immediate mode.
+fn is_compilable(instruction: &Instruction) -> bool {
+    let write_index = match instruction.opcode {
+        OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => Some(2),
+        OP_INPUT => Some(0),
+        _ => None
+    };
+    instruction.params.iter().enumerate().all(|(index, &(mode, value))| match mode {
+        MODE_POS => value >= 0,
+        MODE_IMM => write_index != Some(index),
+        _ => true
+    })
+}
+
+struct Compiler<'a> {
+    compiled: BTreeMap<i64, &'a Instruction>,
+    block_starts: BTreeSet<i64>,
+    text: String,
+    // Whether any block continues at another block.
+    sets_ip: bool
+}
+
+impl<'a> Compiler<'a> {
+    // Bind the value of the parameter to a variable.
+    fn load(&mut self, instruction: &Instruction, index: usize, name: &str) {
+        match instruction.params[index] {
+            (MODE_IMM, value) => self.line(&format!("let {}: i64 = {};", name, value)),
+            (MODE_POS, value) => self.line(&format!("let {} = program.read({});", name, value)),
+            (_, value) => {
+                let address = self.rel(instruction, index, value);
+                self.line(&format!("let {} = {};", name, address));
+                self.line(&format!("let {} = program.read({});", name, name));
+            }
+        }
+    }
+
+    fn rel(&self, instruction: &Instruction, index: usize, offset: i64) -> String {
+        format!("rel!(program, rb, {}, {}, {}, {})", instruction.address, instruction.instr, index, offset)
+    }
+
+    fn address(&self, instruction: &Instruction, index: usize) -> String {
+        match instruction.params[index] {
+            (MODE_REL, value) => self.rel(instruction, index, value),
+            (_, value) => value.to_string()
+        }
+    }
+
+    fn set_ip(&mut self, line: &str) {
+        self.sets_ip = true;
+        self.line(line);
+    }
+
+    fn line(&mut self, line: &str) {
+        writeln!(self.text, "                {}", line).unwrap();
+    }
+
+    fn exit(&mut self, ip: i64, result: &str) {
+        self.line(&format!("program.ip = {};", ip));
+        self.line("program.rel_base = rb;");
+        self.line(&format!("return Ok(Some(StepResult::{}));", result));
+    }
+
+    // Emit the instruction, returns false if it ends the block.
+    fn instruction(&mut self, instruction: &Instruction) -> bool {
+        let address = instruction.address;
+        let end = address + instruction.size();
+        let rel_write = |index: usize| instruction.params[index].0 == MODE_REL;
+        self.line(&format!("// {}", format_instruction(instruction).trim()));
+
+        match instruction.opcode {
+            OP_ADD | OP_MULTIPLY | OP_LESS_THAN | OP_EQUALS => {
+                self.load(instruction, 0, "a");
+                self.load(instruction, 1, "b");
+                let target = self.address(instruction, 2);
+                self.line(&format!("let target = {};", target));
+                let value = match instruction.opcode {
+                    OP_ADD => "a.wrapping_add(b)",
+                    OP_MULTIPLY => "a.wrapping_mul(b)",
+                    OP_LESS_THAN => "(a < b) as i64",
+                    _ => "(a == b) as i64"
+                };
+                self.line(&format!("program.write(target, {});", value));
+                if rel_write(2) {
+                    self.set_ip(&format!("if !program.native {{ ip = {}; continue; }}", end));
+                }
+            },
+            OP_INPUT => {
+                let target = self.address(instruction, 0);
+                self.line(&format!("let target = {};", target));
+                self.line("match program.data.pop_front() {");
+                self.line("    Some(value) => program.write(target, value),");
+                self.line("    None => {");
+                self.line(&format!("        program.ip = {};", address));
+                self.line("        program.rel_base = rb;");
+                self.line("        return Ok(Some(StepResult::NeedInput));");
+                self.line("    }");
+                self.line("}");
+                if rel_write(0) {
+                    self.set_ip(&format!("if !program.native {{ ip = {}; continue; }}", end));
+                }
+            },
+            OP_OUTPUT => {
+                self.load(instruction, 0, "a");
+                self.line("program.data.push_back(a);");
+                self.exit(end, "Output");
+                return false;
+            },
+            OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => {
+                self.load(instruction, 0, "a");
+                self.load(instruction, 1, "b");
+                let op = if instruction.opcode == OP_JUMP_IF_TRUE { "!=" } else { "==" };
+                self.set_ip(&format!("ip = if a {} 0 {{ b }} else {{ {} }};", op, end));
+                return false;
+            },
+            OP_REL_BASE_OFFSET => {
+                self.load(instruction, 0, "a");
+                self.line("rb = rb.wrapping_add(a);");
+            },
+            _ => {
+                self.exit(address, "Halt");
+                return false;
+            }
+        }
+
+        if !self.compiled.contains_key(&end) || self.block_starts.contains(&end) {
+            self.set_ip(&format!("ip = {};", end));
+            return false;
+        }
+        true
+    }
+
+    fn block(&mut self, start: i64) {
+        writeln!(self.text, "            {} => {{", start).unwrap();
+        let mut address = start;
+        loop {
+            let instruction = self.compiled[&address];
+            if !self.instruction(instruction) {
+                break;
+            }
+            address += instruction.size();
+        }
+        writeln!(self.text, "            }},").unwrap();
+    }
+}
+
+// Generate a Rust module that runs the program.
+pub fn transpile(code: &[i64]) -> String {
+    let analysis = analyze(code);
+    let overwritten: BTreeSet<i64> = analysis.code_writes.values().cloned().collect();
+    let compiled: BTreeMap<i64, &Instruction> = analysis.instructions.iter()
+        .filter(|&(address, instruction)| !overwritten.contains(address) && is_compilable(instruction))
+        .map(|(&address, instruction)| (address, instruction))
+        .collect();
+
+    // Blocks start wherever the compiled code can be entered: jump targets,
+    // return addresses, where execution resumes after input and output, and
+    // after instructions left to the interpreter.
+    let mut block_starts: BTreeSet<i64> = analysis.jump_targets.clone();
+    block_starts.insert(0);
+    for (&address, instruction) in &analysis.instructions {
+        let end = address + instruction.size();
+        match instruction.opcode {
+            OP_INPUT => {
+                block_starts.insert(address);
+            },
+            OP_OUTPUT | OP_JUMP_IF_TRUE | OP_JUMP_IF_FALSE => {
+                block_starts.insert(end);
+            },
+            _ => {}
+        }
+        if !compiled.contains_key(&address) {
+            block_starts.insert(end);
+        }
+    }
+    block_starts.retain(|address| compiled.contains_key(address));
+
+    let mut words = vec![b'0'; code.len()];
+    for instruction in compiled.values() {
+        for address in instruction.address..instruction.address + instruction.size() {
+            words[address as usize] = b'1';
+        }
+    }
+
+    let mut text = String::new();
+    writeln!(text, "// Generated by intcode_transpile, do not edit.").unwrap();
+    writeln!(text, "//").unwrap();
+    writeln!(text, "// {} of {} reachable instructions are compiled, the rest is run by the",
+        compiled.len(), analysis.instructions.len()).unwrap();
+    writeln!(text, "// interpreter.").unwrap();
+    writeln!(text).unwrap();
+    writeln!(text, "#![allow(clippy::all)]").unwrap();
+    writeln!(text).unwrap();
+    writeln!(text, "use std::collections::{{HashMap, VecDeque}};").unwrap();
+    writeln!(text, "use std::error;").unwrap();
+    writeln!(text, "use std::fmt;").unwrap();
+    writeln!(text).unwrap();
+
+    writeln!(text, "pub const CODE: [i64; {}] = [", code.len()).unwrap();
+    for chunk in code.chunks(10) {
+        let values: Vec<String> = chunk.iter().map(|value| value.to_string()).collect();
+        writeln!(text, "    {},", values.join(", ")).unwrap();
+    }
+    writeln!(text, "];").unwrap();
+    writeln!(text).unwrap();
+    writeln!(text, "// '1' for the words of compiled instructions.").unwrap();
+    writeln!(text, "const COMPILED: &[u8] = b\"\\").unwrap();
+    for chunk in words.chunks(64) {
+        writeln!(text, "    {}\\", String::from_utf8_lossy(chunk)).unwrap();
+    }
+    writeln!(text, "\";").unwrap();
+    text.push_str(RUNTIME);
+    writeln!(text).unwrap();
+
+    writeln!(text, "// Run compiled code. Returns None when it reaches code that must be").unwrap();
+    writeln!(text, "// interpreted.").unwrap();
+    writeln!(text, "fn run_native(program: &mut Program) -> Result<Option<StepResult>, VmError> {{").unwrap();
+    if block_starts.is_empty() {
+        writeln!(text, "    let _ = program;").unwrap();
+        writeln!(text, "    Ok(None)").unwrap();
+        writeln!(text, "}}").unwrap();
+        return text;
+    }
+    let changes_rel_base = compiled.values().any(|instruction| instruction.opcode == OP_REL_BASE_OFFSET);
+    let mut compiler = Compiler { compiled, block_starts: block_starts.clone(), text: String::new(), sets_ip: false };
+    for &start in &block_starts {
+        compiler.block(start);
+    }
+    writeln!(text, "    let {}ip = program.ip;", if compiler.sets_ip { "mut " } else { "" }).unwrap();
+    writeln!(text, "    let {}rb = program.rel_base;", if changes_rel_base { "mut " } else { "" }).unwrap();
+    writeln!(text, "    while program.native {{").unwrap();
+    writeln!(text, "        match ip {{").unwrap();
+    text.push_str(&compiler.text);
+    writeln!(text, "            _ => break").unwrap();
+    writeln!(text, "        }}").unwrap();
+    writeln!(text, "    }}").unwrap();
+    writeln!(text, "    program.ip = ip;").unwrap();
+    writeln!(text, "    program.rel_base = rb;").unwrap();
+    writeln!(text, "    Ok(None)").unwrap();
+    writeln!(text, "}}").unwrap();
+    text
+}
@@ -0,0 +1,26 @@
+// Convert programs written in the disassembler's language back into Intcode.
+
+use std::env;
+use std::fs;
+use std::process;
+use intcode::asm::assemble;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <source_path>", args[0]);
+        process::exit(1);
+    }
+
+    let source = fs::read_to_string(&args[1]).unwrap();
+    match assemble(&source) {
+        Ok(code) => {
+            let words: Vec<String> = code.iter().map(|x| x.to_string()).collect();
+            println!("{}", words.join(","));
+        },
+        Err(error) => {
+            eprintln!("{}: {}", args[1], error);
+            process::exit(1);
+        }
+    }
+}
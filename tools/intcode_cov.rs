@@ -0,0 +1,159 @@
+// Run an Intcode program one or more times and show which instructions and
+// branches were exercised, as a summary and an annotated disassembly.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::collections::BTreeMap;
+use intcode::*;
+use intcode::coverage::{Coverage, annotate_listing, format_lcov, format_summary, parse_lcov};
+use intcode::disasm::{disassemble, disassemble_recursive_from, disassemble_symbolic_from, parse_symbols};
+use intcode::opcodes::OpcodeSet;
+use intcode::ports::{FixedInput, IntcodeInput, StdinInput};
+use intcode::trace::run_program_traced;
+
+const USAGE: &str = "\
+Usage: intcode_cov [options] <program_path>
+
+Options:
+  --input <v1,v2,...>      run the program with these input values; can be
+                           given several times to merge the coverage of
+                           several runs (default: one run reading from stdin)
+  --set <address>=<value>  change a memory cell before each run
+  --lcov <path>            add the coverage to an lcov-like tracefile,
+                           creating it if it doesn't exist
+  --linear | --raw         listing style, see intcode_disasm
+  --symbols <path>         read names of labels and variables from a file
+  --no-listing             only show the summary
+  --quiet                  don't print the program's output";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_or_exit<T: std::str::FromStr>(text: &str) -> T {
+    text.parse::<T>().unwrap_or_else(|_| usage())
+}
+
+// Run the program until it halts or runs out of input.
+fn run(program: &mut Program, input: &mut dyn IntcodeInput, coverage: &mut Coverage, quiet: bool) {
+    loop {
+        match run_program_traced(program, coverage) {
+            Ok(StepResult::NeedInput) => {
+                match input.read() {
+                    Some(value) => program.data.push_back(value),
+                    None => break
+                }
+            },
+            Ok(StepResult::Output) => {
+                let value = program.data.pop_back().unwrap();
+                if !quiet {
+                    println!("{}", value);
+                }
+            },
+            Ok(StepResult::Halt) => break,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut inputs = Vec::new();
+    let mut writes = Vec::new();
+    let mut lcov_path = None;
+    let mut mode = "symbolic";
+    let mut symbols = BTreeMap::new();
+    let mut listing = true;
+    let mut quiet = false;
+    let mut program_path = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|x| x.as_str());
+        match (args[i].as_str(), value) {
+            ("--input", Some(value)) => inputs.push(parse_code(value)),
+            ("--set", Some(value)) => {
+                let parts: Vec<i64> = value.split('=').map(parse_or_exit).collect();
+                match parts[..] {
+                    [address, value] if address >= 0 => writes.push((address, value)),
+                    _ => usage()
+                }
+            },
+            ("--lcov", Some(path)) => lcov_path = Some(path.to_string()),
+            ("--symbols", Some(path)) => {
+                let text = fs::read_to_string(path).unwrap();
+                symbols = parse_symbols(&text).unwrap_or_else(|error| {
+                    eprintln!("{}: {}", path, error);
+                    process::exit(1);
+                });
+            },
+            (flag, _) if flag.starts_with("--") => {
+                match flag {
+                    "--linear" => mode = "linear",
+                    "--raw" => mode = "raw",
+                    "--no-listing" => listing = false,
+                    "--quiet" => quiet = true,
+                    _ => usage()
+                }
+                i += 1;
+                continue;
+            },
+            (path, _) if program_path.is_none() => {
+                program_path = Some(path.to_string());
+                i += 1;
+                continue;
+            },
+            _ => usage()
+        }
+        i += 2;
+    }
+
+    let program_path = program_path.unwrap_or_else(|| usage());
+    let code = load_code(&program_path);
+
+    let mut coverage = Coverage::new();
+    if let Some(path) = &lcov_path {
+        if Path::new(path).exists() {
+            coverage = parse_lcov(&fs::read_to_string(path).unwrap()).unwrap_or_else(|error| {
+                eprintln!("{}: {}", path, error);
+                process::exit(1);
+            });
+        }
+    }
+
+    let mut run_once = |input: &mut dyn IntcodeInput| {
+        let mut program = Program::new(code.clone());
+        for &(address, value) in &writes {
+            program.memory.write(address, value);
+        }
+        run(&mut program, input, &mut coverage, quiet);
+    };
+    if inputs.is_empty() {
+        run_once(&mut StdinInput::new("> "));
+    }
+    for values in inputs {
+        run_once(&mut FixedInput::new(values));
+    }
+
+    if let Some(path) = &lcov_path {
+        fs::write(path, format_lcov(&coverage, &code, &program_path)).unwrap();
+    }
+
+    print!("{}", format_summary(&coverage.summary(&code)));
+    if listing {
+        let entries = coverage.entries();
+        let listing = match mode {
+            "linear" => disassemble(&code),
+            "raw" => disassemble_recursive_from(&code, &OpcodeSet::new(), &entries),
+            _ => disassemble_symbolic_from(&code, &symbols, &OpcodeSet::new(), &entries)
+        };
+        println!();
+        print!("{}", annotate_listing(&listing, &coverage, &code));
+    }
+}
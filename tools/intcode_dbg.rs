@@ -0,0 +1,323 @@
+// Interactive debugger for Intcode programs.
+
+use std::env;
+use std::process;
+use std::io::{self, BufRead, Write};
+use std::collections::{BTreeMap, BTreeSet};
+use intcode::*;
+use intcode::disasm::{Analysis, analyze, decode_instruction, format_data, format_instruction};
+use intcode::history::{History, reverse_step, step_program_logged};
+
+const HELP: &str = "\
+Commands:
+  s, step [count]          execute one or more instructions
+  c, continue              run until a breakpoint, watchpoint or halt
+  rs, reverse-step [count] undo one or more instructions
+  rc, reverse-continue     run backwards until a breakpoint, watchpoint or the
+                           start of the history
+  who <address>            show the last instruction that wrote to the address
+  b, break <address>       set a breakpoint
+  d, delete <address>      delete a breakpoint
+  w, watch <address>       stop when the value at the address changes
+  unwatch <address>        delete a watchpoint
+  i, info                  show registers, breakpoints and watchpoints
+  l, list [address]        show the disassembly around ip or an address
+  p, print <address> [n]   show n memory cells starting at the address
+  set <address> <value>    change a memory cell
+  set ip|rel_base <value>  change a register
+  input <value>...         queue input values
+  reset                    restart the program
+  q, quit                  exit the debugger
+An empty line repeats the previous command.";
+
+struct Debugger {
+    program: Program,
+    analysis: Analysis,
+    breakpoints: BTreeSet<i64>,
+    watchpoints: BTreeMap<i64, i64>,
+    history: History,
+    stopped: bool
+}
+
+impl Debugger {
+    fn new(code: Vec<i64>) -> Debugger {
+        Debugger {
+            analysis: analyze(&code),
+            program: Program::new(code),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            history: History::new(),
+            stopped: false
+        }
+    }
+
+    // Execute one instruction. Returns true if execution should not continue.
+    fn step(&mut self) -> bool {
+        if self.stopped {
+            println!("The program is not running, use 'reset' to restart it");
+            return true;
+        }
+
+        match step_program_logged(&mut self.program, &mut self.history) {
+            Ok(None) => {},
+            Ok(Some(StepResult::Output)) => {
+                println!("output: {}", self.program.data.pop_back().unwrap());
+            },
+            Ok(Some(StepResult::NeedInput)) => {
+                match read_input_value() {
+                    Some(value) => self.program.data.push_back(value),
+                    None => return true
+                }
+            },
+            Ok(Some(StepResult::Halt)) => {
+                println!("Program halted");
+                self.stopped = true;
+                return true;
+            },
+            Err(error) => {
+                println!("Error: {}", error);
+                return true;
+            }
+        }
+
+        self.check_watchpoints()
+    }
+
+    // Returns true if a watched value has changed.
+    fn check_watchpoints(&mut self) -> bool {
+        let mut stop = false;
+        for (&address, value) in self.watchpoints.iter_mut() {
+            let new_value = self.program.memory.read(address);
+            if new_value != *value {
+                println!("Watchpoint: [{}] changed from {} to {}", address, value, new_value);
+                *value = new_value;
+                stop = true;
+            }
+        }
+        stop
+    }
+
+    fn run(&mut self, max_steps: Option<u64>) {
+        let mut steps = 0;
+        loop {
+            if self.step() {
+                break;
+            }
+            steps += 1;
+            if max_steps == Some(steps) {
+                break;
+            }
+            if self.breakpoints.contains(&self.program.ip) {
+                println!("Breakpoint at {:0>4}", self.program.ip);
+                break;
+            }
+        }
+        if !self.stopped {
+            self.list(self.program.ip, 1);
+        }
+    }
+
+    // Go back by a number of instructions, or until a breakpoint or a
+    // watchpoint is hit if there is no limit.
+    fn reverse(&mut self, max_steps: Option<u64>) {
+        let mut steps = 0;
+        loop {
+            if !reverse_step(&mut self.program, &mut self.history) {
+                println!("Reached the start of the history at step {}", self.history.step());
+                break;
+            }
+            self.stopped = false;
+            if self.check_watchpoints() {
+                break;
+            }
+            steps += 1;
+            if max_steps == Some(steps) {
+                break;
+            }
+            if self.breakpoints.contains(&self.program.ip) {
+                println!("Breakpoint at {:0>4}", self.program.ip);
+                break;
+            }
+        }
+        self.list(self.program.ip, 1);
+    }
+
+    fn who(&self, address: i64) {
+        match self.history.last_write(address) {
+            Some(write) => println!("[{}] was set from {} to {} at step {} by the instruction at {:0>4}",
+                address, write.old_value, write.new_value, write.step, write.ip),
+            None => println!("No write to [{}] in the undo log", address)
+        }
+    }
+
+    // Print disassembly of the current memory contents starting a few
+    // instructions before the address.
+    fn list(&self, address: i64, before: usize) {
+        let start = self.analysis.instructions.range(address.saturating_sub(12)..address).rev()
+            .take(before)
+            .last()
+            .map_or(address, |(&start, _)| start)
+            .max(0);
+        // Only read the cells that are shown, the address can be anywhere.
+        let end = start.saturating_add(39);
+        let memory: Vec<i64> = (start..=end).map(|a| self.program.memory.read(a)).collect();
+
+        let mut ip = start;
+        for _ in 0..(before + 6) {
+            let offset = ip - start;
+            if offset >= memory.len() as i64 {
+                break;
+            }
+            let marker = if ip == self.program.ip { "=>" } else { "  " };
+            let breakpoint = if self.breakpoints.contains(&ip) { "*" } else { " " };
+            match decode_instruction(&memory, offset) {
+                Some(mut instruction) => {
+                    instruction.address = ip;
+                    println!("{}{} {}", marker, breakpoint, format_instruction(&instruction));
+                    ip += instruction.size();
+                },
+                None => {
+                    println!("{}{} {}", marker, breakpoint, format_data(ip, memory[offset as usize]));
+                    ip += 1;
+                }
+            }
+        }
+    }
+
+    fn info(&self) {
+        println!("ip: {}", self.program.ip);
+        println!("rel_base: {}", self.program.rel_base);
+        println!("step: {} (history from step {})", self.history.step(), self.history.oldest_step());
+        println!("pending input: {:?}", self.program.data);
+        println!("breakpoints: {:?}", self.breakpoints);
+        println!("watchpoints: {:?}", self.watchpoints.keys().collect::<Vec<&i64>>());
+    }
+
+    fn execute(&mut self, command: &str, args: &[i64], register: Option<&str>) -> bool {
+        match (command, args) {
+            ("s", []) | ("step", []) => self.run(Some(1)),
+            ("s", &[count]) | ("step", &[count]) if count > 0 => self.run(Some(count as u64)),
+            ("c", []) | ("continue", []) => self.run(None),
+            ("rs", []) | ("reverse-step", []) => self.reverse(Some(1)),
+            ("rs", &[count]) | ("reverse-step", &[count]) if count > 0 => self.reverse(Some(count as u64)),
+            ("s", &[_]) | ("step", &[_]) | ("rs", &[_]) | ("reverse-step", &[_]) => {
+                println!("The number of steps must be positive");
+            },
+            ("rc", []) | ("reverse-continue", []) => self.reverse(None),
+            ("who", &[address]) => self.who(address),
+            ("b", &[address]) | ("break", &[address]) => {
+                self.breakpoints.insert(address);
+            },
+            ("d", &[address]) | ("delete", &[address]) => {
+                self.breakpoints.remove(&address);
+            },
+            ("w", &[address]) | ("watch", &[address]) => {
+                self.watchpoints.insert(address, self.program.memory.read(address));
+            },
+            ("unwatch", &[address]) => {
+                self.watchpoints.remove(&address);
+            },
+            ("i", []) | ("info", []) => self.info(),
+            ("l", []) | ("list", []) => self.list(self.program.ip, 3),
+            ("l", &[address]) | ("list", &[address]) => self.list(address, 3),
+            ("p", &[address]) | ("print", &[address]) => {
+                println!("[{}] = {}", address, self.program.memory.read(address));
+            },
+            ("p", &[address, count]) | ("print", &[address, count]) => {
+                for a in address..(address + count) {
+                    println!("[{}] = {}", a, self.program.memory.read(a));
+                }
+            },
+            // Changing the program by hand invalidates the history.
+            ("set", &[value]) if register == Some("ip") => {
+                self.program.ip = value;
+                self.history.clear();
+            },
+            ("set", &[value]) if register == Some("rel_base") => {
+                self.program.rel_base = value;
+                self.history.clear();
+            },
+            ("set", &[address, value]) if address >= 0 => {
+                self.program.memory.write(address, value);
+                self.history.clear();
+                if let Some(watched) = self.watchpoints.get_mut(&address) {
+                    *watched = value;
+                }
+            },
+            ("input", values) if !values.is_empty() => self.program.data.extend(values),
+            ("reset", []) => {
+                init_program(&mut self.program);
+                self.history.clear();
+                for (&address, value) in self.watchpoints.iter_mut() {
+                    *value = self.program.memory.read(address);
+                }
+                self.stopped = false;
+            },
+            ("h", []) | ("help", []) => println!("{}", HELP),
+            _ => return false
+        }
+        true
+    }
+}
+
+fn read_input_value() -> Option<i64> {
+    print!("input> ");
+    io::stdout().flush().unwrap();
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).unwrap();
+    match line.trim().parse::<i64>() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            println!("Input value is not an integer");
+            None
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <program_path>", args[0]);
+        process::exit(1);
+    }
+
+    let mut debugger = Debugger::new(load_code(&args[1]));
+    let mut last_command = String::new();
+    debugger.list(0, 0);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            line = last_command.clone();
+        }
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (command, rest) = match tokens.split_first() {
+            Some((&command, rest)) => (command, rest),
+            None => continue
+        };
+        if command == "q" || command == "quit" {
+            break;
+        }
+
+        let register = rest.first().cloned().filter(|&r| r == "ip" || r == "rel_base");
+        let rest = if register.is_some() { &rest[1..] } else { rest };
+        let args: Result<Vec<i64>, _> = rest.iter().map(|x| x.parse::<i64>()).collect();
+        let executed = match args {
+            Ok(args) => debugger.execute(command, &args, register),
+            Err(_) => false
+        };
+        if executed {
+            last_command = line;
+        } else {
+            println!("Invalid command, type 'help' for the list of commands");
+        }
+    }
+}
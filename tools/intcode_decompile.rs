@@ -0,0 +1,55 @@
+// Convert Intcode programs into C-like pseudo-code.
+
+use std::env;
+use std::fs;
+use std::process;
+use std::collections::BTreeMap;
+use intcode::load_code;
+use intcode::decompile::decompile;
+use intcode::disasm::parse_symbols;
+
+fn print_usage(program_name: &str) -> ! {
+    eprintln!("Usage: {} [--symbols <symbols_path>] [--entry <address>]... <program_path>", program_name);
+    eprintln!();
+    eprintln!("  --symbols   read names of functions and variables from a file");
+    eprintln!("  --entry     decompile a function at this address even if it isn't");
+    eprintln!("              called directly");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut symbols = BTreeMap::new();
+    let mut entries = Vec::new();
+    let mut program_path = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--symbols" if i + 1 < args.len() => {
+                i += 1;
+                let text = fs::read_to_string(&args[i]).unwrap();
+                symbols = parse_symbols(&text).unwrap_or_else(|error| {
+                    eprintln!("{}: {}", args[i], error);
+                    process::exit(1);
+                });
+            },
+            "--entry" if i + 1 < args.len() => {
+                i += 1;
+                match args[i].parse::<i64>() {
+                    Ok(address) if address >= 0 => entries.push(address),
+                    _ => print_usage(&args[0])
+                }
+            },
+            path if program_path.is_none() && !path.starts_with("--") => program_path = Some(path),
+            _ => print_usage(&args[0])
+        }
+        i += 1;
+    }
+
+    let program = match program_path {
+        Some(path) => load_code(path),
+        None => print_usage(&args[0])
+    };
+    print!("{}", decompile(&program, &symbols, &entries));
+}
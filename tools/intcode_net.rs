@@ -0,0 +1,60 @@
+// Run a network of Intcode programs described by a config file and print the
+// outputs of every node.
+
+use std::env;
+use std::process;
+use intcode::network::{NetworkResult, Scheduler, load_network};
+
+fn usage(program_name: &str) -> ! {
+    eprintln!("Usage: {} [--scheduler round-robin|event] <config_path>", program_name);
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut scheduler = Scheduler::RoundRobin;
+    let mut config_path = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--scheduler" => {
+                i += 1;
+                scheduler = match args.get(i).map(|s| s.as_str()) {
+                    Some("round-robin") => Scheduler::RoundRobin,
+                    Some("event") => Scheduler::EventDriven,
+                    _ => usage(&args[0])
+                };
+            },
+            path if config_path.is_none() => config_path = Some(path.to_string()),
+            _ => usage(&args[0])
+        }
+        i += 1;
+    }
+    let config_path = config_path.unwrap_or_else(|| usage(&args[0]));
+
+    let mut network = match load_network(&config_path) {
+        Ok(network) => network,
+        Err(error) => {
+            eprintln!("{}: {}", config_path, error);
+            process::exit(1);
+        }
+    };
+
+    let result = network.run(scheduler);
+    for node in &network.nodes {
+        let outputs: Vec<String> = node.outputs.iter().map(|x| x.to_string()).collect();
+        println!("{}: {:?} [{}]", node.name, node.status, outputs.join(","));
+    }
+    match result {
+        Ok(NetworkResult::Halted) => println!("All nodes halted"),
+        Ok(NetworkResult::Deadlock(nodes)) => {
+            println!("Deadlock: {} waiting for input", nodes.join(", "));
+            process::exit(2);
+        },
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// Find the amplifier phase settings that produce the highest signal.
+
+use std::env;
+use std::process;
+use std::time::Instant;
+use intcode::*;
+use intcode::amplifier::{PhaseSearch, format_results_json};
+
+const USAGE: &str = "\
+Usage: intcode_phases [options] <program_path>
+
+Options:
+  --phases <start>..<end> phase settings to choose from (default: 0..4)
+  --phases <v1,v2,...>    same, as a list of values
+  --length <count>        number of amplifiers (default: number of phases)
+  --feedback              connect the last amplifier back to the first one
+  --threads <count>       number of worker threads (default: number of CPUs)
+  --top <count>           number of results to show (default: 1)
+  --quiet                 only print the best signal
+  --json                  print the results as a JSON array";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_or_exit<T: std::str::FromStr>(text: &str) -> T {
+    text.parse::<T>().unwrap_or_else(|_| usage())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut phases: Vec<i64> = (0..=4).collect();
+    let mut length = None;
+    let mut feedback = false;
+    let mut threads = None;
+    let mut top = 1;
+    let mut quiet = false;
+    let mut json = false;
+    let mut program_path = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|x| x.as_str());
+        match (args[i].as_str(), value) {
+            ("--phases", Some(value)) if value.contains(',') => {
+                phases = value.split(',').map(parse_or_exit).collect();
+            },
+            ("--phases", Some(value)) => {
+                phases = match value.split_once("..") {
+                    Some((start, end)) => (parse_or_exit(start)..=parse_or_exit(end)).collect(),
+                    None => vec![parse_or_exit(value)]
+                };
+            },
+            ("--length", Some(value)) => length = Some(parse_or_exit::<usize>(value)),
+            ("--threads", Some(value)) => threads = Some(parse_or_exit::<usize>(value)),
+            ("--top", Some(value)) => top = parse_or_exit::<usize>(value),
+            ("--feedback", _) => {
+                feedback = true;
+                i += 1;
+                continue;
+            },
+            ("--quiet", _) => {
+                quiet = true;
+                i += 1;
+                continue;
+            },
+            ("--json", _) => {
+                json = true;
+                i += 1;
+                continue;
+            },
+            (path, _) if program_path.is_none() && !path.starts_with("--") => {
+                program_path = Some(path.to_string());
+                i += 1;
+                continue;
+            },
+            _ => usage()
+        }
+        i += 2;
+    }
+
+    let program = match program_path {
+        Some(path) => load_program(&path),
+        None => usage()
+    };
+    let length = length.unwrap_or(phases.len());
+
+    let mut search = PhaseSearch::new(program, phases).with_length(length).with_top(top);
+    if feedback {
+        search = search.with_feedback();
+    }
+    if let Some(threads) = threads {
+        search = search.with_threads(threads);
+    }
+
+    let candidates = search.candidates();
+    let start_time = Instant::now();
+    let results = match search.run() {
+        Ok(results) => results,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            process::exit(1);
+        }
+    };
+
+    if json {
+        println!("{}", format_results_json(&results));
+    } else if quiet {
+        if let Some(result) = results.first() {
+            println!("{}", result.signal);
+        }
+    } else {
+        for result in &results {
+            println!("{:>16}  {:?}", result.signal, result.phases);
+        }
+        println!("Tried {} phase settings in {:.3}s", candidates.unwrap_or(u64::MAX), start_time.elapsed().as_secs_f64());
+    }
+}
@@ -0,0 +1,137 @@
+// Run an Intcode program and show where it spends its time: executions per
+// opcode, hot blocks and loops, and a disassembly annotated with hit counts.
+
+use std::env;
+use std::fs;
+use std::process;
+use std::collections::BTreeMap;
+use intcode::*;
+use intcode::disasm::{disassemble, disassemble_recursive, disassemble_symbolic, parse_symbols};
+use intcode::ports::{FixedInput, IntcodeInput, StdinInput};
+use intcode::profile::{Profile, annotate_listing, format_report_with};
+use intcode::trace::run_program_traced;
+
+const USAGE: &str = "\
+Usage: intcode_prof [options] <program_path>
+
+Options:
+  --input <v1,v2,...>      input values, prompted for on stdin when exhausted
+  --repeat                 repeat the input values instead of prompting
+  --set <address>=<value>  change a memory cell before running the program
+  --top <count>            number of hot blocks and loops to show (default: 10)
+  --linear | --raw         listing style, see intcode_disasm
+  --symbols <path>         read names of labels and variables from a file
+  --no-listing             only show the summary
+  --quiet                  don't print the program's output";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_or_exit<T: std::str::FromStr>(text: &str) -> T {
+    text.parse::<T>().unwrap_or_else(|_| usage())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut input = Vec::new();
+    let mut repeat = false;
+    let mut writes = Vec::new();
+    let mut top = 10;
+    let mut mode = "symbolic";
+    let mut symbols = BTreeMap::new();
+    let mut listing = true;
+    let mut quiet = false;
+    let mut program_path = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|x| x.as_str());
+        match (args[i].as_str(), value) {
+            ("--input", Some(value)) => input = parse_code(value),
+            ("--set", Some(value)) => {
+                let parts: Vec<i64> = value.split('=').map(parse_or_exit).collect();
+                match parts[..] {
+                    [address, value] if address >= 0 => writes.push((address, value)),
+                    _ => usage()
+                }
+            },
+            ("--top", Some(value)) => top = parse_or_exit::<usize>(value),
+            ("--symbols", Some(path)) => {
+                let text = fs::read_to_string(path).unwrap();
+                symbols = parse_symbols(&text).unwrap_or_else(|error| {
+                    eprintln!("{}: {}", path, error);
+                    process::exit(1);
+                });
+            },
+            (flag, _) if flag.starts_with("--") => {
+                match flag {
+                    "--repeat" => repeat = true,
+                    "--linear" => mode = "linear",
+                    "--raw" => mode = "raw",
+                    "--no-listing" => listing = false,
+                    "--quiet" => quiet = true,
+                    _ => usage()
+                }
+                i += 1;
+                continue;
+            },
+            (path, _) if program_path.is_none() => {
+                program_path = Some(path.to_string());
+                i += 1;
+                continue;
+            },
+            _ => usage()
+        }
+        i += 2;
+    }
+
+    let mut program = match program_path {
+        Some(path) => load_program(&path),
+        None => usage()
+    };
+    for (address, value) in writes {
+        program.memory.write(address, value);
+    }
+
+    let mut fixed_input = FixedInput::new(input);
+    if repeat {
+        fixed_input = fixed_input.with_repeat();
+    }
+    let mut stdin_input = StdinInput::new("> ");
+    let mut profile = Profile::new();
+
+    loop {
+        match run_program_traced(&mut program, &mut profile) {
+            Ok(StepResult::NeedInput) => {
+                match fixed_input.read().or_else(|| stdin_input.read()) {
+                    Some(value) => program.data.push_back(value),
+                    None => break
+                }
+            },
+            Ok(StepResult::Output) => {
+                let value = program.data.pop_back().unwrap();
+                if !quiet {
+                    println!("{}", value);
+                }
+            },
+            Ok(StepResult::Halt) => break,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                break;
+            }
+        }
+    }
+
+    print!("{}", format_report_with(&profile, top, &program.opcodes));
+    if listing {
+        let listing = match mode {
+            "linear" => disassemble(&program.code),
+            "raw" => disassemble_recursive(&program.code),
+            _ => disassemble_symbolic(&program.code, &symbols)
+        };
+        println!();
+        print!("{}", annotate_listing(&listing, &profile));
+    }
+}
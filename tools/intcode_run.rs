@@ -0,0 +1,136 @@
+// Run an Intcode program on the console.
+
+use std::env;
+use std::process;
+use std::time::Duration;
+use intcode::*;
+use intcode::budget::{Budget, Meter, run_program_limited};
+use intcode::ports::{AsciiInput, AsciiOutput, IntcodeInput, IntcodeOutput, StdinInput, StdoutOutput};
+use intcode::session::{Recorder, load_session, replay, run_program_recorded, save_session};
+
+fn print_usage(program_name: &str) -> ! {
+    eprintln!("Usage: {} [options] <program_path>", program_name);
+    eprintln!();
+    eprintln!("  --ascii                   show output values 0-127 as text and enter input as");
+    eprintln!("                            lines of text");
+    eprintln!("  --max-instructions <n>    stop after executing n instructions");
+    eprintln!("  --timeout <seconds>       stop after running for this long (excluding the time");
+    eprintln!("                            spent waiting for input)");
+    eprintln!("  --max-memory <cells>      stop before writing to more than this many cells");
+    eprintln!("                            outside the code");
+    eprintln!("  --record <path>           save the input and output values of the session");
+    eprintln!("  --replay <path>           run the program with the input values of a recorded");
+    eprintln!("                            session and check that it behaves the same");
+    eprintln!();
+    eprintln!("Sessions can't be recorded or replayed with resource limits.");
+    process::exit(1);
+}
+
+fn parse_or_exit<T: std::str::FromStr>(text: &str, program_name: &str) -> T {
+    text.parse::<T>().unwrap_or_else(|_| print_usage(program_name))
+}
+
+type Runner<'a, M> = dyn FnMut(&mut Program<M>) -> Result<StepResult, VmError> + 'a;
+
+fn run<M: Memory>(program: &mut Program<M>, runner: &mut Runner<M>, input: &mut dyn IntcodeInput,
+        output: &mut dyn IntcodeOutput) {
+    loop {
+        match runner(program) {
+            Ok(StepResult::NeedInput) => match input.read() {
+                Some(value) => program.data.push_back(value),
+                None => return
+            },
+            Ok(StepResult::Output) => output.write(program.data.pop_back().unwrap()),
+            Ok(StepResult::Halt) => {
+                println!("Program exited");
+                return;
+            },
+            Err(error) => {
+                println!("Error: {}", error);
+                return;
+            }
+        }
+    }
+}
+
+fn run_console<M: Memory>(program: &mut Program<M>, runner: &mut Runner<M>, ascii: bool) {
+    if ascii {
+        run(program, runner, &mut AsciiInput::stdin(), &mut AsciiOutput::stdout());
+    } else {
+        run(program, runner, &mut StdinInput::new("> "), &mut StdoutOutput);
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut ascii = false;
+    let mut budget = Budget::new();
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut program_path = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|x| x.as_str());
+        match (args[i].as_str(), value) {
+            ("--ascii", _) => ascii = true,
+            ("--max-instructions", Some(value)) => {
+                budget = budget.with_instructions(parse_or_exit(value, &args[0]));
+                i += 1;
+            },
+            ("--timeout", Some(value)) => {
+                let time = Duration::try_from_secs_f64(parse_or_exit(value, &args[0]))
+                    .unwrap_or_else(|_| print_usage(&args[0]));
+                budget = budget.with_time(time);
+                i += 1;
+            },
+            ("--max-memory", Some(value)) => {
+                budget = budget.with_memory_cells(parse_or_exit(value, &args[0]));
+                i += 1;
+            },
+            ("--record", Some(value)) => {
+                record_path = Some(value);
+                i += 1;
+            },
+            ("--replay", Some(value)) => {
+                replay_path = Some(value);
+                i += 1;
+            },
+            (path, _) if program_path.is_none() && !path.starts_with("--") => program_path = Some(path),
+            _ => print_usage(&args[0])
+        }
+        i += 1;
+    }
+
+    let code = match program_path {
+        Some(path) => load_code(path),
+        None => print_usage(&args[0])
+    };
+    if (record_path.is_some() || replay_path.is_some()) && budget != Budget::new() {
+        print_usage(&args[0]);
+    }
+
+    if let Some(path) = replay_path {
+        let session = load_session(path).unwrap();
+        match replay(&mut Program::new(code), &session) {
+            Ok(instructions) => println!("Replayed {} events ({} instructions)", session.events.len(), instructions),
+            Err(error) => {
+                println!("Error: {}", error);
+                process::exit(1);
+            }
+        }
+    } else if let Some(path) = record_path {
+        let mut recorder = Recorder::new();
+        run_console(&mut Program::new(code), &mut |program| run_program_recorded(program, &mut recorder), ascii);
+        save_session(&recorder.session, path).unwrap();
+    } else if budget.memory_cells.is_some() {
+        // Paged memory allocates a whole page for each scattered write, so
+        // the number of cells would say little about the memory used.
+        let mut meter = Meter::new(budget);
+        let mut program = Program::with_memory(code, SparseMemory::new());
+        run_console(&mut program, &mut |program| run_program_limited(program, &mut meter), ascii);
+    } else {
+        let mut meter = Meter::new(budget);
+        run_console(&mut Program::new(code), &mut |program| run_program_limited(program, &mut meter), ascii);
+    }
+}
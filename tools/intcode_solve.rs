@@ -0,0 +1,144 @@
+// Find the values of input cells (like day 2's noun and verb) for which a
+// program leaves a given value at an address.
+
+use std::env;
+use std::process;
+use std::ops::RangeInclusive;
+use intcode::*;
+use intcode::solver::{Method, Solver};
+
+const USAGE: &str = "\
+Usage: intcode_solve [options] <program_path> <value>
+
+Options:
+  --unknown <name>=<address>[:<min>..<max>]
+                           input cell to solve for, values between min and max
+                           (default: 0..99); may be repeated (default:
+                           noun=1 and verb=2)
+  --target <address>       address of the result (default: 0)
+  --set <address>=<value>  change a memory cell before running the program
+  --threads <count>        number of worker threads for brute force search
+                           (default: number of CPUs)
+  --brute-force            don't try to solve the program symbolically
+  --quiet                  only print the solutions";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_or_exit<T: std::str::FromStr>(text: &str) -> T {
+    text.parse::<T>().unwrap_or_else(|_| usage())
+}
+
+fn parse_unknown(text: &str) -> (String, i64, RangeInclusive<i64>) {
+    let (name, rest) = text.split_once('=').unwrap_or_else(|| usage());
+    let (address, range) = match rest.split_once(':') {
+        Some((address, range)) => (address, Some(range)),
+        None => (rest, None)
+    };
+    let values = match range.map(|range| range.split_once("..")) {
+        Some(Some((min, max))) => parse_or_exit(min)..=parse_or_exit(max),
+        Some(None) => usage(),
+        None => 0..=99
+    };
+    (name.to_string(), parse_or_exit(address), values)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut unknowns = Vec::new();
+    let mut target = 0;
+    let mut writes = Vec::new();
+    let mut threads = None;
+    let mut brute_force = false;
+    let mut quiet = false;
+    let mut positional = Vec::new();
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|x| x.as_str());
+        match (args[i].as_str(), value) {
+            ("--unknown", Some(value)) => unknowns.push(parse_unknown(value)),
+            ("--target", Some(value)) => target = parse_or_exit::<i64>(value),
+            ("--set", Some(value)) => {
+                let parts: Vec<i64> = value.split('=').map(parse_or_exit).collect();
+                match parts[..] {
+                    [address, value] if address >= 0 => writes.push((address, value)),
+                    _ => usage()
+                }
+            },
+            ("--threads", Some(value)) => threads = Some(parse_or_exit::<usize>(value)),
+            (flag, _) if flag.starts_with("--") && !flag[2..].starts_with(|c: char| c.is_ascii_digit()) => {
+                match flag {
+                    "--brute-force" => brute_force = true,
+                    "--quiet" => quiet = true,
+                    _ => usage()
+                }
+                i += 1;
+                continue;
+            },
+            (arg, _) => {
+                positional.push(arg.to_string());
+                i += 1;
+                continue;
+            }
+        }
+        i += 2;
+    }
+
+    let (program_path, value) = match &positional[..] {
+        [path, value] => (path, parse_or_exit::<i64>(value)),
+        _ => usage()
+    };
+    let mut program = load_program(program_path);
+    for (address, value) in writes {
+        program.memory.write(address, value);
+    }
+    if unknowns.is_empty() {
+        unknowns.push(("noun".to_string(), 1, 0..=99));
+        unknowns.push(("verb".to_string(), 2, 0..=99));
+    }
+
+    let mut solver = Solver::new(program, target);
+    for (name, address, values) in unknowns {
+        solver = solver.with_unknown(&name, address, values);
+    }
+    if let Some(threads) = threads {
+        solver = solver.with_threads(threads);
+    }
+
+    let too_many = solver.combinations().is_none();
+    let solution = if brute_force {
+        if too_many && !quiet {
+            println!("Too many combinations to try");
+        }
+        solver.solve_brute_force(value)
+    } else {
+        let solution = solver.solve(value);
+        if !quiet {
+            match &solution.method {
+                Method::Symbolic(polynomial) => {
+                    println!("position {} = {}", target, polynomial.format(&solver.names()));
+                },
+                Method::BruteForce(error) if too_many => {
+                    println!("Can't solve symbolically ({}) and there are too many combinations to try", error);
+                },
+                Method::BruteForce(error) => {
+                    println!("Can't solve symbolically ({}), using brute force", error);
+                }
+            }
+        }
+        solution.inputs
+    };
+
+    if solution.is_empty() && !quiet {
+        println!("No solution");
+    }
+    for values in solution {
+        let assignments: Vec<String> = solver.names().iter().zip(&values)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        println!("{}", assignments.join(" "));
+    }
+}
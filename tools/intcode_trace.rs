@@ -0,0 +1,109 @@
+// Run an Intcode program and write a trace of every executed instruction.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::process;
+use intcode::*;
+use intcode::trace::{TraceFormat, TraceWriter, run_program_traced};
+
+const USAGE: &str = "\
+Usage: intcode_trace [options] <program_path>
+
+Options:
+  --format json|text     trace format (default: text)
+  --range <start>-<end>  only trace instructions in the address range
+  --limit <count>        stop tracing after count instructions
+  --input <v1,v2,...>    input values, prompted for on stdin when exhausted
+  --output <path>        write the trace to a file instead of stderr";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(1);
+}
+
+fn parse_or_exit<T: std::str::FromStr>(text: &str) -> T {
+    text.parse::<T>().unwrap_or_else(|_| usage())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut format = TraceFormat::Text;
+    let mut range = None;
+    let mut limit = None;
+    let mut input = Vec::new();
+    let mut output_path = None;
+    let mut program_path = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).map(|x| x.as_str());
+        match (args[i].as_str(), value) {
+            ("--format", Some("json")) => format = TraceFormat::JsonLines,
+            ("--format", Some("text")) => format = TraceFormat::Text,
+            ("--range", Some(value)) => {
+                let bounds: Vec<i64> = value.split('-').map(parse_or_exit).collect();
+                match bounds[..] {
+                    [start, end] => range = Some(start..=end),
+                    _ => usage()
+                }
+            },
+            ("--limit", Some(value)) => limit = Some(parse_or_exit::<u64>(value)),
+            ("--input", Some(value)) => input = parse_code(value),
+            ("--output", Some(value)) => output_path = Some(value.to_string()),
+            (path, _) if program_path.is_none() && !path.starts_with("--") => {
+                program_path = Some(path.to_string());
+                i += 1;
+                continue;
+            },
+            _ => usage()
+        }
+        i += 2;
+    }
+
+    let mut program = match program_path {
+        Some(path) => load_program(&path),
+        None => usage()
+    };
+    program.data.extend(input);
+
+    let out: Box<dyn Write> = match output_path {
+        Some(path) => Box::new(io::BufWriter::new(File::create(path).unwrap())),
+        None => Box::new(io::stderr())
+    };
+    let mut tracer = TraceWriter::new(out, format).with_opcodes(program.opcodes.clone());
+    if let Some(range) = range {
+        tracer = tracer.with_range(range);
+    }
+    if let Some(limit) = limit {
+        tracer = tracer.with_limit(limit);
+    }
+
+    loop {
+        match run_program_traced(&mut program, &mut tracer) {
+            Ok(StepResult::NeedInput) => {
+                let mut input_text = String::new();
+                print!("> ");
+                io::stdout().flush().unwrap();
+                io::stdin().read_line(&mut input_text).unwrap();
+                match input_text.trim().parse::<i64>() {
+                    Ok(value) => program.data.push_back(value),
+                    Err(_) => {
+                        eprintln!("Input value is not an integer");
+                        break;
+                    }
+                }
+            },
+            Ok(StepResult::Output) => {
+                println!("{}", program.data.pop_back().unwrap());
+            },
+            Ok(StepResult::Halt) => break,
+            Err(error) => {
+                eprintln!("Error: {}", error);
+                break;
+            }
+        }
+    }
+
+    eprintln!("Traced {} instructions", tracer.count());
+}
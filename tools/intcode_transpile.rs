@@ -0,0 +1,43 @@
+// Compile an Intcode program into a Rust module, see intcode::transpile.
+
+use std::env;
+use std::fs;
+use std::process;
+use intcode::load_code;
+use intcode::transpile::transpile;
+
+fn print_usage(program_name: &str) -> ! {
+    eprintln!("Usage: {} [-o <output_path>] <program_path>", program_name);
+    eprintln!();
+    eprintln!("  -o    write the module to a file instead of stdout");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut output_path = None;
+    let mut program_path = None;
+
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "-o" if i + 1 < args.len() => {
+                i += 1;
+                output_path = Some(args[i].clone());
+            },
+            path if program_path.is_none() && !path.starts_with('-') => program_path = Some(path),
+            _ => print_usage(&args[0])
+        }
+        i += 1;
+    }
+
+    let code = match program_path {
+        Some(path) => load_code(path),
+        None => print_usage(&args[0])
+    };
+    let module = transpile(&code);
+    match output_path {
+        Some(path) => fs::write(path, module).unwrap(),
+        None => print!("{}", module)
+    }
+}